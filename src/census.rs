@@ -0,0 +1,542 @@
+//! 汤搜索：在地图中间放一块随机的“汤”，演化到稳定之后把地图拆成一个个物体，
+//! 按标准形式（apgcode）分类计数
+//!
+//! 演化在GPU上进行，拆分和分类在CPU上进行：每个物体单独放在一小块地图上演化，
+//! 回到原来的样子时就知道了它的周期和位移
+
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    fmt,
+};
+
+use crate::{
+    cpu::CpuEngine, pattern::Pattern, rule::Rule, topology::Topology, Engine, Result, Simulator,
+};
+
+/// 固定种子的伪随机数（splitmix64），同一个种子总是得到同一块汤
+#[derive(Debug, Clone)]
+pub struct SoupRng(u64);
+
+impl SoupRng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// 以 `probability` 的概率返回 `true`
+    pub fn chance(&mut self, probability: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+/// `size` 大小的随机汤，每个细胞以 `density` 的概率是活的
+pub fn soup(seed: u64, size: (u32, u32), density: f64) -> Pattern {
+    let mut rng = SoupRng::new(seed);
+    let mut soup = Pattern::new(size.0, size.1);
+    for cell in &mut soup.cells {
+        *cell = rng.chance(density);
+    }
+    soup
+}
+
+/// 物体的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    /// 静物，周期为 1
+    StillLife,
+    /// 振荡器，周期大于 1，不移动
+    Oscillator,
+    /// 飞船，每个周期移动一段距离
+    Spaceship,
+    /// 在 `MAX_PERIOD` 代之内没有回到原来的样子
+    Unknown,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::StillLife => "静物",
+            Kind::Oscillator => "振荡器",
+            Kind::Spaceship => "飞船",
+            Kind::Unknown => "未知",
+        }
+    }
+}
+
+/// 分类后的物体
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Object {
+    /// 标准形式，例如方块是 `xs4_33`，闪光灯是 `xp2_7`，滑翔机是 `xq4_153`
+    pub apgcode: String,
+    pub kind: Kind,
+    /// 周期，不知道时为 0
+    pub period: u64,
+}
+
+/// 分类时最多演化的代数，也就是能识别的最大周期
+pub const MAX_PERIOD: u64 = 256;
+
+/// 单独演化一个物体，找出它的周期和位移，再用所有相位、所有方向中最短的编码作为标准形式
+pub fn classify(object: &Pattern, rule: Rule) -> Object {
+    classify_within(object, rule, MAX_PERIOD)
+}
+
+/// 同 `classify`，但最多演化 `max_period` 代，周期更长时算作未知
+fn classify_within(object: &Pattern, rule: Rule, max_period: u64) -> Object {
+    let object = object.cropped();
+    // 生命游戏中的飞船最快每两代移动一格，留出足够的空间，跑出去的会被算作未知
+    let margin = max_period as u32 / 2 + 2;
+    let map_size = (object.width + 2 * margin, object.height + 2 * margin);
+    let mut engine = CpuEngine::new(map_size, rule, Topology::Dead);
+    // 包围盒加上留白，一定放得下
+    let _ = engine.write_cells((margin, margin), &object);
+
+    let mut phases = vec![object.clone()];
+    for period in 1..=max_period {
+        engine.update();
+        let Ok(cells) = engine.read_cells() else {
+            break;
+        };
+        let Some((x, y, _, _)) = cells.bounding_box() else {
+            // 全部死掉了，不是稳定的物体
+            break;
+        };
+        let phase = cells.cropped();
+        if phase.cells == object.cells
+            && (phase.width, phase.height) == (object.width, object.height)
+        {
+            let moved = (x, y) != (margin, margin);
+            let (kind, prefix) = match (moved, period) {
+                (true, _) => (Kind::Spaceship, format!("xq{period}")),
+                (false, 1) => (
+                    Kind::StillLife,
+                    format!("xs{}", object.cells.iter().filter(|&&alive| alive).count()),
+                ),
+                (false, _) => (Kind::Oscillator, format!("xp{period}")),
+            };
+            let code = phases.iter().map(canonical).min_by(shorter).unwrap();
+            return Object {
+                apgcode: format!("{prefix}_{code}"),
+                kind,
+                period,
+            };
+        }
+        phases.push(phase);
+    }
+    Object {
+        apgcode: format!("unknown_{}", canonical(&object)),
+        kind: Kind::Unknown,
+        period: 0,
+    }
+}
+
+/// 先比较长度，再按字典序比较
+fn shorter(a: &String, b: &String) -> std::cmp::Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// 一个相位在 8 个方向中最短的编码
+fn canonical(pattern: &Pattern) -> String {
+    (0..8)
+        .map(|orientation| wechsler(&orient(pattern, orientation)))
+        .min_by(shorter)
+        .unwrap()
+}
+
+/// 旋转和翻转，`orientation` 的三个位分别表示左右翻转、上下翻转和转置
+fn orient(pattern: &Pattern, orientation: u32) -> Pattern {
+    let transpose = orientation & 4 != 0;
+    let (width, height) = if transpose {
+        (pattern.height, pattern.width)
+    } else {
+        (pattern.width, pattern.height)
+    };
+    let mut result = Pattern::new(width, height);
+    for y in 0..pattern.height {
+        for x in 0..pattern.width {
+            let x1 = if orientation & 1 != 0 {
+                pattern.width - 1 - x
+            } else {
+                x
+            };
+            let y1 = if orientation & 2 != 0 {
+                pattern.height - 1 - y
+            } else {
+                y
+            };
+            let (x1, y1) = if transpose { (y1, x1) } else { (x1, y1) };
+            result.set(x1, y1, pattern.get(x, y));
+        }
+    }
+    result
+}
+
+/// 扩展 Wechsler 编码：每 5 行一条，每列的 5 个细胞是一个 32 进制的字符，
+/// 连续的空列缩写成 `w`、`x`、`y?`，条之间用 `z` 分隔
+pub fn wechsler(pattern: &Pattern) -> String {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut code = String::new();
+    for strip in 0..pattern.height.div_ceil(5) {
+        if strip > 0 {
+            code.push('z');
+        }
+        let columns: Vec<usize> = (0..pattern.width)
+            .map(|x| {
+                (0..5)
+                    .filter(|&row| {
+                        let y = strip * 5 + row;
+                        y < pattern.height && pattern.get(x, y)
+                    })
+                    .map(|row| 1 << row)
+                    .sum()
+            })
+            .collect();
+        // 每条末尾的空列省略
+        let end = columns
+            .iter()
+            .rposition(|&column| column != 0)
+            .map_or(0, |i| i + 1);
+        let mut zeros = 0;
+        for &column in &columns[..end] {
+            if column == 0 {
+                zeros += 1;
+                continue;
+            }
+            while zeros >= 4 {
+                let run = zeros.min(39);
+                code.push('y');
+                code.push(DIGITS[run - 4] as char);
+                zeros -= run;
+            }
+            code.push_str(["", "0", "w", "x"][zeros]);
+            zeros = 0;
+            code.push(DIGITS[column] as char);
+        }
+    }
+    code
+}
+
+/// 把整张地图拆成物体
+///
+/// 8 连通的一块细胞先作为一个物体；相距不超过 2 格的几块中有不能单独分类的
+/// （比如脉冲星的四分之一）时，把这几块合起来作为一个物体
+pub fn split(map: &Pattern, topology: Topology, rule: Rule) -> Vec<Object> {
+    let mut objects = vec![];
+    for cluster in clusters(map, topology, 2) {
+        let cluster: Vec<(i64, i64)> = cluster.into_iter().map(|(_, cell)| cell).collect();
+        let parts: Vec<Object> = components(&cluster)
+            .iter()
+            .map(|part| classify(&to_pattern(part), rule))
+            .collect();
+        if parts.iter().all(|part| part.kind != Kind::Unknown) {
+            objects.extend(parts);
+        } else {
+            objects.push(classify(&to_pattern(&cluster), rule));
+        }
+    }
+    objects
+}
+
+/// 连成一块的细胞，每个细胞是地图上的位置和展开后的坐标
+type Cluster = Vec<((u32, u32), (i64, i64))>;
+
+/// 相距不超过 `radius` 格的活细胞连成一块，坐标跨过地图边界时展开，不会被拆成两半
+fn clusters(map: &Pattern, topology: Topology, radius: i64) -> Vec<Cluster> {
+    let map_size = (map.width, map.height);
+    let mut visited = vec![false; map.cells.len()];
+    let mut clusters = vec![];
+    for start in 0..map.cells.len() {
+        if !map.cells[start] || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let origin = (
+            (start as u32 % map.width) as i64,
+            (start as u32 / map.width) as i64,
+        );
+        // 地图上的位置和展开后的坐标
+        let mut queue = VecDeque::from([(origin, origin)]);
+        let mut cluster = vec![];
+        while let Some(((x, y), unwrapped)) = queue.pop_front() {
+            cluster.push(((x as u32, y as u32), unwrapped));
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let Some((nx, ny)) = topology.locate((x + dx, y + dy), map_size) else {
+                        continue;
+                    };
+                    let index = (ny * map.width + nx) as usize;
+                    if map.cells[index] && !visited[index] {
+                        visited[index] = true;
+                        queue.push_back((
+                            (nx as i64, ny as i64),
+                            (unwrapped.0 + dx, unwrapped.1 + dy),
+                        ));
+                    }
+                }
+            }
+        }
+        clusters.push(cluster);
+    }
+    clusters
+}
+
+/// 一块细胞中 8 连通的部分
+fn components(cells: &[(i64, i64)]) -> Vec<Vec<(i64, i64)>> {
+    let mut remaining: HashSet<(i64, i64)> = cells.iter().copied().collect();
+    let mut components = vec![];
+    for &start in cells {
+        if !remaining.remove(&start) {
+            continue;
+        }
+        let mut queue = VecDeque::from([start]);
+        let mut component = vec![];
+        while let Some((x, y)) = queue.pop_front() {
+            component.push((x, y));
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if remaining.remove(&(x + dx, y + dy)) {
+                        queue.push_back((x + dx, y + dy));
+                    }
+                }
+            }
+        }
+        components.push(component);
+    }
+    components
+}
+
+fn to_pattern(cells: &[(i64, i64)]) -> Pattern {
+    let x0 = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+    let y0 = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+    let x1 = cells.iter().map(|&(x, _)| x).max().unwrap_or(-1);
+    let y1 = cells.iter().map(|&(_, y)| y).max().unwrap_or(-1);
+    let mut pattern = Pattern::new((x1 - x0 + 1) as u32, (y1 - y0 + 1) as u32);
+    for &(x, y) in cells {
+        pattern.set((x - x0) as u32, (y - y0) as u32, true);
+    }
+    pattern
+}
+
+/// 普查表中的一项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub kind: Kind,
+    pub count: u64,
+}
+
+/// 所有汤中找到的物体
+#[derive(Debug, Clone, Default)]
+pub struct Census {
+    entries: BTreeMap<String, Entry>,
+    /// 搜索过的汤的数量
+    pub soups: u64,
+    /// 在限定的代数内没有稳定下来的汤的数量
+    pub unstable: u64,
+}
+
+impl Census {
+    pub fn add(&mut self, object: &Object) {
+        self.entries
+            .entry(object.apgcode.clone())
+            .or_insert(Entry {
+                kind: object.kind,
+                count: 0,
+            })
+            .count += 1;
+    }
+
+    pub fn get(&self, apgcode: &str) -> Option<Entry> {
+        self.entries.get(apgcode).copied()
+    }
+
+    /// 按类型分组，同一类型中数量多的在前
+    pub fn entries(&self) -> Vec<(&str, Entry)> {
+        let mut entries: Vec<(&str, Entry)> = self
+            .entries
+            .iter()
+            .map(|(apgcode, &entry)| (apgcode.as_str(), entry))
+            .collect();
+        entries.sort_by(|a, b| {
+            (a.1.kind, std::cmp::Reverse(a.1.count), a.0).cmp(&(
+                b.1.kind,
+                std::cmp::Reverse(b.1.count),
+                b.0,
+            ))
+        });
+        entries
+    }
+}
+
+impl fmt::Display for Census {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "共 {} 个汤，其中 {} 个没有稳定下来",
+            self.soups, self.unstable
+        )?;
+        let entries = self.entries();
+        let width = entries
+            .iter()
+            .map(|(apgcode, _)| apgcode.len())
+            .max()
+            .unwrap_or(0);
+        for (apgcode, entry) in entries {
+            writeln!(
+                f,
+                "{apgcode:<width$}  {:>10}  {}",
+                entry.count,
+                entry.kind.name()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 在模拟器上一个接一个地搜索汤
+pub struct SoupSearch {
+    sim: Simulator,
+    soup_size: (u32, u32),
+    density: f64,
+    /// 每个汤最多演化的代数，超过时算作没有稳定下来
+    pub max_generations: u64,
+    pub census: Census,
+}
+
+impl SoupSearch {
+    /// 默认每个汤最多演化的代数
+    pub const DEFAULT_MAX_GENERATIONS: u64 = 50_000;
+
+    /// 判断周期时记住的代数，飞出去的飞船已经被删掉，剩下的只有振荡器的周期
+    const PERIOD_TABLE_SIZE: usize = 8192;
+
+    /// 每次演化多少代之后检查一次是否稳定，以及有没有飞船飞出去
+    const CHUNK: u64 = 64;
+
+    /// 汤的四周留出这么多格，完全在这个范围之外的飞船算作飞出去了
+    const ESCAPE_MARGIN: u32 = 24;
+
+    /// 能识别为飞出去的飞船的最大周期，滑翔机和轻、中、重量级飞船的周期都是 4
+    const ESCAPE_PERIOD: u64 = 16;
+
+    /// 汤放在 `sim` 的地图正中间，地图要比汤大，最好是环面。飞出去的飞船被删掉之前
+    /// 不能绕回来，所以汤的四周最好再留出几十格
+    pub fn new(mut sim: Simulator, soup_size: (u32, u32), density: f64) -> Result<Self> {
+        let map_size = sim.map_size();
+        if soup_size.0 > map_size.0 || soup_size.1 > map_size.1 {
+            return Err(format!(
+                "{}x{} 的汤放不进 {}x{} 的地图",
+                soup_size.0, soup_size.1, map_size.0, map_size.1
+            )
+            .into());
+        }
+        sim.enable_period_detection(Self::PERIOD_TABLE_SIZE);
+        Ok(Self {
+            sim,
+            soup_size,
+            density,
+            max_generations: Self::DEFAULT_MAX_GENERATIONS,
+            census: Census::default(),
+        })
+    }
+
+    pub fn simulator(&self) -> &Simulator {
+        &self.sim
+    }
+
+    /// 搜索种子为 `seed` 的汤，结果加到 `census` 中
+    ///
+    /// 和 apgsearch 一样，飞出汤的范围的飞船先分类、记下来再删掉，只有剩下的部分要稳定下来，
+    /// 所以结果和地图的大小无关。返回飞出去的飞船和稳定下来之后拆出的物体，
+    /// 没有稳定下来时返回 `None`
+    pub fn run(&mut self, seed: u64) -> Result<Option<Vec<Object>>> {
+        let map_size = self.sim.map_size();
+        let soup = soup(seed, self.soup_size, self.density);
+        self.sim
+            .write_cells((0, 0), &Pattern::new(map_size.0, map_size.1))?;
+        let offset = soup.centered_offset(map_size);
+        self.sim.write_cells(offset, &soup)?;
+        self.census.soups += 1;
+
+        let region = (
+            offset.0.saturating_sub(Self::ESCAPE_MARGIN),
+            offset.1.saturating_sub(Self::ESCAPE_MARGIN),
+            (offset.0 + soup.width + Self::ESCAPE_MARGIN).min(map_size.0),
+            (offset.1 + soup.height + Self::ESCAPE_MARGIN).min(map_size.1),
+        );
+        let mut escaped = vec![];
+        let start = self.sim.generation();
+        loop {
+            self.sim.step(Self::CHUNK);
+            self.sim.wait_stats();
+            escaped.extend(self.remove_escaped(region)?);
+            // 清空地图之前的周期已经作废
+            if self
+                .sim
+                .period()
+                .is_some_and(|period| period.generation > start)
+            {
+                break;
+            }
+            if self.sim.generation() - start >= self.max_generations {
+                self.census.unstable += 1;
+                return Ok(None);
+            }
+        }
+
+        let mut objects = escaped;
+        objects.extend(split(
+            &self.sim.read_cells()?,
+            self.sim.topology(),
+            self.sim.rule(),
+        ));
+        for object in &objects {
+            self.census.add(object);
+        }
+        Ok(Some(objects))
+    }
+
+    /// 删掉完全在 `region`（`(左, 上, 右, 下)`）之外的飞船，返回删掉的飞船
+    ///
+    /// 活细胞的包围盒还在范围之内时不用读回地图。范围之外的块只演化 `ESCAPE_PERIOD` 代，
+    /// 周期更长的飞船留在地图上
+    fn remove_escaped(&mut self, region: (u32, u32, u32, u32)) -> Result<Vec<Object>> {
+        let (left, top, right, bottom) = region;
+        let inside = |(x, y): (u32, u32)| (left..right).contains(&x) && (top..bottom).contains(&y);
+        let Some((x, y, width, height)) = self
+            .sim
+            .stats()
+            .latest()
+            .and_then(|sample| sample.bounding_box)
+        else {
+            return Ok(vec![]);
+        };
+        if inside((x, y)) && inside((x + width - 1, y + height - 1)) {
+            return Ok(vec![]);
+        }
+
+        let mut escaped = vec![];
+        let mut erased = vec![];
+        let map = self.sim.read_cells()?;
+        for cluster in clusters(&map, self.sim.topology(), 2) {
+            if cluster.iter().any(|&(cell, _)| inside(cell)) {
+                continue;
+            }
+            let cells: Vec<(i64, i64)> = cluster.iter().map(|&(_, cell)| cell).collect();
+            let object = classify_within(&to_pattern(&cells), self.sim.rule(), Self::ESCAPE_PERIOD);
+            // 范围之外的静物和振荡器留到最后和剩下的部分一起拆分
+            if object.kind == Kind::Spaceship {
+                erased.extend(cluster.iter().map(|&(cell, _)| cell));
+                escaped.push(object);
+            }
+        }
+        self.sim.set_cells(&erased, false)?;
+        Ok(escaped)
+    }
+}
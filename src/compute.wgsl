@@ -7,12 +7,29 @@ var this_map : texture_storage_2d<rgba8unorm, write>;
 
 struct MapSize {
     map_size: vec2i,
-    _padding: vec2i,
+    // 0: 边界外是死细胞 1: 环面 2: 克莱因瓶 3: 射影平面
+    topology: u32,
+    _padding: u32,
 }
 
+const TOPOLOGY_DEAD: u32 = 0u;
+const TOPOLOGY_TORUS: u32 = 1u;
+const TOPOLOGY_KLEIN_BOTTLE: u32 = 2u;
+const TOPOLOGY_CROSS_SURFACE: u32 = 3u;
+
 @group(0) @binding(2)
 var<uniform> map_size : MapSize;
 
+// 第 n 位为 1 表示邻居数为 n 时出生/存活
+struct Rule {
+    birth: u32,
+    survival: u32,
+    _padding: vec2u,
+}
+
+@group(0) @binding(3)
+var<uniform> rule : Rule;
+
 struct ComputeInput {
     @builtin(global_invocation_id) global_id: vec3<u32>
 }
@@ -22,9 +39,10 @@ fn cs_main(in: ComputeInput) {
     let uv = vec2<i32>(in.global_id.xy);
     let offsets = vec3(-1, 0, 1);
 
-    // if uv.x > map_size.map_size.x || uv.y > map_size.map_size.x {
-    //     return;
-    // }
+    // 工作组可能会超出地图的范围
+    if any(uv >= map_size.map_size) {
+        return;
+    }
 
     var sum: u32 = u32(0);
     sum += is_life(uv + offsets.xx);
@@ -36,15 +54,61 @@ fn cs_main(in: ComputeInput) {
     sum += is_life(uv + offsets.yz);
 
     sum += is_life(uv + offsets.zz);
-    let last_state = is_life(uv);
-    let life = f32(sum == u32(3) || (sum == u32(2) && bool(last_state)));
+    // 射影平面上角的细胞越过左右边和越过上下边的邻居都是对角的细胞，只算一次
+    let last_cell = map_size.map_size - 1;
+    if map_size.topology == TOPOLOGY_CROSS_SURFACE && all((uv == vec2(0)) | (uv == last_cell)) {
+        sum -= is_life(last_cell - uv);
+    }
+    let last = textureLoad(map_textre, uv, 0);
+    let last_alive = last.r > 0.0;
+    let mask = select(rule.birth, rule.survival, last_alive);
+    let life = ((mask >> sum) & 1u) == 1u;
 
-    textureStore(this_map, vec2<i32>(uv), vec4(life));
+    // 年龄和尾迹放在 g 和 b 通道中，见 `resources.rs`
+    var age = 0u;
+    var trail = 0u;
+    if life && last_alive {
+        age = min(u32(round(last.g * 255.0)) + 1u, 255u);
+    } else if !life && last_alive {
+        trail = 255u;
+    } else if !life {
+        trail = max(u32(round(last.b * 255.0)), 1u) - 1u;
+    }
+
+    textureStore(this_map, vec2<i32>(uv), vec4(f32(life), f32(age) / 255.0, f32(trail) / 255.0, f32(life)));
 }
 
 
 fn is_life(location: vec2i) -> u32 {
-    let new_location = clamp(location, vec2<i32>(0, 0), map_size.map_size);
-    let life = textureLoad(map_textre, new_location, 0).r > 0.0;
+    let size = map_size.map_size;
+    let topology = map_size.topology;
+    var p = location;
+
+    let outside = (p < vec2(0)) | (p >= size);
+    if any(outside) {
+        if topology == TOPOLOGY_DEAD {
+            return 0u;
+        }
+        // 射影平面上斜着越过角的位置不在地图上
+        if topology == TOPOLOGY_CROSS_SURFACE && all(outside) {
+            return 0u;
+        }
+        // 从左右两边越界
+        if p.x < 0 || p.x >= size.x {
+            p.x = (p.x + size.x) % size.x;
+            if topology == TOPOLOGY_CROSS_SURFACE {
+                p.y = size.y - 1 - p.y;
+            }
+        }
+        // 从上下两边越界
+        if p.y < 0 || p.y >= size.y {
+            p.y = (p.y + size.y) % size.y;
+            if topology == TOPOLOGY_KLEIN_BOTTLE || topology == TOPOLOGY_CROSS_SURFACE {
+                p.x = size.x - 1 - p.x;
+            }
+        }
+    }
+
+    let life = textureLoad(map_textre, p, 0).r > 0.0;
     return u32(life);
 }
\ No newline at end of file
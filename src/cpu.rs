@@ -0,0 +1,188 @@
+use crate::{
+    pattern::Pattern,
+    resources::{ALIVE, DEAD},
+    rule::Rule,
+    topology::Topology,
+    Engine, Result,
+};
+
+/// 纯CPU实现的引擎，规则和拓扑与 `compute.wgsl` 完全一致
+///
+/// 用来检查GPU的计算结果，也用在不支持计算管线的设备上
+pub struct CpuEngine {
+    map_size: (u32, u32),
+    rule: Rule,
+    topology: Topology,
+    /// 按行储存，1 表示活细胞
+    cells: Vec<u8>,
+    /// 下一代，演化完之后和 `cells` 交换
+    next: Vec<u8>,
+    /// `cells` 中活细胞的包围盒 `(x0, y0, x1, y1)`，可能比实际的大
+    bounds: Option<(i64, i64, i64, i64)>,
+    /// `next` 中残留的活细胞的包围盒
+    next_bounds: Option<(i64, i64, i64, i64)>,
+    generation: u64,
+}
+
+/// 把一个点并入包围盒
+fn extend(bounds: &mut Option<(i64, i64, i64, i64)>, x: i64, y: i64) {
+    *bounds = Some(match *bounds {
+        Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+        None => (x, y, x, y),
+    });
+}
+
+impl CpuEngine {
+    /// 地图初始全是死细胞
+    pub fn new(map_size: (u32, u32), rule: Rule, topology: Topology) -> Self {
+        let len = (map_size.0 * map_size.1) as usize;
+        Self {
+            map_size,
+            rule,
+            topology,
+            cells: vec![0; len],
+            next: vec![0; len],
+            bounds: None,
+            next_bounds: None,
+            generation: 0,
+        }
+    }
+
+    pub fn map_size(&self) -> (u32, u32) {
+        self.map_size
+    }
+
+    /// 设置一个细胞的状态
+    pub fn set_cell(&mut self, (x, y): (u32, u32), alive: bool) {
+        self.cells[(y * self.map_size.0 + x) as usize] = alive as u8;
+        if alive {
+            extend(&mut self.bounds, x as i64, y as i64);
+        }
+    }
+
+    /// 整张地图，格式和纹理中的一样
+    pub fn texels(&self) -> Vec<[u8; 4]> {
+        self.cells
+            .iter()
+            .map(|&cell| if cell == 1 { ALIVE } else { DEAD })
+            .collect()
+    }
+
+    /// 写入一块矩形区域，格式和纹理中的一样
+    pub fn write_texels(&mut self, origin: (u32, u32), size: (u32, u32), data: &[[u8; 4]]) {
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                let texel = data[(y * size.0 + x) as usize];
+                self.set_cell((origin.0 + x, origin.1 + y), texel[0] > 0);
+            }
+        }
+    }
+
+    /// 这一代需要计算的区域 `(x0, y0, x1, y1)`，区域外的细胞下一代一定是死的
+    fn active_region(&self) -> Option<(i64, i64, i64, i64)> {
+        let (width, height) = (self.map_size.0 as i64, self.map_size.1 as i64);
+        let full = Some((0, 0, width - 1, height - 1));
+        // B0 的规则中，没有活细胞的地方也会出生
+        if self.rule.birth & 1 == 1 {
+            return full;
+        }
+        let (x0, y0, x1, y1) = self.bounds?;
+        let (x0, y0, x1, y1) = (x0 - 1, y0 - 1, x1 + 1, y1 + 1);
+        // 碰到边界时，另一边的细胞也可能受到影响
+        if self.topology != Topology::Dead && (x0 < 0 || y0 < 0 || x1 >= width || y1 >= height) {
+            return full;
+        }
+        Some((x0.max(0), y0.max(0), x1.min(width - 1), y1.min(height - 1)))
+    }
+
+    /// 演化一代
+    pub fn update(&mut self) {
+        let (width, height) = (self.map_size.0 as i64, self.map_size.1 as i64);
+
+        // 清掉 `next` 中上上一代留下的细胞
+        if let Some((x0, y0, x1, y1)) = self.next_bounds.take() {
+            for y in y0..=y1 {
+                self.next[(y * width + x0) as usize..=(y * width + x1) as usize].fill(0);
+            }
+        }
+
+        let mut bounds = None;
+        if let Some((x0, y0, x1, y1)) = self.active_region() {
+            let cells = &self.cells;
+            let at = |x: i64, y: i64| cells[(y * width + x) as usize] as u32;
+
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    let neighbors = if x > 0 && y > 0 && x < width - 1 && y < height - 1 {
+                        // 内部的细胞不需要考虑拓扑
+                        at(x - 1, y - 1)
+                            + at(x, y - 1)
+                            + at(x + 1, y - 1)
+                            + at(x - 1, y)
+                            + at(x + 1, y)
+                            + at(x - 1, y + 1)
+                            + at(x, y + 1)
+                            + at(x + 1, y + 1)
+                    } else {
+                        let mut sum = 0;
+                        for dy in -1..=1 {
+                            for dx in -1..=1 {
+                                if (dx, dy) == (0, 0) {
+                                    continue;
+                                }
+                                if let Some((nx, ny)) =
+                                    self.topology.neighbor((x, y), (dx, dy), self.map_size)
+                                {
+                                    sum += at(nx as i64, ny as i64);
+                                }
+                            }
+                        }
+                        sum
+                    };
+                    let alive = self.rule.next_state(at(x, y) == 1, neighbors);
+                    if alive {
+                        extend(&mut bounds, x, y);
+                    }
+                    self.next[(y * width + x) as usize] = alive as u8;
+                }
+            }
+        }
+
+        std::mem::swap(&mut self.cells, &mut self.next);
+        self.next_bounds = self.bounds;
+        self.bounds = bounds;
+        self.generation += 1;
+    }
+}
+
+impl Engine for CpuEngine {
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn step(&mut self, n: u64) {
+        for _ in 0..n {
+            self.update();
+        }
+    }
+
+    fn read_cells(&self) -> Result<Pattern> {
+        Ok(Pattern {
+            width: self.map_size.0,
+            height: self.map_size.1,
+            cells: self.cells.iter().map(|&cell| cell == 1).collect(),
+            rule: Some(self.rule),
+            ..Default::default()
+        })
+    }
+
+    fn write_cells(&mut self, offset: (u32, u32), pattern: &Pattern) -> Result<()> {
+        pattern.check_fits(offset, self.map_size)?;
+        for y in 0..pattern.height {
+            for x in 0..pattern.width {
+                self.set_cell((offset.0 + x, offset.1 + y), pattern.get(x, y));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+//! 热度图：每个细胞在最近若干代中变化了多少次
+//!
+//! 每一代演化之后，`heatmap.wgsl` 比较演化前后的地图，把每个细胞是否变化了
+//! 移入它的 `u32` 中，所以窗口最长 32 代
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    resources::{map_read, PackedCells},
+    Cells, Result, Storage,
+};
+
+/// 热度图的计算管线和每个细胞的变化记录
+pub struct Heatmap {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+    workgroup_count: (u32, u32),
+    map_size_uniform: wgpu::Buffer,
+    /// 每个细胞一个 `u32`，按行排列，第 0 位是最近的一代
+    pub buffer: wgpu::Buffer,
+    window: u32,
+}
+
+impl Heatmap {
+    /// 最长的窗口
+    pub const MAX_WINDOW: u32 = u32::BITS;
+
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        map_size: (u32, u32),
+        storage: Storage,
+        window: u32,
+    ) -> Result<Self> {
+        if !(1..=Self::MAX_WINDOW).contains(&window) {
+            return Err(format!("热度图的窗口必须在 1 到 {} 代之间", Self::MAX_WINDOW).into());
+        }
+        let size = map_size.0 as u64 * map_size.1 as u64 * std::mem::size_of::<u32>() as u64;
+        let limits = device.limits();
+        let max = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        if size > max {
+            return Err(format!(
+                "{}x{} 的热度图需要 {size} 字节的存储缓冲区，设备最多支持 {max} 字节",
+                map_size.0, map_size.1
+            )
+            .into());
+        }
+
+        let map_binding = |binding| match storage {
+            Storage::Texture => wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            Storage::Packed => wgpu::BindGroupLayoutEntry {
+                binding: binding + 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                map_binding(0),
+                map_binding(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let map_size_uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[
+                map_size.0,
+                map_size.1,
+                map_size.0 / PackedCells::CELLS_PER_WORD,
+                0,
+            ]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        // 新创建的缓冲区全是 0，也就是没有变化过
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let shader_module = device.create_shader_module(wgpu::include_wgsl!("heatmap.wgsl"));
+        let pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(&layout),
+                module: &shader_module,
+                entry_point: match storage {
+                    Storage::Texture => "cs_texture",
+                    Storage::Packed => "cs_packed",
+                },
+            })
+        };
+
+        // 按位储存时 x 方向每个调用负责一个字
+        let columns = match storage {
+            Storage::Texture => map_size.0,
+            Storage::Packed => map_size.0 / PackedCells::CELLS_PER_WORD,
+        };
+        Ok(Self {
+            bind_group_layout,
+            pipeline,
+            workgroup_count: (columns.div_ceil(16), map_size.1.div_ceil(16)),
+            map_size_uniform,
+            buffer,
+            window,
+        })
+    }
+
+    /// 数变化次数的窗口，单位是代
+    pub fn window(&self) -> u32 {
+        self.window
+    }
+
+    /// 比较 `before` 和 `after` 的绑定组，两者要么都是纹理，要么都是缓冲区
+    pub(crate) fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        before: Cells,
+        after: Cells,
+    ) -> wgpu::BindGroup {
+        let map_entry = |binding, cells| match cells {
+            Cells::Texture(texture) => wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+            Cells::Packed(cells) => wgpu::BindGroupEntry {
+                binding: binding + 2,
+                resource: cells.buffer.as_entire_binding(),
+            },
+        };
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: &[
+                map_entry(0, before),
+                map_entry(1, after),
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: self.map_size_uniform.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// 在 `encoder` 中记录一次更新，`bind_group` 来自 `bind_group`
+    pub(crate) fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_group: &wgpu::BindGroup,
+    ) {
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+        cpass.set_pipeline(&self.pipeline);
+        cpass.set_bind_group(0, bind_group, &[]);
+        cpass.dispatch_workgroups(self.workgroup_count.0, self.workgroup_count.1, 1);
+    }
+
+    /// 读回每个细胞在窗口内变化的次数，按行排列
+    pub(crate) fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Vec<u32>> {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: self.buffer.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &buffer, 0, self.buffer.size());
+        queue.submit(Some(encoder.finish()));
+
+        let mask = u32::MAX >> (u32::BITS - self.window);
+        Ok(map_read(device, &buffer)?
+            .chunks_exact(std::mem::size_of::<u32>())
+            .map(|word| (u32::from_ne_bytes(word.try_into().unwrap()) & mask).count_ones())
+            .collect())
+    }
+}
@@ -0,0 +1,61 @@
+// 热度图：记录每个细胞最近 32 代中每一代是否变化了，第 0 位是最近的一代
+// 显示时只数窗口内的位，见 `shader.wgsl`
+
+// 演化前后的纹理和按位储存的缓冲区，两个入口各用其中一种
+@group(0) @binding(0)
+var before_textre : texture_2d<f32>;
+
+@group(0) @binding(1)
+var after_textre : texture_2d<f32>;
+
+@group(0) @binding(2)
+var<storage, read> before_cells : array<u32>;
+
+@group(0) @binding(3)
+var<storage, read> after_cells : array<u32>;
+
+@group(0) @binding(4)
+var<storage, read_write> heat : array<u32>;
+
+struct MapSize {
+    map_size: vec2u,
+    // 按位储存时每行的字数
+    words_per_row: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(5)
+var<uniform> map_size : MapSize;
+
+struct ComputeInput {
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+}
+
+// 每个调用负责一个纹素
+@compute @workgroup_size(16, 16)
+fn cs_texture(in: ComputeInput) {
+    let cell = in.global_id.xy;
+    if any(cell >= map_size.map_size) {
+        return;
+    }
+    let before = textureLoad(before_textre, vec2<i32>(cell), 0).r > 0.0;
+    let after = textureLoad(after_textre, vec2<i32>(cell), 0).r > 0.0;
+    let index = cell.y * map_size.map_size.x + cell.x;
+    heat[index] = (heat[index] << 1u) | u32(before != after);
+}
+
+// 每个调用负责一个字，也就是 32 个细胞
+@compute @workgroup_size(16, 16)
+fn cs_packed(in: ComputeInput) {
+    let word_index = in.global_id.x;
+    let y = in.global_id.y;
+    if word_index >= map_size.words_per_row || y >= map_size.map_size.y {
+        return;
+    }
+    let word = y * map_size.words_per_row + word_index;
+    let changed = before_cells[word] ^ after_cells[word];
+    let first = y * map_size.map_size.x + word_index * 32u;
+    for (var bit = 0u; bit < 32u; bit++) {
+        heat[first + bit] = (heat[first + bit] << 1u) | ((changed >> bit) & 1u);
+    }
+}
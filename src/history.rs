@@ -0,0 +1,93 @@
+//! 倒退用的历史：每隔若干代保存一个关键帧，中间的代从关键帧重新演化得到
+//!
+//! 关键帧把历史分成若干段，每一段从关键帧开始一直演化到下一个关键帧。
+//! 编辑细胞之后，在下一次演化之前保存编辑后的地图作为新的一段，
+//! 编辑前的地图就是上一段的末尾，所以撤销编辑和倒退一代是同一个操作
+
+use std::collections::VecDeque;
+
+use crate::{
+    pattern::Pattern,
+    resources::{PackedCells, Texture},
+};
+
+/// 关键帧的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyframeKind {
+    /// 开始记录历史时的地图
+    Start,
+    /// 每隔若干代保存的地图
+    Periodic,
+    /// 编辑之后的地图
+    Edit,
+}
+
+/// 关键帧中的地图，和模拟器中的地图类型相同，GPU上的地图直接在GPU上复制
+pub(crate) enum Snapshot {
+    Texture(Texture),
+    Packed(PackedCells),
+    Cpu(Pattern),
+}
+
+pub(crate) struct Keyframe {
+    pub generation: u64,
+    pub kind: KeyframeKind,
+    pub snapshot: Snapshot,
+}
+
+pub(crate) struct History {
+    /// 每隔多少代保存一个关键帧
+    pub interval: u64,
+    /// 最多保存的关键帧数，超出时丢掉最早的
+    capacity: usize,
+    /// 按时间顺序排列，最后一个是当前这一段的开头
+    pub keyframes: VecDeque<Keyframe>,
+    /// 当前这一段的开头之后是否编辑过细胞
+    pub dirty: bool,
+    /// 被丢掉的关键帧的地图，保存新的关键帧时重复使用
+    spare: Vec<Snapshot>,
+}
+
+impl History {
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            keyframes: VecDeque::new(),
+            dirty: false,
+            spare: vec![],
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn push(&mut self, keyframe: Keyframe) {
+        if self.keyframes.len() == self.capacity {
+            let oldest = self.keyframes.pop_front().unwrap();
+            self.spare.push(oldest.snapshot);
+        }
+        self.keyframes.push_back(keyframe);
+    }
+
+    /// 取出一个可以重复使用的地图
+    pub fn take_spare(&mut self) -> Option<Snapshot> {
+        self.spare.pop()
+    }
+
+    /// 丢掉第 `index` 个之后的关键帧，倒退之后它们就不再是历史了
+    pub fn truncate(&mut self, index: usize) {
+        while self.keyframes.len() > index + 1 {
+            let keyframe = self.keyframes.pop_back().unwrap();
+            self.spare.push(keyframe.snapshot);
+        }
+    }
+
+    /// 包含第 `generation` 代的最后一段开头的关键帧
+    pub fn segment_of(&self, generation: u64) -> Option<usize> {
+        self.keyframes
+            .iter()
+            .rposition(|keyframe| keyframe.generation <= generation)
+    }
+}
@@ -0,0 +1,451 @@
+//! 按键设置：把按键和鼠标按钮映射到有名字的动作
+//!
+//! 设置文件每行一条 `动作 = 按键, 按键, ...`，`#` 之后是注释。文件中出现的动作替换掉
+//! 默认的绑定，没有出现的动作保留默认的绑定；同一个按键只属于最后绑定它的动作。
+//! 按键的名字和 winit 的 `KeyCode` 一样，例如 `KeyW`、`ArrowUp`、`Digit1`，
+//! 鼠标按钮是 `MouseLeft`、`MouseRight`、`MouseMiddle`、`MouseBack`、`MouseForward`
+
+use std::{fmt, path::Path};
+
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+use crate::Result;
+
+/// 可以绑定按键的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    ZoomIn,
+    ZoomOut,
+    FitMap,
+    OneToOne,
+    ToggleCamera,
+    Drag,
+    Step,
+    Jump,
+    StepBack,
+    SeekBack,
+    RunToTarget,
+    HoldRun,
+    ToggleRun,
+    Faster,
+    Slower,
+    ToggleColors,
+    ToggleHeatmap,
+    CycleGrid,
+    ToggleZoomedOut,
+    Paint,
+    Erase,
+    Save,
+    ExportImage,
+    SaveSnapshot,
+    LoadSnapshot,
+    ToggleRecording,
+    Quit,
+}
+
+impl Action {
+    /// 所有的动作，按设置文件中的顺序
+    pub const ALL: [Action; 31] = [
+        Action::PanUp,
+        Action::PanDown,
+        Action::PanLeft,
+        Action::PanRight,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::FitMap,
+        Action::OneToOne,
+        Action::ToggleCamera,
+        Action::Drag,
+        Action::Step,
+        Action::Jump,
+        Action::StepBack,
+        Action::SeekBack,
+        Action::RunToTarget,
+        Action::HoldRun,
+        Action::ToggleRun,
+        Action::Faster,
+        Action::Slower,
+        Action::ToggleColors,
+        Action::ToggleHeatmap,
+        Action::CycleGrid,
+        Action::ToggleZoomedOut,
+        Action::Paint,
+        Action::Erase,
+        Action::Save,
+        Action::ExportImage,
+        Action::SaveSnapshot,
+        Action::LoadSnapshot,
+        Action::ToggleRecording,
+        Action::Quit,
+    ];
+
+    /// 设置文件中的名字
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::PanUp => "pan_up",
+            Action::PanDown => "pan_down",
+            Action::PanLeft => "pan_left",
+            Action::PanRight => "pan_right",
+            Action::ZoomIn => "zoom_in",
+            Action::ZoomOut => "zoom_out",
+            Action::FitMap => "fit_map",
+            Action::OneToOne => "one_to_one",
+            Action::ToggleCamera => "toggle_camera",
+            Action::Drag => "drag",
+            Action::Step => "step",
+            Action::Jump => "jump",
+            Action::StepBack => "step_back",
+            Action::SeekBack => "seek_back",
+            Action::RunToTarget => "run_to_target",
+            Action::HoldRun => "hold_run",
+            Action::ToggleRun => "toggle_run",
+            Action::Faster => "faster",
+            Action::Slower => "slower",
+            Action::ToggleColors => "toggle_colors",
+            Action::ToggleHeatmap => "toggle_heatmap",
+            Action::CycleGrid => "cycle_grid",
+            Action::ToggleZoomedOut => "toggle_zoomed_out",
+            Action::Paint => "paint",
+            Action::Erase => "erase",
+            Action::Save => "save",
+            Action::ExportImage => "export_image",
+            Action::SaveSnapshot => "save_snapshot",
+            Action::LoadSnapshot => "load_snapshot",
+            Action::ToggleRecording => "toggle_recording",
+            Action::Quit => "quit",
+        }
+    }
+}
+
+/// 一个按键或者鼠标按钮
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+/// 可以写在设置文件中的按键
+const KEYS: &[KeyCode] = &[
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::Insert,
+    KeyCode::Delete,
+    KeyCode::Space,
+    KeyCode::Enter,
+    KeyCode::Escape,
+    KeyCode::Backspace,
+    KeyCode::Tab,
+    KeyCode::Equal,
+    KeyCode::Minus,
+    KeyCode::BracketLeft,
+    KeyCode::BracketRight,
+    KeyCode::Backslash,
+    KeyCode::Semicolon,
+    KeyCode::Quote,
+    KeyCode::Backquote,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::AltLeft,
+    KeyCode::AltRight,
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+    KeyCode::NumpadAdd,
+    KeyCode::NumpadSubtract,
+    KeyCode::NumpadMultiply,
+    KeyCode::NumpadDivide,
+    KeyCode::NumpadDecimal,
+    KeyCode::NumpadEnter,
+];
+
+const MOUSE_BUTTONS: &[(MouseButton, &str)] = &[
+    (MouseButton::Left, "MouseLeft"),
+    (MouseButton::Right, "MouseRight"),
+    (MouseButton::Middle, "MouseMiddle"),
+    (MouseButton::Back, "MouseBack"),
+    (MouseButton::Forward, "MouseForward"),
+];
+
+impl Input {
+    fn parse(name: &str) -> Option<Self> {
+        if let Some(&(button, _)) = MOUSE_BUTTONS.iter().find(|(_, n)| *n == name) {
+            return Some(Input::Mouse(button));
+        }
+        KEYS.iter()
+            .find(|key| format!("{key:?}") == name)
+            .map(|&key| Input::Key(key))
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Key(key) => write!(f, "{key:?}"),
+            Input::Mouse(button) => match MOUSE_BUTTONS.iter().find(|(b, _)| b == button) {
+                Some((_, name)) => f.write_str(name),
+                None => write!(f, "{button:?}"),
+            },
+        }
+    }
+}
+
+/// 按键到动作的映射
+#[derive(Debug, Clone)]
+pub struct InputMap {
+    /// 按绑定的顺序排列，一个按键最多出现一次
+    bindings: Vec<(Input, Action)>,
+}
+
+impl Default for InputMap {
+    /// 和 README 中的操作方法一致
+    fn default() -> Self {
+        use Action::*;
+        use Input::{Key, Mouse};
+        Self {
+            bindings: vec![
+                (Key(KeyCode::KeyW), PanUp),
+                (Key(KeyCode::ArrowUp), PanUp),
+                (Key(KeyCode::KeyS), PanDown),
+                (Key(KeyCode::ArrowDown), PanDown),
+                (Key(KeyCode::KeyA), PanLeft),
+                (Key(KeyCode::ArrowLeft), PanLeft),
+                (Key(KeyCode::KeyD), PanRight),
+                (Key(KeyCode::ArrowRight), PanRight),
+                (Key(KeyCode::PageUp), ZoomIn),
+                (Key(KeyCode::PageDown), ZoomOut),
+                (Key(KeyCode::KeyF), FitMap),
+                (Key(KeyCode::Digit1), OneToOne),
+                (Key(KeyCode::KeyO), ToggleCamera),
+                (Mouse(MouseButton::Middle), Drag),
+                (Key(KeyCode::KeyN), Step),
+                (Key(KeyCode::KeyJ), Jump),
+                (Key(KeyCode::Backspace), StepBack),
+                (Key(KeyCode::KeyZ), StepBack),
+                (Key(KeyCode::KeyB), SeekBack),
+                (Key(KeyCode::KeyG), RunToTarget),
+                (Key(KeyCode::Space), HoldRun),
+                (Key(KeyCode::Enter), ToggleRun),
+                (Key(KeyCode::NumpadEnter), ToggleRun),
+                (Key(KeyCode::Equal), Faster),
+                (Key(KeyCode::NumpadAdd), Faster),
+                (Key(KeyCode::Minus), Slower),
+                (Key(KeyCode::NumpadSubtract), Slower),
+                (Key(KeyCode::KeyC), ToggleColors),
+                (Key(KeyCode::KeyH), ToggleHeatmap),
+                (Key(KeyCode::KeyL), CycleGrid),
+                (Key(KeyCode::KeyM), ToggleZoomedOut),
+                (Mouse(MouseButton::Left), Paint),
+                (Mouse(MouseButton::Right), Erase),
+                (Key(KeyCode::KeyP), Save),
+                (Key(KeyCode::KeyI), ExportImage),
+                (Key(KeyCode::F5), SaveSnapshot),
+                (Key(KeyCode::F9), LoadSnapshot),
+                (Key(KeyCode::KeyR), ToggleRecording),
+                (Key(KeyCode::Escape), Quit),
+            ],
+        }
+    }
+}
+
+impl InputMap {
+    /// 在默认的绑定上应用设置文件
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| format!("无法读取按键设置 {}：{error}", path.display()))?;
+        Self::parse(&text).map_err(|error| format!("{}：{error}", path.display()).into())
+    }
+
+    /// 在默认的绑定上应用设置文件的内容
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut map = Self::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let line_number = index + 1;
+            let (name, inputs) = line
+                .split_once('=')
+                .ok_or_else(|| format!("第 {line_number} 行应为 `动作 = 按键, ...`"))?;
+            let name = name.trim();
+            let action = Action::ALL
+                .into_iter()
+                .find(|action| action.name() == name)
+                .ok_or_else(|| format!("第 {line_number} 行：未知的动作 `{name}`"))?;
+
+            // 留空表示不绑定任何按键
+            map.bindings.retain(|&(_, bound)| bound != action);
+            for input in inputs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                let input = Input::parse(input)
+                    .ok_or_else(|| format!("第 {line_number} 行：未知的按键 `{input}`"))?;
+                map.bindings.retain(|&(bound, _)| bound != input);
+                map.bindings.push((input, action));
+            }
+        }
+        Ok(map)
+    }
+
+    /// `input` 绑定的动作
+    pub fn action(&self, input: Input) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|&&(bound, _)| bound == input)
+            .map(|&(_, action)| action)
+    }
+}
+
+/// 写成设置文件的格式
+impl fmt::Display for InputMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for action in Action::ALL {
+            let inputs: Vec<String> = self
+                .bindings
+                .iter()
+                .filter(|&&(_, bound)| bound == action)
+                .map(|(input, _)| input.to_string())
+                .collect();
+            if inputs.is_empty() {
+                writeln!(f, "{} =", action.name())?;
+            } else {
+                writeln!(f, "{} = {}", action.name(), inputs.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        InputMap::parse(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn rebinds_actions() {
+        let map = InputMap::parse(
+            "# 注释和空行被忽略\n\
+             \n\
+             step = KeyX, MouseBack  # 替换掉 N\n\
+             quit = KeyW\n",
+        )
+        .unwrap();
+        assert_eq!(map.action(Input::Key(KeyCode::KeyX)), Some(Action::Step));
+        assert_eq!(
+            map.action(Input::Mouse(MouseButton::Back)),
+            Some(Action::Step)
+        );
+        assert_eq!(map.action(Input::Key(KeyCode::KeyN)), None);
+        // 同一个按键只属于最后绑定它的动作，没有出现的动作保留其他默认的按键
+        assert_eq!(map.action(Input::Key(KeyCode::KeyW)), Some(Action::Quit));
+        assert_eq!(map.action(Input::Key(KeyCode::Escape)), None);
+        assert_eq!(
+            map.action(Input::Key(KeyCode::ArrowUp)),
+            Some(Action::PanUp)
+        );
+    }
+
+    #[test]
+    fn empty_bindings() {
+        let map = InputMap::parse("paint =\nerase = ,\n").unwrap();
+        assert_eq!(map.action(Input::Mouse(MouseButton::Left)), None);
+        assert_eq!(map.action(Input::Mouse(MouseButton::Right)), None);
+        let text = map.to_string();
+        assert!(text.contains("paint =\n"));
+        assert!(text.contains("erase =\n"));
+    }
+
+    #[test]
+    fn round_trips() {
+        let map = InputMap::parse("faster = KeyQ, MouseForward\n").unwrap();
+        let text = map.to_string();
+        let parsed = InputMap::parse(&text).unwrap();
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(
+            InputMap::default().to_string().lines().count(),
+            Action::ALL.len()
+        );
+    }
+
+    #[test]
+    fn reports_lines() {
+        assert_eq!(
+            error("step = KeyX\n\nstep KeyY"),
+            "第 3 行应为 `动作 = 按键, ...`"
+        );
+        assert_eq!(error("# 注释\nfly = KeyX"), "第 2 行：未知的动作 `fly`");
+        assert_eq!(error("step = KeyX, Shift"), "第 1 行：未知的按键 `Shift`");
+    }
+}
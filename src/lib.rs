@@ -0,0 +1,41 @@
+//! 生命游戏的模拟部分，不依赖窗口和展示平面，可以在测试和批处理中使用
+//!
+//! 窗口程序（`src/main.rs`）建立在这个库之上
+
+pub mod census;
+pub mod compute;
+pub mod cpu;
+pub mod heatmap;
+mod history;
+pub mod packed;
+pub mod pattern;
+pub mod period;
+pub mod pyramid;
+pub mod recording;
+pub mod resources;
+pub mod rule;
+mod simulator;
+pub mod snapshot;
+pub mod stats;
+pub mod topology;
+
+use std::error::Error;
+
+pub use simulator::{request_adapter, request_device, Cells, Simulator, Storage};
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// 演化的接口，GPU上的 `Simulator` 和CPU上的 `cpu::CpuEngine` 都实现了它
+pub trait Engine {
+    /// 当前是第几代
+    fn generation(&self) -> u64;
+
+    /// 演化 `n` 代
+    fn step(&mut self, n: u64);
+
+    /// 读回整张地图
+    fn read_cells(&self) -> Result<pattern::Pattern>;
+
+    /// 把图案写到地图上，`offset` 是图案左上角的位置
+    fn write_cells(&mut self, offset: (u32, u32), pattern: &pattern::Pattern) -> Result<()>;
+}
@@ -1,23 +1,98 @@
-mod compute;
+mod input;
 mod mvp;
 mod render;
-mod resources;
+mod speed;
 
-use std::{cell::OnceCell, error::Error, time::Instant};
+use std::{
+    cell::OnceCell,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
-use resources::{Texture, Vertex};
+use input::{Action, Input, InputMap};
+use life_game_wgpu::{
+    census::SoupSearch,
+    pattern,
+    pyramid::Density,
+    recording::{self, Recorder},
+    resources::{ColorMap, Gradient, Grid},
+    rule,
+    snapshot::{Snapshot, View},
+    topology, Engine, Result, Simulator, Storage,
+};
 use wgpu::util::DeviceExt;
 use winit::{
-    event::{Event, WindowEvent},
-    keyboard::KeyCode,
+    event::{ElementState, Event, WindowEvent},
+    keyboard::PhysicalKey,
 };
 
-type Result<T> = std::result::Result<T, Box<dyn Error>>;
-
 fn main() -> Result<()> {
     // 初始化日志
     env_logger::init();
 
+    let options = Options::parse()?;
+
+    // 按键设置，没有指定文件时和 README 中的一样
+    let input_map = match &options.input_map {
+        Some(path) => InputMap::load(path)?,
+        None => InputMap::default(),
+    };
+    if options.print_input_map {
+        print!("{input_map}");
+        return Ok(());
+    }
+
+    // 汤搜索不需要窗口
+    if let Some(soups) = options.census {
+        return census(&options, soups);
+    }
+
+    // 按 F5 保存、F9 恢复的快照
+    let snapshot_path = options
+        .snapshot
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SNAPSHOT.into());
+    // 从快照继续时，地图大小、规则、拓扑和储存方式都用快照中的
+    let snapshot = options
+        .resume
+        .then(|| Snapshot::load(&snapshot_path))
+        .transpose()?;
+
+    // 地图大小
+    let map_size = match &snapshot {
+        Some(snapshot) => snapshot.map_size,
+        None => options.size.unwrap_or((2048, 2048)),
+    };
+
+    // 从文件读取的初始图案
+    let pattern = options
+        .pattern
+        .as_ref()
+        .map(|path| pattern::Pattern::load(path, map_size))
+        .transpose()?;
+
+    // 命令行指定的规则优先于图案文件中的规则
+    let rule = options
+        .rule
+        .or(pattern.as_ref().and_then(|pattern| pattern.rule))
+        .unwrap_or_default();
+    let (rule, topology, storage) = match &snapshot {
+        Some(snapshot) => (snapshot.rule, snapshot.topology, snapshot.storage),
+        None => (rule, options.topology.unwrap_or_default(), options.storage),
+    };
+
+    // 不打开窗口，演化并录制
+    if let Some(generations) = options.headless {
+        let mut sim = pollster::block_on(Simulator::new(map_size, rule, topology, storage))?;
+        initialize(&mut sim, &options, pattern.as_ref(), snapshot.as_ref())?;
+        return headless(
+            &options,
+            sim,
+            generations,
+            snapshot.as_ref().map(|snapshot| &snapshot.view),
+        );
+    }
+
     // 创建窗口
     let event_loop = winit::event_loop::EventLoop::new()?;
     let window = winit::window::WindowBuilder::new()
@@ -26,70 +101,31 @@ fn main() -> Result<()> {
         .build(&event_loop)?;
 
     // wgpu的适配器和设备差创建是异步函数，得用一个异步运行时库
-    let mut state = pollster::block_on(State::new(&window))?;
+    let mut state = pollster::block_on(State::new(&window, map_size, rule, topology, storage))?;
 
-    // 地图大小
-    let map_size = (2048, 2048);
-
-    // 默认的地图
-    let mut default_map = (0..map_size.0 * map_size.1)
-        .map(|_| [0, 0, 0, 0])
-        .collect::<Vec<[u8; 4]>>();
+    initialize(
+        &mut state.sim,
+        &options,
+        pattern.as_ref(),
+        snapshot.as_ref(),
+    )?;
 
-    // 用来快速配置地图的宏
-    macro_rules! lightup {
-        ($x :expr, $y: expr) => {
-            default_map[($y * map_size.0 + $x) as usize] = [255u8, 255, 255, 255];
-        };
-        (all) => {
-            for x in 0..map_size.0 {
-                for y in 0..map_size.1 {
-                    lightup!(x, y);
-                }
-            }
-        };
-    }
+    // 倒退用的历史，从初始地图开始记录
+    state.sim.enable_history(
+        options
+            .keyframe_interval
+            .unwrap_or(DEFAULT_KEYFRAME_INTERVAL),
+        options.keyframes.unwrap_or(DEFAULT_KEYFRAMES),
+    );
 
-    // 创建10,000个滑翔机
-    for x in 0..100 {
-        for y in 0..100 {
-            let x = x * 10;
-            let y = y * 10;
-            lightup!(x + 3, y + 2);
-            lightup!(x + 4, y + 3);
-            lightup!(x + 2, y + 4);
-            lightup!(x + 3, y + 4);
-            lightup!(x + 4, y + 4);
-        }
+    // 判断地图是否已经稳定，每一代都要统计，所以只在需要自动暂停时打开
+    if options.auto_pause {
+        state.sim.enable_period_detection(PERIOD_TABLE_SIZE);
     }
 
-    // 创建两个纹理
-    let textures = [
-        Texture::new(&state, map_size),
-        Texture::new(&state, map_size),
-    ];
-
-    // 都初始化成“初始地图”
-    for texture in &textures {
-        state.queue.write_texture(
-            wgpu::ImageCopyTextureBase {
-                texture: &texture.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            bytemuck::cast_slice(&default_map),
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(map_size.0 * std::mem::size_of::<u32>() as u32),
-                rows_per_image: Some(map_size.1),
-            },
-            wgpu::Extent3d {
-                width: map_size.0,
-                height: map_size.1,
-                depth_or_array_layers: 1,
-            },
-        );
+    // 缩小时显示每一块的密度，在CPU上演化时只能用最近邻采样
+    if let Err(error) = state.sim.enable_pyramid() {
+        eprintln!("缩小时可能会漏掉细胞：{error}");
     }
 
     // 投影& 透视
@@ -105,25 +141,75 @@ fn main() -> Result<()> {
         100.0,
     );
     let mut camera_controler = mvp::CameraController::new();
+    // 正交模式下缩放是精确的每像素格数，滚轮围绕鼠标缩放，按 O 切换到透视模式
+    let mut orthographic = !options.perspective;
+    let mut ortho = mvp::OrthoCamera::new(
+        map_size,
+        (window.inner_size().width, window.inner_size().height),
+    );
+    if let Some(snapshot) = &snapshot {
+        orthographic = mvp::restore_view(
+            &snapshot.view,
+            &mut ortho,
+            &mut camera,
+            &mut camera_controler,
+        );
+    }
 
     // 渲染的部分
-    let vertices: &[Vertex] = &[
-        [-1., 1.0, 0., 0.].into(), // 左上
-        [1.0, 1.0, 1., 0.].into(), // 右上
-        [1.0, -1., 1., 1.].into(), // 右下
-        [-1., -1., 0., 1.].into(), // 左下
-    ];
-    let indicens: &[u16] = &[0, 1, 2, 0, 2, 3];
+    // 按 C 在按年龄着色和黑白之间切换
+    let mut color_map = options.color_map.unwrap_or_default();
+    // 按 H 显示的热度图的渐变色
+    let mut gradient = options.heat_gradient.unwrap_or_default();
+    if let Some(opacity) = options.heat_opacity {
+        gradient.opacity = opacity;
+    }
+    // 按 L 在网格线、加上主线和不画线之间切换
+    let mut grid = options.grid.unwrap_or_default();
+    // 按 M 在缩小时显示最大值和平均密度之间切换
+    let mut density = options.zoomed_out.unwrap_or_default();
     let render = render::Render::new(
-        &state,
-        vertices,
-        indicens,
-        projection.calc_matrix() * camera.calc_matrix(),
+        &state.sim,
+        state.config.format,
+        if orthographic {
+            ortho.calc_matrix()
+        } else {
+            projection.calc_matrix() * camera.calc_matrix()
+        },
+        &color_map,
+        &gradient,
+        &grid,
     );
+    render.set_density(&state.sim, density);
 
-    // 更新（计算）的部分
+    // 按 R 开始或者停止录制，用 `--record` 指定文件时一开始就录制
+    let mut recording = options
+        .record
+        .clone()
+        .map(|path| Recording::start(path, &options, map_size))
+        .transpose()?;
+
+    // 更新（计算）的部分：按住 Space 时，或者按 Enter 切换到运行状态时，按设定的速度演化
     let mut update = false;
-    let compute = compute::Compute::new(&state, map_size);
+    let mut running = false;
+    let mut speed = speed::Speed::new(options.speed.unwrap_or(DEFAULT_SPEED));
+    let mut last_update = Instant::now();
+    // 正在一直演化到的目标代数
+    let mut running_to: Option<u64> = None;
+
+    // 鼠标绘制：左键画上活细胞，右键擦除
+    let mut cursor_position = glam::Vec2::ZERO;
+    // 正在绘制时为 `Some(是否画活细胞)`
+    let mut painting: Option<bool> = None;
+    // 上一次绘制的格子，拖动时从这里连线过去
+    let mut last_painted: Option<(u32, u32)> = None;
+    // 按住中键拖动地图
+    let mut dragging = false;
+
+    // 窗口标题，只在变化时更新
+    let mut title = String::new();
+    // 上一次报告的周期，只在变化时报告
+    let mut reported_period = None;
 
     let mut last_frame: OnceCell<Instant> = OnceCell::new();
 
@@ -134,75 +220,882 @@ fn main() -> Result<()> {
             *instant = Instant::now()
         };
         match event {
-            Event::WindowEvent { window_id, event } if window_id == window.id() => match event {
-                WindowEvent::CloseRequested => loop_target.exit(),
-
-                WindowEvent::Resized(new_size) if new_size.width > 0 && new_size.height > 0 => {
-                    state.config.width = new_size.width;
-                    state.config.height = new_size.height;
-                    state.surface.configure(&state.device, &state.config);
-                    projection.resize(new_size.width, new_size.height);
-                }
-                WindowEvent::KeyboardInput {
-                    event:
-                        winit::event::KeyEvent {
-                            state: element_state,
-                            physical_key: winit::keyboard::PhysicalKey::Code(key_code),
-                            // virtual_keycode: Some(key_code),
-                            ..
-                        },
-                    ..
-                } if !camera_controler.process_keyboard(key_code, element_state) => {
-                    match key_code {
-                        KeyCode::Escape if element_state == winit::event::ElementState::Pressed => {
-                            loop_target.exit()
+            Event::WindowEvent { window_id, event } if window_id == window.id() => {
+                // 按键和鼠标按钮先按设置找到绑定的动作
+                let input = match &event {
+                    WindowEvent::KeyboardInput {
+                        event:
+                            winit::event::KeyEvent {
+                                state,
+                                physical_key: PhysicalKey::Code(key_code),
+                                ..
+                            },
+                        ..
+                    } => Some((Input::Key(*key_code), *state)),
+                    WindowEvent::MouseInput { state, button, .. } => {
+                        Some((Input::Mouse(*button), *state))
+                    }
+                    _ => None,
+                };
+                let action =
+                    input.and_then(|(input, state)| Some((input_map.action(input)?, state)));
+                if let Some((action, element_state)) = action {
+                    let pressed = element_state == ElementState::Pressed;
+                    match action {
+                        Action::PanUp | Action::PanDown | Action::PanLeft | Action::PanRight => {
+                            camera_controler.process_action(action, pressed)
+                        }
+                        Action::Drag => dragging = pressed,
+                        Action::Paint | Action::Erase => {
+                            // 按下时开始绘制，`Some(是否画活细胞)`
+                            painting = pressed.then_some(action == Action::Paint);
+                            last_painted = None;
+                        }
+                        Action::HoldRun => {
+                            if pressed && !update {
+                                speed.reset();
+                            }
+                            update = pressed
+                        }
+                        _ if !pressed => {}
+                        Action::Quit => loop_target.exit(),
+                        Action::Step => state.sim.update(),
+                        Action::Jump => state.sim.step(options.jump.unwrap_or(DEFAULT_JUMP)),
+                        Action::StepBack if !state.sim.step_back() => {
+                            println!("已经退到最早的历史")
+                        }
+                        Action::StepBack => {}
+                        Action::SeekBack => {
+                            let generation = state
+                                .sim
+                                .generation()
+                                .saturating_sub(options.jump.unwrap_or(DEFAULT_JUMP));
+                            if let Some((first, _)) = state.sim.history_range() {
+                                state.sim.seek(generation.max(first));
+                            }
+                        }
+                        Action::ZoomIn | Action::ZoomOut => {
+                            // 和滚轮滚一格一样
+                            let lines = if action == Action::ZoomIn { 1.0 } else { -1.0 };
+                            let delta = winit::event::MouseScrollDelta::LineDelta(0.0, lines);
+                            if orthographic {
+                                let size = window.inner_size();
+                                ortho.zoom_at(
+                                    glam::vec2(size.width as f32, size.height as f32) / 2.0,
+                                    mvp::CameraController::wheel_zoom_factor(delta),
+                                );
+                            } else {
+                                camera_controler.process_wheel(delta, dt);
+                            }
+                        }
+                        Action::ToggleColors => {
+                            color_map = if color_map == ColorMap::MONOCHROME {
+                                ColorMap::AGE
+                            } else {
+                                ColorMap::MONOCHROME
+                            };
+                            render.set_color_map(&state.sim, &color_map);
+                        }
+                        Action::CycleGrid => {
+                            grid = if grid == Grid::LINES {
+                                Grid::MAJOR_LINES
+                            } else if grid.is_enabled() {
+                                Grid::OFF
+                            } else {
+                                Grid::LINES
+                            };
+                            render.set_grid(&state.sim, &grid);
                         }
-                        KeyCode::KeyN if element_state == winit::event::ElementState::Pressed => {
-                            compute.update(&mut state, &textures)
+                        Action::ToggleZoomedOut => {
+                            density = match density {
+                                Density::Max => Density::Average,
+                                Density::Average => Density::Max,
+                            };
+                            render.set_density(&state.sim, density);
                         }
-                        KeyCode::Space => {
-                            update = element_state == winit::event::ElementState::Pressed
+                        Action::FitMap => {
+                            orthographic = true;
+                            ortho.fit();
                         }
-                        _ => {}
+                        Action::OneToOne => {
+                            orthographic = true;
+                            ortho.one_to_one();
+                        }
+                        Action::ToggleCamera => orthographic = !orthographic,
+                        Action::ToggleHeatmap => {
+                            // 不显示时也不计算
+                            if state.sim.heatmap().is_some() {
+                                state.sim.disable_heatmap();
+                            } else if let Err(error) = state
+                                .sim
+                                .enable_heatmap(options.heat_window.unwrap_or(DEFAULT_HEAT_WINDOW))
+                            {
+                                eprintln!("无法显示热度图：{error}");
+                            }
+                        }
+                        Action::RunToTarget => {
+                            // 再按一次停下
+                            running_to = match (running_to, options.target) {
+                                (Some(_), _) => None,
+                                (None, Some(target)) => Some(target),
+                                (None, None) => {
+                                    println!("没有用 `--target` 指定目标代数");
+                                    None
+                                }
+                            };
+                        }
+                        Action::Save => {
+                            let path = format!(
+                                "generation_{}.{}",
+                                state.sim.generation(),
+                                options.save_format.unwrap_or("rle")
+                            );
+                            match state.sim.save(&path) {
+                                Ok(()) => println!("已保存到 {path}"),
+                                Err(e) => eprintln!("保存失败：{e}"),
+                            }
+                        }
+                        Action::ExportImage => {
+                            let path = format!("generation_{}.png", state.sim.generation());
+                            match state.sim.save_image(&path, options.image_channels) {
+                                Ok(()) => println!("已导出到 {path}"),
+                                Err(e) => eprintln!("导出失败：{e}"),
+                            }
+                        }
+                        Action::SaveSnapshot => {
+                            let view =
+                                mvp::save_view(orthographic, &ortho, &camera, &camera_controler);
+                            match state
+                                .sim
+                                .snapshot(view)
+                                .and_then(|snapshot| snapshot.save(&snapshot_path))
+                            {
+                                Ok(()) => println!("已保存快照到 {}", snapshot_path.display()),
+                                Err(e) => eprintln!("保存快照失败：{e}"),
+                            }
+                        }
+                        Action::LoadSnapshot => {
+                            let resumed = Snapshot::load(&snapshot_path).and_then(|snapshot| {
+                                state.sim.resume(&snapshot)?;
+                                Ok(snapshot.view)
+                            });
+                            match resumed {
+                                Ok(view) => {
+                                    orthographic = mvp::restore_view(
+                                        &view,
+                                        &mut ortho,
+                                        &mut camera,
+                                        &mut camera_controler,
+                                    );
+                                    running_to = None;
+                                    println!("已恢复到第 {} 代", state.sim.generation());
+                                }
+                                Err(e) => eprintln!("恢复快照失败：{e}"),
+                            }
+                        }
+                        Action::ToggleRecording if recording.is_some() => {
+                            stop_recording(&mut recording)
+                        }
+                        Action::ToggleRecording => {
+                            let path =
+                                recording_path(options.record.as_ref(), state.sim.generation());
+                            match Recording::start(path, &options, map_size) {
+                                Ok(started) => recording = Some(started),
+                                Err(e) => eprintln!("录制失败：{e}"),
+                            }
+                        }
+                        Action::ToggleRun => {
+                            running = !running;
+                            speed.reset();
+                        }
+                        Action::Faster => speed.faster(),
+                        Action::Slower => speed.slower(),
                     }
                 }
-                WindowEvent::MouseWheel { delta, .. } => camera_controler.process_wheel(delta, dt),
-                WindowEvent::RedrawRequested => {
-                    camera_controler.update_camera(&mut camera, dt);
-
-                    render.update_camera_uniform(
-                        &state,
-                        projection.calc_matrix() * camera.calc_matrix(),
-                    );
-                    render.render(&state, &textures);
-                    if update {
-                        compute.update(&mut state, &textures)
+
+                match event {
+                    WindowEvent::CloseRequested => loop_target.exit(),
+
+                    WindowEvent::Resized(new_size) if new_size.width > 0 && new_size.height > 0 => {
+                        state.config.width = new_size.width;
+                        state.config.height = new_size.height;
+                        state.surface.configure(&state.sim.device, &state.config);
+                        projection.resize(new_size.width, new_size.height);
+                        ortho.resize(new_size.width, new_size.height);
+                    }
+                    WindowEvent::MouseWheel { delta, .. } if orthographic => ortho.zoom_at(
+                        cursor_position,
+                        mvp::CameraController::wheel_zoom_factor(delta),
+                    ),
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        camera_controler.process_wheel(delta, dt)
+                    }
+                    WindowEvent::CursorMoved { position, .. } => {
+                        let position = glam::vec2(position.x as f32, position.y as f32);
+                        if dragging && orthographic {
+                            ortho.pan(position - cursor_position);
+                        }
+                        cursor_position = position;
+                    }
+
+                    WindowEvent::RedrawRequested => {
+                        let view_proj = if orthographic {
+                            camera_controler.update_ortho_camera(&mut ortho, dt);
+                            ortho.calc_matrix()
+                        } else {
+                            camera_controler.update_camera(&mut camera, dt);
+                            projection.calc_matrix() * camera.calc_matrix()
+                        };
+
+                        if let Some(alive) = painting {
+                            let window_size = window.inner_size();
+                            let cell = if orthographic {
+                                cell_in_map(ortho.screen_to_cell(cursor_position), map_size)
+                            } else {
+                                mvp::unproject(
+                                    view_proj,
+                                    cursor_position,
+                                    (window_size.width, window_size.height),
+                                )
+                                .and_then(|point| cell_at(point, map_size))
+                            };
+                            // 光标没有移到别的格子时什么都不做，每次绘制都算一次编辑
+                            if let Some(cell) = cell.filter(|&cell| Some(cell) != last_painted) {
+                                let cells = line_cells(last_painted.unwrap_or(cell), cell);
+                                if let Err(e) = state.sim.set_cells(&cells, alive) {
+                                    eprintln!("绘制失败：{e}");
+                                }
+                            }
+                            last_painted = cell;
+                        }
+
+                        render.update_camera_uniform(&state.sim, view_proj);
+                        render.render(&state);
+
+                        // 标题中显示代数、活细胞的数量和速度，统计结果会晚几帧读回
+                        if let Some(&sample) = state.sim.poll_stats().latest() {
+                            let status = if running || update {
+                                format!("{} 代/秒", speed.generations_per_second())
+                            } else {
+                                "暂停".to_string()
+                            };
+                            let period = match state.sim.period() {
+                                Some(period) if period.period == 1 => " - 静物".to_string(),
+                                Some(period) => format!(" - 周期 {}", period.period),
+                                None => String::new(),
+                            };
+                            // 正交模式下显示一格占几个像素，或者一个像素覆盖几格
+                            let round = |value: f32| (value * 1000.0).round() / 1000.0;
+                            let zoom = match ortho.cells_per_pixel() {
+                                _ if !orthographic => String::new(),
+                                cells if cells >= 1.0 => format!(" - 每像素 {} 格", round(cells)),
+                                cells => format!(" - 每格 {} 像素", round(1.0 / cells)),
+                            };
+                            let new_title = format!(
+                                "生命游戏 wgpu - 第 {} 代 - {} 个细胞 - {status}{period}{zoom}",
+                                sample.generation, sample.population
+                            );
+                            if new_title != title {
+                                window.set_title(&new_title);
+                                title = new_title;
+                            }
+                        }
+
+                        // 稳定下来时报告周期，可以自动暂停
+                        let period = state.sim.period();
+                        if period != reported_period {
+                            reported_period = period;
+                            if let Some(period) = period {
+                                println!(
+                                    "第 {} 代稳定了，周期为 {}",
+                                    period.generation, period.period
+                                );
+                                if options.auto_pause {
+                                    running = false;
+                                    update = false;
+                                    running_to = None;
+                                }
+                            }
+                        }
+
+                        // 演化的速度和帧率无关，落后时把多代放在一次提交中
+                        let now = Instant::now();
+                        let elapsed = now - last_update;
+                        last_update = now;
+                        if running || update {
+                            let generations = speed.advance(elapsed);
+                            if generations > 0 {
+                                state.sim.step(generations);
+                            }
+                        }
+                        // 分成多帧演化到目标，窗口不会卡住
+                        if let Some(target) = running_to {
+                            let generation = state.sim.generation();
+                            state
+                                .sim
+                                .run_until(target.min(generation + RUN_TO_TARGET_PER_FRAME));
+                            if state.sim.generation() >= target {
+                                println!("已演化到第 {} 代", state.sim.generation());
+                                running_to = None;
+                            }
+                        }
+                        // 录制经过的代数，每帧最多截一帧
+                        let size = (state.config.width, state.config.height);
+                        if let Some(Err(e)) = recording
+                            .as_mut()
+                            .map(|active| active.capture(&state.sim, &render, size))
+                        {
+                            eprintln!("录制失败：{e}");
+                            stop_recording(&mut recording);
+                        }
+                        window.request_redraw();
                     }
-                    window.request_redraw();
+                    _ => (),
                 }
-                _ => (),
-            },
+            }
+            // 退出之前写完录制的文件
+            Event::LoopExiting => stop_recording(&mut recording),
             _ => (),
         }
     })?)
 }
 
+/// 准备初始地图：快照、图案文件、图片，或者默认的一万个滑翔机
+fn initialize(
+    sim: &mut Simulator,
+    options: &Options,
+    pattern: Option<&pattern::Pattern>,
+    snapshot: Option<&Snapshot>,
+) -> Result<()> {
+    let map_size = sim.map_size();
+    if let Some(snapshot) = snapshot {
+        sim.resume(snapshot)?;
+    } else if let Some(pattern) = pattern {
+        let offset = options
+            .offset
+            .unwrap_or_else(|| pattern.centered_offset(map_size));
+        sim.write_cells(offset, pattern)?;
+    } else if let Some(path) = &options.image {
+        // 图片代替默认的地图，从左上角开始
+        let image = pattern::Pattern::from_png(
+            std::fs::File::open(path)?,
+            options.threshold.unwrap_or(pattern::DEFAULT_THRESHOLD),
+        )?;
+        sim.write_cells((0, 0), &image)?;
+    } else {
+        // 默认的地图，只覆盖左上角放滑翔机的区域，地图很大时不用准备整张地图
+        let mut default_map = pattern::Pattern::new(map_size.0.min(1000), map_size.1.min(1000));
+
+        // 用来快速配置地图的宏
+        macro_rules! lightup {
+            ($x :expr, $y: expr) => {
+                if $x < default_map.width && $y < default_map.height {
+                    default_map.set($x, $y, true);
+                }
+            };
+        }
+
+        // 没有指定图案时，创建10,000个滑翔机
+        for x in 0..100 {
+            for y in 0..100 {
+                let x = x * 10;
+                let y = y * 10;
+                lightup!(x + 3, y + 2);
+                lightup!(x + 4, y + 3);
+                lightup!(x + 2, y + 4);
+                lightup!(x + 3, y + 4);
+                lightup!(x + 4, y + 4);
+            }
+        }
+
+        // 初始化成“初始地图”
+        sim.write_cells((0, 0), &default_map)?;
+    }
+    Ok(())
+}
+
+/// 地图所在的正方形上的一点对应的格子
+///
+/// 正方形的左上角是 `(-1, 1)`，右下角是 `(1, -1)`，见 `render::map_quad`
+fn cell_at(point: glam::Vec2, map_size: (u32, u32)) -> Option<(u32, u32)> {
+    let u = (point.x + 1.0) / 2.0;
+    let v = (1.0 - point.y) / 2.0;
+    cell_in_map(
+        glam::vec2(u * map_size.0 as f32, v * map_size.1 as f32),
+        map_size,
+    )
+}
+
+/// 以格为单位的位置所在的格子，在地图外时为 `None`
+fn cell_in_map(position: glam::Vec2, map_size: (u32, u32)) -> Option<(u32, u32)> {
+    if !(0.0..map_size.0 as f32).contains(&position.x)
+        || !(0.0..map_size.1 as f32).contains(&position.y)
+    {
+        return None;
+    }
+    Some((position.x as u32, position.y as u32))
+}
+
+/// 两个格子之间的线段经过的所有格子（Bresenham 算法），拖动鼠标时用来补上中间的格子
+fn line_cells(from: (u32, u32), to: (u32, u32)) -> Vec<(u32, u32)> {
+    let (mut x, mut y) = (from.0 as i64, from.1 as i64);
+    let (x1, y1) = (to.0 as i64, to.1 as i64);
+    let dx = (x1 - x).abs();
+    let dy = -(y1 - y).abs();
+    let (sx, sy) = ((x1 - x).signum(), (y1 - y).signum());
+    let mut error = dx + dy;
+
+    let mut cells = vec![];
+    loop {
+        cells.push((x as u32, y as u32));
+        if x == x1 && y == y1 {
+            return cells;
+        }
+        let e2 = 2 * error;
+        if e2 >= dy {
+            error += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            error += dx;
+            y += sy;
+        }
+    }
+}
+
+/// 默认每秒演化的代数
+const DEFAULT_SPEED: f64 = 60.0;
+
+/// 按 J 时默认前进的代数
+const DEFAULT_JUMP: u64 = 100;
+
+/// 默认每隔多少代保存一个倒退用的关键帧
+const DEFAULT_KEYFRAME_INTERVAL: u64 = 100;
+
+/// 默认最多保存的关键帧数
+const DEFAULT_KEYFRAMES: usize = 16;
+
+/// 热度图默认的窗口
+const DEFAULT_HEAT_WINDOW: u32 = 16;
+
+/// 判断周期时记住的代数，也是能找到的最大周期
+const PERIOD_TABLE_SIZE: usize = 4096;
+
+/// 不打开窗口，搜索 `soups` 个汤，最后打印普查表
+fn census(options: &Options, soups: u64) -> Result<()> {
+    let sim = pollster::block_on(Simulator::new(
+        options.size.unwrap_or((256, 256)),
+        options.rule.unwrap_or_default(),
+        // 环面上的飞船不会撞到边界
+        options.topology.unwrap_or(topology::Topology::Torus),
+        options.storage,
+    ))?;
+    let mut search = SoupSearch::new(
+        sim,
+        options.soup_size.unwrap_or((16, 16)),
+        options.density.unwrap_or(0.5),
+    )?;
+    let seed = options.seed.unwrap_or(0);
+    let end = seed
+        .checked_add(soups)
+        .ok_or_else(|| format!("从种子 {seed} 开始搜索 {soups} 个汤，种子超出了范围"))?;
+    for (index, seed) in (seed..end).enumerate() {
+        if search.run(seed)?.is_none() {
+            println!("种子为 {seed} 的汤没有稳定下来");
+        }
+        if (index + 1) % 100 == 0 {
+            println!("已搜索 {} 个汤", index + 1);
+        }
+    }
+    print!("{}", search.census);
+    Ok(())
+}
+
+/// 默认的快照文件
+const DEFAULT_SNAPSHOT: &str = "life.snapshot";
+
+/// 录制时默认每帧显示的时间
+const DEFAULT_RECORD_DELAY: Duration = Duration::from_millis(100);
+
+/// 不打开窗口录制时默认的画面大小
+const DEFAULT_RECORD_SIZE: (u32, u32) = (720, 720);
+
+/// 录制的来源
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Source {
+    /// 渲染出来的画面，和窗口中看到的一样
+    #[default]
+    Frame,
+    /// 地图的一块区域，一个像素一个细胞
+    Board,
+}
+
+/// 正在进行的录制，每隔 `every` 代截一帧
+struct Recording {
+    recorder: Recorder,
+    source: Source,
+    /// 录制地图时的区域，左上角和大小
+    region: ((u32, u32), (u32, u32)),
+    every: u64,
+    /// 上一帧的代数
+    last: Option<u64>,
+}
+
+impl Recording {
+    /// 按 `path` 的扩展名选择格式，开始录制
+    fn start(path: PathBuf, options: &Options, map_size: (u32, u32)) -> Result<Self> {
+        let format = recording::Format::from_path(&path);
+        let recorder = Recorder::new(
+            path,
+            format,
+            options.record_delay.unwrap_or(DEFAULT_RECORD_DELAY),
+        )?;
+        println!("开始录制到 {}", recorder.path().display());
+        Ok(Self {
+            recorder,
+            source: options.record_source,
+            region: options.record_region.unwrap_or(((0, 0), map_size)),
+            every: options.record_every.unwrap_or(1),
+            last: None,
+        })
+    }
+
+    /// 第一次，或者从上一帧起经过了 `every` 的整数倍时截一帧，`size` 是渲染的画面的大小
+    fn capture(
+        &mut self,
+        sim: &Simulator,
+        render: &render::Render,
+        size: (u32, u32),
+    ) -> Result<()> {
+        let generation = sim.generation();
+        if self
+            .last
+            .is_some_and(|last| last / self.every == generation / self.every)
+        {
+            return Ok(());
+        }
+        let (size, rgba) = match self.source {
+            Source::Frame => (size, render.capture(sim, size)?),
+            Source::Board => {
+                let (origin, size) = self.region;
+                (size, recording::board_frame(sim, origin, size)?)
+            }
+        };
+        self.recorder.push(size, &rgba)?;
+        self.last = Some(generation);
+        Ok(())
+    }
+
+    /// 写完文件
+    fn finish(self) -> Result<()> {
+        let path = self.recorder.path().display().to_string();
+        let frames = self.recorder.finish()?;
+        println!("已录制 {frames} 帧到 {path}");
+        Ok(())
+    }
+}
+
+/// 停止正在进行的录制
+fn stop_recording(recording: &mut Option<Recording>) {
+    if let Some(Err(e)) = recording.take().map(Recording::finish) {
+        eprintln!("录制失败：{e}");
+    }
+}
+
+/// 按 R 开始录制时的文件名，格式和 `--record` 的一样，默认是 GIF
+fn recording_path(record: Option<&PathBuf>, generation: u64) -> PathBuf {
+    let extension = match record.map(recording::Format::from_path) {
+        Some(recording::Format::Apng) => ".png",
+        Some(recording::Format::Sequence) => "",
+        _ => ".gif",
+    };
+    format!("recording_{generation}{extension}").into()
+}
+
+/// 不打开窗口，演化 `generations` 代并录制，`view` 是快照中的摄像机
+fn headless(
+    options: &Options,
+    mut sim: Simulator,
+    generations: u64,
+    view: Option<&View>,
+) -> Result<()> {
+    let path = options
+        .record
+        .clone()
+        .ok_or("`--headless` 需要用 `--record` 指定录制到的文件")?;
+    let map_size = sim.map_size();
+    let size = options.record_size.unwrap_or(DEFAULT_RECORD_SIZE);
+
+    // 摄像机和刚打开窗口时一样，从快照继续时用快照中的
+    let mut ortho = mvp::OrthoCamera::new(map_size, size);
+    let mut camera = mvp::Camera::new([0.0, 0.0, 1.0], 1.0, 8.0);
+    let mut camera_controler = mvp::CameraController::new();
+    let orthographic = match view {
+        Some(view) => mvp::restore_view(view, &mut ortho, &mut camera, &mut camera_controler),
+        None => !options.perspective,
+    };
+    camera_controler.update_camera(&mut camera, Duration::ZERO);
+    let view_proj = if orthographic {
+        ortho.calc_matrix()
+    } else {
+        mvp::Projection::new(size.0, size.1, 30., 0.1, 100.0).calc_matrix() * camera.calc_matrix()
+    };
+
+    if let Err(error) = sim.enable_pyramid() {
+        eprintln!("缩小时可能会漏掉细胞：{error}");
+    }
+    let render = render::Render::new(
+        &sim,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+        view_proj,
+        &options.color_map.unwrap_or_default(),
+        &Gradient::default(),
+        &options.grid.unwrap_or_default(),
+    );
+    render.set_density(&sim, options.zoomed_out.unwrap_or_default());
+
+    // 每次演化到 `every` 的下一个整数倍
+    let mut recording = Recording::start(path, options, map_size)?;
+    recording.capture(&sim, &render, size)?;
+    let target = sim.generation() + generations;
+    while sim.generation() < target {
+        let every = recording.every;
+        let generation = sim.generation();
+        sim.run_until(target.min(generation + every - generation % every));
+        recording.capture(&sim, &render, size)?;
+    }
+    recording.finish()
+}
+
+/// 按 G 演化到目标代数时，每帧最多演化的代数
+const RUN_TO_TARGET_PER_FRAME: u64 = 100;
+
+/// 命令行参数
+#[derive(Default)]
+struct Options {
+    /// `--rule <规则>`，例如 `B36/S23` 或者 `23/36`
+    rule: Option<rule::Rule>,
+    /// `--topology <dead|torus|klein|cross>`
+    topology: Option<topology::Topology>,
+    /// `--pattern <文件>`，初始图案
+    pattern: Option<std::path::PathBuf>,
+    /// `--offset <x>,<y>`，初始图案左上角的位置，默认放在正中间
+    offset: Option<(u32, u32)>,
+    /// `--save-format <rle|cells|mc>`，按 P 保存时的格式
+    save_format: Option<&'static str>,
+    /// `--image <文件>`，用黑白 PNG 图片代替默认的地图，一个像素一个细胞
+    image: Option<std::path::PathBuf>,
+    /// `--threshold <0~255>`，读图片时比这个亮度暗的像素是活细胞
+    threshold: Option<u8>,
+    /// `--image-channels`，按 I 导出的图片包含年龄和尾迹通道
+    image_channels: bool,
+    /// `--snapshot <文件>`，按 F5 保存、F9 恢复的快照文件
+    snapshot: Option<std::path::PathBuf>,
+    /// `--resume`，启动时从快照继续
+    resume: bool,
+    /// `--record <文件>`，一开始就录制，`.gif` 是 GIF，`.png` 是 APNG，其他的是图片序列的目录
+    record: Option<PathBuf>,
+    /// `--record-every <代数>`，每隔多少代截一帧
+    record_every: Option<u64>,
+    /// `--record-source <frame|board>`，录制渲染出来的画面还是地图
+    record_source: Source,
+    /// `--record-region <x>,<y>,<宽>x<高>`，录制地图时的区域，默认是整张地图
+    record_region: Option<((u32, u32), (u32, u32))>,
+    /// `--record-size <宽>x<高>`，不打开窗口录制画面时的大小
+    record_size: Option<(u32, u32)>,
+    /// `--record-delay <毫秒>`，动画中每帧显示的时间
+    record_delay: Option<Duration>,
+    /// `--headless <代数>`，不打开窗口，演化这么多代并录制
+    headless: Option<u64>,
+    /// `--size <宽>x<高>`，地图大小
+    size: Option<(u32, u32)>,
+    /// `--packed`，每 32 个细胞打包成一个 `u32`，用于很大的地图
+    storage: Storage,
+    /// `--jump <代数>`，按 J 时前进的代数
+    jump: Option<u64>,
+    /// `--target <代数>`，按 G 时一直演化到的代数
+    target: Option<u64>,
+    /// `--speed <代/秒>`，运行时每秒演化的代数
+    speed: Option<f64>,
+    /// `--keyframe-interval <代数>`，每隔多少代保存一个倒退用的关键帧
+    keyframe_interval: Option<u64>,
+    /// `--keyframes <个数>`，最多保存的关键帧数
+    keyframes: Option<usize>,
+    /// `--auto-pause`，地图稳定下来（找到周期）时自动暂停
+    auto_pause: bool,
+    /// `--colors <age|mono>`，按年龄着色或者只有黑白两色
+    color_map: Option<ColorMap>,
+    /// `--grid <off|on|major>`，放大时是否在格子之间画线，`major` 另外每隔 10 格画一条主线
+    grid: Option<Grid>,
+    /// `--input-map <文件>`，按键设置，见 `input.rs`
+    input_map: Option<std::path::PathBuf>,
+    /// `--print-input-map`，打印按键设置（应用 `--input-map` 之后）然后退出
+    print_input_map: bool,
+    /// `--camera <ortho|perspective>`，正交（默认）还是透视的摄像机
+    perspective: bool,
+    /// `--zoomed-out <max|average>`，缩小时每个像素显示一块里有没有活细胞，还是活细胞的密度
+    zoomed_out: Option<Density>,
+    /// `--heat-window <代数>`，热度图数变化次数的窗口，最多 32 代
+    heat_window: Option<u32>,
+    /// `--heat-gradient <颜色,颜色,...>`，热度图的渐变色
+    heat_gradient: Option<Gradient>,
+    /// `--heat-opacity <0~1>`，热度图的不透明度
+    heat_opacity: Option<f32>,
+    /// `--census <个数>`，不打开窗口，搜索这么多个随机的汤并统计其中的物体
+    census: Option<u64>,
+    /// `--seed <种子>`，第一个汤的种子，之后的汤依次加一
+    seed: Option<u64>,
+    /// `--soup-size <宽>x<高>`，汤的大小
+    soup_size: Option<(u32, u32)>,
+    /// `--density <0~1>`，汤中活细胞的比例
+    density: Option<f64>,
+}
+
+impl Options {
+    fn parse() -> Result<Self> {
+        let mut options = Self::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("参数 `{arg}` 缺少值"));
+            match arg.as_str() {
+                "--packed" => options.storage = Storage::Packed,
+                "--auto-pause" => options.auto_pause = true,
+                "--image-channels" => options.image_channels = true,
+                "--resume" => options.resume = true,
+                "--snapshot" => options.snapshot = Some(value()?.into()),
+                "--record" => options.record = Some(value()?.into()),
+                "--record-every" => {
+                    options.record_every = match value()?.parse()? {
+                        0 => return Err("`--record-every` 至少是 1".into()),
+                        every => Some(every),
+                    }
+                }
+                "--record-source" => {
+                    options.record_source = match value()?.as_str() {
+                        "frame" => Source::Frame,
+                        "board" => Source::Board,
+                        source => return Err(format!("未知的录制来源 `{source}`").into()),
+                    }
+                }
+                "--record-region" => {
+                    let value = value()?;
+                    let mut items = value.splitn(3, ',');
+                    let (Some(x), Some(y), Some(size)) = (items.next(), items.next(), items.next())
+                    else {
+                        return Err(format!("区域 `{value}` 应写成 `x,y,宽x高` 的形式").into());
+                    };
+                    options.record_region =
+                        Some(((x.trim().parse()?, y.trim().parse()?), parse_size(size)?));
+                }
+                "--record-size" => options.record_size = Some(parse_size(&value()?)?),
+                "--record-delay" => {
+                    options.record_delay = Some(Duration::from_millis(value()?.parse()?))
+                }
+                "--headless" => options.headless = Some(value()?.parse()?),
+                "--print-input-map" => options.print_input_map = true,
+                "--input-map" => options.input_map = Some(value()?.into()),
+                "--jump" => options.jump = Some(value()?.parse()?),
+                "--target" => options.target = Some(value()?.parse()?),
+                "--speed" => {
+                    let value = value()?;
+                    options.speed = match value.parse::<f64>()? {
+                        speed if speed.is_finite() => Some(speed),
+                        _ => return Err(format!("速度 `{value}` 应该是有限的数").into()),
+                    }
+                }
+                "--keyframe-interval" => options.keyframe_interval = Some(value()?.parse()?),
+                "--keyframes" => options.keyframes = Some(value()?.parse()?),
+                "--size" => options.size = Some(parse_size(&value()?)?),
+                "--colors" => {
+                    options.color_map = Some(match value()?.as_str() {
+                        "age" => ColorMap::AGE,
+                        "mono" => ColorMap::MONOCHROME,
+                        colors => return Err(format!("未知的颜色 `{colors}`").into()),
+                    })
+                }
+                "--grid" => {
+                    options.grid = Some(match value()?.as_str() {
+                        "off" => Grid::OFF,
+                        "on" => Grid::LINES,
+                        "major" => Grid::MAJOR_LINES,
+                        grid => return Err(format!("未知的网格线 `{grid}`").into()),
+                    })
+                }
+                "--camera" => {
+                    options.perspective = match value()?.as_str() {
+                        "ortho" => false,
+                        "perspective" => true,
+                        camera => return Err(format!("未知的摄像机 `{camera}`").into()),
+                    }
+                }
+                "--zoomed-out" => options.zoomed_out = Some(value()?.parse()?),
+                "--heat-window" => options.heat_window = Some(value()?.parse()?),
+                "--heat-gradient" => options.heat_gradient = Some(value()?.parse()?),
+                "--heat-opacity" => options.heat_opacity = Some(value()?.parse()?),
+                "--census" => options.census = Some(value()?.parse()?),
+                "--seed" => options.seed = Some(value()?.parse()?),
+                "--soup-size" => options.soup_size = Some(parse_size(&value()?)?),
+                "--density" => {
+                    let value = value()?;
+                    options.density = match value.parse::<f64>()? {
+                        density if (0.0..=1.0).contains(&density) => Some(density),
+                        _ => return Err(format!("密度 `{value}` 应该在 0 到 1 之间").into()),
+                    }
+                }
+                "--rule" => options.rule = Some(value()?.parse()?),
+                "--topology" => options.topology = Some(value()?.parse()?),
+                "--pattern" => options.pattern = Some(value()?.into()),
+                "--image" => options.image = Some(value()?.into()),
+                "--threshold" => options.threshold = Some(value()?.parse()?),
+                "--save-format" => {
+                    options.save_format = Some(match value()?.as_str() {
+                        "rle" => "rle",
+                        "cells" => "cells",
+                        "mc" => "mc",
+                        format => return Err(format!("未知的保存格式 `{format}`").into()),
+                    })
+                }
+                "--offset" => {
+                    let value = value()?;
+                    let (x, y) = value
+                        .split_once(',')
+                        .ok_or_else(|| format!("偏移 `{value}` 应写成 `x,y` 的形式"))?;
+                    options.offset = Some((x.trim().parse()?, y.trim().parse()?));
+                }
+                _ => return Err(format!("未知的参数 `{arg}`").into()),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// 解析 `宽x高` 形式的大小
+fn parse_size(value: &str) -> Result<(u32, u32)> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or_else(|| format!("大小 `{value}` 应写成 `宽x高` 的形式"))?;
+    Ok((width.trim().parse()?, height.trim().parse()?))
+}
+
 /// 储存图形部分的状态
 pub struct State {
     _instance: wgpu::Instance,
     _adapter: wgpu::Adapter,
     surface: wgpu::Surface,
     config: wgpu::SurfaceConfiguration,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
 
-    /// 本质上是创建两个纹理 交替读写来进行更新
-    /// 通过来回取反这个量来做到翻转
-    cycle_render_binding_group: bool,
+    /// 模拟器，和展示平面共用同一个设备
+    sim: Simulator,
 }
 
 impl State {
-    async fn new(window: &winit::window::Window) -> Result<Self> {
+    async fn new(
+        window: &winit::window::Window,
+        map_size: (u32, u32),
+        rule: rule::Rule,
+        topology: topology::Topology,
+        storage: Storage,
+    ) -> Result<Self> {
         // 创建实例，展示平面，适配器，设备，命令队列
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends: wgpu::Backends::all(),
@@ -211,28 +1104,12 @@ impl State {
 
         let surface = unsafe { instance.create_surface(window)? };
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptionsBase {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .ok_or("没有合适的适配器")?;
+        let adapter = life_game_wgpu::request_adapter(&instance, Some(&surface)).await?;
 
         // 打印一些调试信息
         println!("{:?}", adapter.get_info());
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                },
-                None,
-            )
-            .await?;
+        let (device, queue) = life_game_wgpu::request_device(&adapter).await?;
 
         let swapchain_capabilities = surface.get_capabilities(&adapter);
 
@@ -248,14 +1125,18 @@ impl State {
 
         surface.configure(&device, &config);
 
+        let sim =
+            Simulator::from_device(&adapter, device, queue, map_size, rule, topology, storage)?;
+        if sim.is_cpu_fallback() {
+            println!("设备不支持计算管线，使用CPU演化");
+        }
+
         Ok(Self {
             _instance: instance,
             surface,
             config,
             _adapter: adapter,
-            device,
-            queue,
-            cycle_render_binding_group: false,
+            sim,
         })
     }
 }
@@ -1,8 +1,10 @@
 use std::time::Duration;
 
 use glam::Vec3Swizzles;
-use winit::event::{ElementState, MouseScrollDelta};
-use winit::keyboard::KeyCode;
+use life_game_wgpu::snapshot::View;
+use winit::event::MouseScrollDelta;
+
+use crate::input::Action;
 
 pub struct Projection {
     pub aspect: f32,
@@ -30,6 +32,138 @@ impl Projection {
     }
 }
 
+/// 屏幕上的一点（像素，左上角为原点）对应的 z = 0 平面上的点
+///
+/// `view_proj` 是投影矩阵乘以视图矩阵，射线和平面平行时返回 `None`
+pub fn unproject(
+    view_proj: glam::Mat4,
+    position: glam::Vec2,
+    screen_size: (u32, u32),
+) -> Option<glam::Vec2> {
+    let ndc = glam::vec2(
+        position.x / screen_size.0 as f32 * 2.0 - 1.0,
+        1.0 - position.y / screen_size.1 as f32 * 2.0,
+    );
+    let inverse = view_proj.inverse();
+    let near = inverse.project_point3(ndc.extend(0.0));
+    let far = inverse.project_point3(ndc.extend(1.0));
+    if (far.z - near.z).abs() < f32::EPSILON {
+        return None;
+    }
+    let t = -near.z / (far.z - near.z);
+    Some((near + (far - near) * t).xy())
+}
+
+/// 正交摄像机：地图是平的，用不着透视
+///
+/// `center` 是屏幕中心对着的地图上的位置，以格为单位，y 向下；
+/// `cells_per_pixel` 是每个像素覆盖的格数，小于 1 时一格占多个像素
+#[derive(Debug, Clone, Copy)]
+pub struct OrthoCamera {
+    pub center: glam::Vec2,
+    cells_per_pixel: f32,
+    map_size: (u32, u32),
+    screen_size: (u32, u32),
+}
+
+impl OrthoCamera {
+    /// 最多放大到一格 64 像素
+    pub const MIN_CELLS_PER_PIXEL: f32 = 1.0 / 64.0;
+    /// 按住方向键时每秒移动的像素数
+    const PAN_PIXELS_PER_SECOND: f32 = 600.0;
+
+    /// 整张地图正好放进屏幕
+    pub fn new(map_size: (u32, u32), screen_size: (u32, u32)) -> Self {
+        let mut camera = Self {
+            center: glam::Vec2::ZERO,
+            cells_per_pixel: 1.0,
+            map_size,
+            screen_size,
+        };
+        camera.fit();
+        camera
+    }
+
+    pub fn cells_per_pixel(&self) -> f32 {
+        self.cells_per_pixel
+    }
+
+    /// 最多缩小到整张地图只占屏幕的四分之一
+    fn max_cells_per_pixel(&self) -> f32 {
+        let fit = (self.map_size.0 as f32 / self.screen_size.0 as f32)
+            .max(self.map_size.1 as f32 / self.screen_size.1 as f32);
+        fit.max(1.0) * 4.0
+    }
+
+    /// 设置每个像素覆盖的格数，离 2 的整数次幂很近时取整，避免滚轮累积误差
+    pub fn set_cells_per_pixel(&mut self, cells_per_pixel: f32) {
+        let exponent = cells_per_pixel.log2();
+        let cells_per_pixel = if (exponent - exponent.round()).abs() < 1e-3 {
+            exponent.round().exp2()
+        } else {
+            cells_per_pixel
+        };
+        self.cells_per_pixel =
+            cells_per_pixel.clamp(Self::MIN_CELLS_PER_PIXEL, self.max_cells_per_pixel());
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.screen_size = (width, height);
+    }
+
+    /// 屏幕上的一点（像素，左上角为原点）对应的地图上的位置，以格为单位
+    pub fn screen_to_cell(&self, position: glam::Vec2) -> glam::Vec2 {
+        let screen = glam::vec2(self.screen_size.0 as f32, self.screen_size.1 as f32);
+        self.center + (position - screen / 2.0) * self.cells_per_pixel
+    }
+
+    /// 缩放 `factor` 倍（大于 1 时缩小），保持 `position` 处的格子不动
+    pub fn zoom_at(&mut self, position: glam::Vec2, factor: f32) {
+        let anchor = self.screen_to_cell(position);
+        self.set_cells_per_pixel(self.cells_per_pixel * factor);
+        let screen = glam::vec2(self.screen_size.0 as f32, self.screen_size.1 as f32);
+        self.center = anchor - (position - screen / 2.0) * self.cells_per_pixel;
+    }
+
+    /// 拖动：地图跟着鼠标移动 `delta` 像素
+    pub fn pan(&mut self, delta: glam::Vec2) {
+        self.center -= delta * self.cells_per_pixel;
+    }
+
+    /// 整张地图正好放进屏幕，居中
+    pub fn fit(&mut self) {
+        self.center = glam::vec2(self.map_size.0 as f32, self.map_size.1 as f32) / 2.0;
+        let fit = (self.map_size.0 as f32 / self.screen_size.0 as f32)
+            .max(self.map_size.1 as f32 / self.screen_size.1 as f32);
+        self.set_cells_per_pixel(fit);
+    }
+
+    /// 一格正好一个像素，中心不动
+    pub fn one_to_one(&mut self) {
+        self.set_cells_per_pixel(1.0);
+    }
+
+    /// 地图所在的正方形（左上角 `(-1, 1)`，右下角 `(1, -1)`）到裁剪空间的矩阵
+    pub fn calc_matrix(&self) -> glam::Mat4 {
+        let (map_width, map_height) = (self.map_size.0 as f32, self.map_size.1 as f32);
+        // 正方形上的一个单位是半张地图，屏幕上的一个单位是半个屏幕
+        let scale = glam::vec3(
+            map_width / (self.screen_size.0 as f32 * self.cells_per_pixel),
+            map_height / (self.screen_size.1 as f32 * self.cells_per_pixel),
+            1.0,
+        );
+        let center = glam::vec3(
+            self.center.x / map_width * 2.0 - 1.0,
+            1.0 - self.center.y / map_height * 2.0,
+            0.0,
+        );
+        // z = 0 的平面放在深度范围的中间
+        glam::Mat4::from_translation(glam::vec3(0.0, 0.0, 0.5))
+            * glam::Mat4::from_scale(scale)
+            * glam::Mat4::from_translation(-center)
+    }
+}
+
 pub struct Camera {
     pub position: glam::Vec3,
     speed: f32,       // 速度
@@ -54,10 +188,10 @@ impl Camera {
 
 #[derive(Debug)]
 pub struct CameraController {
-    amount_left: bool,     // a
-    amount_right: bool,    // d
-    amount_forward: bool,  // w
-    amount_backward: bool, // s
+    amount_left: bool,     // pan_left
+    amount_right: bool,    // pan_right
+    amount_forward: bool,  // pan_up
+    amount_backward: bool, // pan_down
     scale: f32,
 }
 
@@ -66,26 +200,14 @@ impl CameraController {
         Default::default()
     }
 
-    pub fn process_keyboard(&mut self, key: KeyCode, state: ElementState) -> bool {
-        let state = state == ElementState::Pressed;
-        match key {
-            KeyCode::KeyW | KeyCode::ArrowUp => {
-                self.amount_forward = state;
-                true
-            }
-            KeyCode::KeyS | KeyCode::ArrowDown => {
-                self.amount_backward = state;
-                true
-            }
-            KeyCode::KeyA | KeyCode::ArrowLeft => {
-                self.amount_left = state;
-                true
-            }
-            KeyCode::KeyD | KeyCode::ArrowRight => {
-                self.amount_right = state;
-                true
-            }
-            _ => false,
+    /// 处理平移的动作，按下时开始移动，松开时停下，忽略其他动作
+    pub fn process_action(&mut self, action: Action, pressed: bool) {
+        match action {
+            Action::PanUp => self.amount_forward = pressed,
+            Action::PanDown => self.amount_backward = pressed,
+            Action::PanLeft => self.amount_left = pressed,
+            Action::PanRight => self.amount_right = pressed,
+            _ => {}
         }
     }
 
@@ -97,6 +219,38 @@ impl CameraController {
         self.scale -= dt.as_secs_f32() * 1000.0 * delta;
     }
 
+    /// 正交模式下滚轮的缩放倍数，向上滚放大，每滚一格缩放 2 的四分之一次方
+    pub fn wheel_zoom_factor(delta: MouseScrollDelta) -> f32 {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            // 触控板上按 30 像素一格算
+            MouseScrollDelta::PixelDelta(delta) => delta.y as f32 / 30.0,
+        };
+        (-lines / 4.0).exp2()
+    }
+
+    /// 正交模式下按住方向键时平移，速度按屏幕上的像素计算，和缩放无关
+    pub fn update_ortho_camera(&self, camera: &mut OrthoCamera, dt: Duration) {
+        // 地图坐标的 y 向下
+        let mut direction = glam::Vec2::ZERO;
+        if self.amount_forward {
+            direction.y -= 1.0;
+        }
+        if self.amount_backward {
+            direction.y += 1.0;
+        }
+        if self.amount_left {
+            direction.x -= 1.0;
+        }
+        if self.amount_right {
+            direction.x += 1.0;
+        }
+        camera.center += direction
+            * OrthoCamera::PAN_PIXELS_PER_SECOND
+            * dt.as_secs_f32()
+            * camera.cells_per_pixel;
+    }
+
     pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
         let dt = dt.as_secs_f32();
 
@@ -135,3 +289,93 @@ impl Default for CameraController {
         }
     }
 }
+
+/// 快照中保存的摄像机状态
+pub fn save_view(
+    orthographic: bool,
+    ortho: &OrthoCamera,
+    camera: &Camera,
+    controller: &CameraController,
+) -> View {
+    View {
+        orthographic,
+        center: ortho.center.into(),
+        cells_per_pixel: ortho.cells_per_pixel,
+        position: camera.position.into(),
+        scale: controller.scale,
+    }
+}
+
+/// 恢复快照中的摄像机状态，返回是否是正交摄像机
+pub fn restore_view(
+    view: &View,
+    ortho: &mut OrthoCamera,
+    camera: &mut Camera,
+    controller: &mut CameraController,
+) -> bool {
+    ortho.center = view.center.into();
+    ortho.cells_per_pixel = view.cells_per_pixel;
+    camera.position = view.position.into();
+    controller.scale = view.scale;
+    view.orthographic
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: glam::Vec2, b: glam::Vec2) {
+        assert!((a - b).abs().max_element() < 1e-3, "{a} != {b}");
+    }
+
+    #[test]
+    fn fit_centers_the_map() {
+        // 地图比屏幕宽，按宽度放进屏幕
+        let camera = OrthoCamera::new((512, 128), (256, 256));
+        assert_eq!(camera.cells_per_pixel(), 2.0);
+        assert_close(camera.center, glam::vec2(256.0, 64.0));
+        assert_close(
+            camera.screen_to_cell(glam::Vec2::ZERO),
+            glam::vec2(0.0, -192.0),
+        );
+        assert_close(
+            camera.screen_to_cell(glam::vec2(256.0, 256.0)),
+            glam::vec2(512.0, 320.0),
+        );
+    }
+
+    #[test]
+    fn zoom_keeps_the_anchor_cell() {
+        let mut camera = OrthoCamera::new((256, 256), (800, 600));
+        let cursor = glam::vec2(123.0, 456.0);
+        for factor in [0.5, 0.8, 1.25, 3.0, 0.01, 1000.0] {
+            let anchor = camera.screen_to_cell(cursor);
+            camera.zoom_at(cursor, factor);
+            // 超出缩放范围被限制时，光标下的格子也不动
+            assert_close(camera.screen_to_cell(cursor), anchor);
+        }
+        assert_eq!(
+            camera.cells_per_pixel(),
+            camera.max_cells_per_pixel(),
+            "最后一次缩小超出了范围"
+        );
+    }
+
+    #[test]
+    fn cells_per_pixel_snaps_and_clamps() {
+        let mut camera = OrthoCamera::new((256, 256), (256, 256));
+        camera.set_cells_per_pixel(0.2501);
+        assert_eq!(camera.cells_per_pixel(), 0.25);
+        camera.set_cells_per_pixel(0.3);
+        assert_eq!(camera.cells_per_pixel(), 0.3);
+        camera.set_cells_per_pixel(1e-6);
+        assert_eq!(camera.cells_per_pixel(), OrthoCamera::MIN_CELLS_PER_PIXEL);
+        camera.set_cells_per_pixel(1e6);
+        assert_eq!(camera.cells_per_pixel(), 4.0);
+        // 一格一个像素时中心不动
+        camera.center = glam::vec2(10.0, 20.0);
+        camera.one_to_one();
+        assert_eq!(camera.cells_per_pixel(), 1.0);
+        assert_close(camera.center, glam::vec2(10.0, 20.0));
+    }
+}
@@ -0,0 +1,150 @@
+//! 按位储存地图的计算管线，见 `packed.wgsl`
+
+use wgpu::util::DeviceExt;
+
+use crate::{resources::PackedCells, rule::Rule, topology::Topology};
+
+pub struct PackedCompute {
+    compute_bind_group_layout: wgpu::BindGroupLayout,
+    compute_pipeline: wgpu::ComputePipeline,
+    map_size: (u32, u32),
+    map_size_uniform: wgpu::Buffer,
+    rule_uniform: wgpu::Buffer,
+}
+
+impl PackedCompute {
+    /// `map_size.0` 必须是 32 的倍数
+    pub fn new(
+        device: &wgpu::Device,
+        map_size: (u32, u32),
+        rule: Rule,
+        topology: Topology,
+    ) -> Self {
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let compute_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    storage_entry(0, true),
+                    storage_entry(1, false),
+                    uniform_entry(2),
+                    uniform_entry(3),
+                ],
+            });
+
+        let words_per_row = map_size.0 / PackedCells::CELLS_PER_WORD;
+        let map_size_uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[
+                map_size.0,
+                map_size.1,
+                topology.to_uniform(),
+                words_per_row,
+            ]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // 出生和存活的位掩码
+        let rule_uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&rule.to_uniform()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let compute_shader_module = device.create_shader_module(wgpu::include_wgsl!("packed.wgsl"));
+
+        let compute_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&compute_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(&layout),
+                module: &compute_shader_module,
+                entry_point: "cs_main",
+            })
+        };
+        Self {
+            compute_bind_group_layout,
+            compute_pipeline,
+            map_size,
+            map_size_uniform,
+            rule_uniform,
+        }
+    }
+
+    /// 在 `encoder` 中记录一次演化：读取 `read` 中的这一代，把下一代写入 `write`
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        read: &PackedCells,
+        write: &PackedCells,
+    ) {
+        self.dispatch(encoder, &self.bind_group(device, read, write));
+    }
+
+    /// 读取 `read` 中的这一代，把下一代写入 `write` 的绑定组
+    pub fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        read: &PackedCells,
+        write: &PackedCells,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.compute_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: read.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: write.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.map_size_uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.rule_uniform.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// 在 `encoder` 中记录一次演化，`bind_group` 来自 `bind_group`
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, bind_group: &wgpu::BindGroup) {
+        // 每个调用负责一个字
+        let words_per_row = self.map_size.0 / PackedCells::CELLS_PER_WORD;
+        let workgroup_count = (words_per_row.div_ceil(8), self.map_size.1.div_ceil(8), 1);
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+
+        cpass.set_pipeline(&self.compute_pipeline);
+        cpass.set_bind_group(0, bind_group, &[]);
+        cpass.dispatch_workgroups(workgroup_count.0, workgroup_count.1, workgroup_count.2)
+    }
+}
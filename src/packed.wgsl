@@ -0,0 +1,165 @@
+// 按位储存的地图：每行由连续的 u32 组成，第 x 列的细胞是第 x / 32 个字的第 x % 32 位
+// 每个调用计算一个字，用按位运算同时得出 32 个细胞的下一代
+
+@group(0) @binding(0)
+var<storage, read> last_map : array<u32>;
+
+@group(0) @binding(1)
+var<storage, read_write> this_map : array<u32>;
+
+struct MapSize {
+    map_size: vec2i,
+    // 0: 边界外是死细胞 1: 环面 2: 克莱因瓶 3: 射影平面
+    topology: u32,
+    // 每行的字数
+    words_per_row: u32,
+}
+
+const TOPOLOGY_DEAD: u32 = 0u;
+const TOPOLOGY_TORUS: u32 = 1u;
+const TOPOLOGY_KLEIN_BOTTLE: u32 = 2u;
+const TOPOLOGY_CROSS_SURFACE: u32 = 3u;
+
+@group(0) @binding(2)
+var<uniform> map_size : MapSize;
+
+// 第 n 位为 1 表示邻居数为 n 时出生/存活
+struct Rule {
+    birth: u32,
+    survival: u32,
+    _padding: vec2u,
+}
+
+@group(0) @binding(3)
+var<uniform> rule : Rule;
+
+struct ComputeInput {
+    @builtin(global_invocation_id) global_id: vec3<u32>
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(in: ComputeInput) {
+    let word = i32(in.global_id.x);
+    let y = i32(in.global_id.y);
+
+    // 工作组可能会超出地图的范围
+    if word >= i32(map_size.words_per_row) || y >= map_size.map_size.y {
+        return;
+    }
+
+    // 32 个细胞各自的邻居数，按位切开储存：sum.x 的每一位是对应细胞邻居数的最低位
+    var sum = vec4(0u);
+    var center = 0u;
+    let x = word * 32;
+    for (var dy = -1; dy <= 1; dy++) {
+        let row = row_word(word, y + dy);
+        // 左边和右边的邻居，移出字的那一位由相邻的细胞补上
+        let left = (row << 1u) | is_life(vec2(x - 1, y + dy));
+        let right = (row >> 1u) | (is_life(vec2(x + 32, y + dy)) << 31u);
+        sum = add(sum, left);
+        sum = add(sum, right);
+        if dy == 0 {
+            center = row;
+        } else if map_size.topology == TOPOLOGY_CROSS_SURFACE && (y + dy < 0 || y + dy >= map_size.map_size.y) {
+            // 射影平面上角的细胞越过上下边的邻居和越过左右边的是同一个，只算左右的那个
+            var corners = 0u;
+            if word == 0 {
+                corners |= 1u;
+            }
+            if word == i32(map_size.words_per_row) - 1 {
+                corners |= 1u << 31u;
+            }
+            sum = add(sum, row & ~corners);
+        } else {
+            sum = add(sum, row);
+        }
+    }
+
+    var next = 0u;
+    for (var n = 0u; n <= 8u; n++) {
+        // 邻居数恰好为 n 的细胞
+        let digits = (vec4(n) >> vec4(0u, 1u, 2u, 3u)) & vec4(1u);
+        let bits = select(~sum, sum, digits == vec4(1u));
+        let count_is_n = bits.x & bits.y & bits.z & bits.w;
+
+        let birth = select(0u, ~center, ((rule.birth >> n) & 1u) == 1u);
+        let survival = select(0u, center, ((rule.survival >> n) & 1u) == 1u);
+        next |= count_is_n & (birth | survival);
+    }
+
+    this_map[y * i32(map_size.words_per_row) + word] = next;
+}
+
+// 位切片的加法：把 a 的每一位加到 sum 中对应细胞的计数上
+fn add(sum: vec4u, a: u32) -> vec4u {
+    var s = sum;
+    var carry = a;
+    var next_carry = s.x & carry;
+    s.x ^= carry;
+    carry = next_carry;
+    next_carry = s.y & carry;
+    s.y ^= carry;
+    carry = next_carry;
+    next_carry = s.z & carry;
+    s.z ^= carry;
+    carry = next_carry;
+    // 邻居数最多是 8，最高位不会再进位
+    s.w |= carry;
+    return s;
+}
+
+fn word_at(word: i32, y: i32) -> u32 {
+    return last_map[y * i32(map_size.words_per_row) + word];
+}
+
+// 第 y 行中的第 word 个字，y 可以越过上下边界，和 `is_life` 的规则一致
+fn row_word(word: i32, y: i32) -> u32 {
+    let size = map_size.map_size;
+    let topology = map_size.topology;
+
+    if y >= 0 && y < size.y {
+        return word_at(word, y);
+    }
+    if topology == TOPOLOGY_DEAD {
+        return 0u;
+    }
+    let row = (y + size.y) % size.y;
+    if topology == TOPOLOGY_KLEIN_BOTTLE || topology == TOPOLOGY_CROSS_SURFACE {
+        // 左右翻转：宽度是 32 的倍数，第 x 列换到第 width - 1 - x 列相当于倒着取字，再把字里的位倒过来
+        return reverseBits(word_at(i32(map_size.words_per_row) - 1 - word, row));
+    }
+    return word_at(word, row);
+}
+
+fn is_life(location: vec2i) -> u32 {
+    let size = map_size.map_size;
+    let topology = map_size.topology;
+    var p = location;
+
+    let outside = (p < vec2(0)) | (p >= size);
+    if any(outside) {
+        if topology == TOPOLOGY_DEAD {
+            return 0u;
+        }
+        // 射影平面上斜着越过角的位置不在地图上
+        if topology == TOPOLOGY_CROSS_SURFACE && all(outside) {
+            return 0u;
+        }
+        // 从左右两边越界
+        if p.x < 0 || p.x >= size.x {
+            p.x = (p.x + size.x) % size.x;
+            if topology == TOPOLOGY_CROSS_SURFACE {
+                p.y = size.y - 1 - p.y;
+            }
+        }
+        // 从上下两边越界
+        if p.y < 0 || p.y >= size.y {
+            p.y = (p.y + size.y) % size.y;
+            if topology == TOPOLOGY_KLEIN_BOTTLE || topology == TOPOLOGY_CROSS_SURFACE {
+                p.x = size.x - 1 - p.x;
+            }
+        }
+    }
+
+    return (word_at(p.x / 32, p.y) >> u32(p.x % 32)) & 1u;
+}
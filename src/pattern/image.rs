@@ -0,0 +1,82 @@
+//! PNG 图片，一个像素一个细胞
+//!
+//! 读的时候按亮度区分，比阈值暗的像素是活细胞，透明的部分当作白色；
+//! 写的时候活细胞是黑色，死细胞是白色，所以导出的图片可以原样读回来
+
+use std::io::Read;
+
+use super::{Error, Pattern};
+
+/// 默认的亮度阈值
+pub const DEFAULT_THRESHOLD: u8 = 128;
+
+fn decoding(error: png::DecodingError) -> Error {
+    match error {
+        png::DecodingError::IoError(error) => Error::Io(error),
+        error => Error::Image(error.to_string()),
+    }
+}
+
+/// 把 `data` 编码成 PNG，`color_type` 是每个像素的格式
+pub(crate) fn encode_png(
+    (width, height): (u32, u32),
+    color_type: png::ColorType,
+    data: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set_color(color_type);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(data))
+        .map_err(|e| Error::Image(e.to_string()))?;
+    Ok(bytes)
+}
+
+impl Pattern {
+    /// 读取 PNG 图片，亮度（0 到 255）低于 `threshold` 的像素是活细胞
+    pub fn from_png(reader: impl Read, threshold: u8) -> Result<Self, Error> {
+        let mut decoder = png::Decoder::new(reader);
+        // 调色板、低位深和 16 位的图片都转换成每个通道 8 位
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(decoding)?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).map_err(decoding)?;
+
+        let channels = info.color_type.samples();
+        let mut pattern = Pattern::new(info.width, info.height);
+        for (cell, pixel) in pattern.cells.iter_mut().zip(
+            data.chunks_exact(channels)
+                .take((info.width * info.height) as usize),
+        ) {
+            let (luminance, alpha) = match *pixel {
+                [gray] => (gray as f32, 255.0),
+                [gray, alpha] => (gray as f32, alpha as f32),
+                [r, g, b] => (
+                    0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32,
+                    255.0,
+                ),
+                [r, g, b, alpha] => (
+                    0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32,
+                    alpha as f32,
+                ),
+                _ => unreachable!("转换之后每个像素最多 4 个通道"),
+            };
+            // 叠在白色背景上
+            let alpha = alpha / 255.0;
+            *cell = luminance * alpha + 255.0 * (1.0 - alpha) < threshold as f32;
+        }
+        Ok(pattern)
+    }
+
+    /// 转换成黑白的 PNG 图片，活细胞是黑色
+    pub fn to_png(&self) -> Result<Vec<u8>, Error> {
+        let data: Vec<u8> = self
+            .cells
+            .iter()
+            .map(|&alive| if alive { 0 } else { 255 })
+            .collect();
+        encode_png((self.width, self.height), png::ColorType::Grayscale, &data)
+    }
+}
@@ -0,0 +1,160 @@
+//! Life 1.05 和 Life 1.06 格式
+//!
+//! Life 1.05 由 `#P x y` 开头的块组成，块中的 `*` 是活细胞，`.` 是死细胞：
+//!
+//! ```text
+//! #Life 1.05
+//! #D 滑翔机
+//! #N
+//! #P -1 -1
+//! .*.
+//! ..*
+//! ***
+//! ```
+//!
+//! Life 1.06 每行是一个活细胞的坐标：
+//!
+//! ```text
+//! #Life 1.06
+//! 0 -1
+//! 1 0
+//! -1 1
+//! 0 1
+//! 1 1
+//! ```
+//!
+//! 两种格式的坐标都是相对于图案中心的，可以是负数，读的时候平移到活细胞的包围盒
+
+use super::{Error, Pattern};
+use crate::rule::Rule;
+
+/// 解析一个可能为负的坐标
+fn coordinate(number: usize, item: &str) -> Result<i64, Error> {
+    item.parse()
+        .map_err(|_| Error::parse(number, format!("`{item}` 不是有效的坐标")))
+}
+
+/// 解析 `x y` 两个整数
+fn point(number: usize, line: &str) -> Result<(i64, i64), Error> {
+    let mut items = line.split_whitespace();
+    match (items.next(), items.next(), items.next()) {
+        (Some(x), Some(y), None) => Ok((coordinate(number, x)?, coordinate(number, y)?)),
+        _ => Err(Error::parse(number, "应该是 `x y` 两个整数")),
+    }
+}
+
+impl Pattern {
+    /// 由活细胞的坐标创建图案，先检查包围盒能不能放进 `map_size` 大小的地图，再分配内存
+    fn from_points(points: &[(i64, i64)], map_size: (u32, u32)) -> Result<Self, Error> {
+        let Some(&(x, y)) = points.first() else {
+            return Ok(Pattern::default());
+        };
+        let (x0, y0, x1, y1) = points
+            .iter()
+            .fold((x, y, x, y), |(x0, y0, x1, y1), &(x, y)| {
+                (x0.min(x), y0.min(y), x1.max(x), y1.max(y))
+            });
+        // 从 i64::MIN 到 i64::MAX 的宽度放不进 u64
+        let size = |start: i64, end: i64| {
+            end.abs_diff(start)
+                .checked_add(1)
+                .and_then(|size| u32::try_from(size).ok())
+                .unwrap_or(u32::MAX)
+        };
+        let (width, height) = (size(x0, x1), size(y0, y1));
+        if width > map_size.0 || height > map_size.1 {
+            return Err(Error::TooLarge {
+                size: (width, height),
+                offset: (0, 0),
+                map_size,
+            });
+        }
+        let mut pattern = Pattern::new(width, height);
+        for &(x, y) in points {
+            pattern.set((x - x0) as u32, (y - y0) as u32, true);
+        }
+        Ok(pattern)
+    }
+
+    /// 解析 Life 1.05 格式的图案，包围盒放不进 `map_size` 大小的地图时出错
+    pub fn from_life105(text: &str, map_size: (u32, u32)) -> Result<Self, Error> {
+        let mut points = vec![];
+        let mut rule = None;
+        let mut comments = vec![];
+        // 当前块的左上角和块中已经读过的行数，没有 `#P` 时从 (0, 0) 开始
+        let (mut left, mut top, mut rows) = (0i64, 0i64, 0i64);
+
+        for (number, line) in text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line))
+        {
+            let line = line.trim();
+            if let Some(directive) = line.strip_prefix('#') {
+                let mut chars = directive.chars();
+                let kind = chars.next();
+                let content = chars.as_str().trim();
+                match kind {
+                    Some('D' | 'C') => comments.push(content.to_string()),
+                    Some('N') => rule = Some(Rule::CONWAY),
+                    Some('R') => {
+                        rule = Some(
+                            content
+                                .parse()
+                                .map_err(|e| Error::parse(number, format!("规则无效：{e}")))?,
+                        )
+                    }
+                    Some('P') => {
+                        (left, top) = point(number, content)?;
+                        rows = 0;
+                    }
+                    // `#Life 1.05` 头部
+                    _ => {}
+                }
+                continue;
+            }
+            let overflow = || Error::parse(number, "坐标超出了范围");
+            let y = top.checked_add(rows).ok_or_else(overflow)?;
+            for (dx, c) in line.chars().enumerate() {
+                match c {
+                    '*' => points.push((left.checked_add(dx as i64).ok_or_else(overflow)?, y)),
+                    '.' => {}
+                    c => return Err(Error::parse(number, format!("无法识别的字符 `{c}`"))),
+                }
+            }
+            rows += 1;
+        }
+
+        let mut pattern = Self::from_points(&points, map_size)?;
+        pattern.rule = rule;
+        pattern.comments = comments;
+        Ok(pattern)
+    }
+
+    /// 解析 Life 1.06 格式的图案，包围盒放不进 `map_size` 大小的地图时出错
+    pub fn from_life106(text: &str, map_size: (u32, u32)) -> Result<Self, Error> {
+        let mut points = vec![];
+        let mut comments = vec![];
+        for (number, line) in text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line))
+        {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(directive) = line.strip_prefix('#') {
+                // 除了 `#Life 1.06` 头部，只有 `#D` 注释
+                if let Some(comment) = directive.strip_prefix(['D', 'C']) {
+                    comments.push(comment.trim().to_string());
+                }
+                continue;
+            }
+            points.push(point(number, line)?);
+        }
+        let mut pattern = Self::from_points(&points, map_size)?;
+        pattern.comments = comments;
+        Ok(pattern)
+    }
+}
@@ -0,0 +1,354 @@
+//! Golly 的 macrocell `.mc` 格式：用四叉树描述很大的图案，相同的子树只写一次
+//!
+//! ```text
+//! [M2] (golly 2.0)
+//! #R B3/S23
+//! .*$..*$***$
+//! 4 1 0 0 0
+//! ```
+//!
+//! 以 `.`、`*` 或者 `$` 开头的行是 8x8 的叶子，`$` 结束一行，行尾和末尾的死细胞可以省略。
+//! `层数 左上 右上 左下 右下` 的行是 2^层数 大小的节点，子节点是前面的行号（从 1 开始），
+//! 0 表示全是死细胞。最后一个节点是整个图案
+
+use std::{collections::HashMap, fmt::Write};
+
+use super::{Error, Pattern};
+
+/// 叶子的层数，8x8
+const LEAF_LEVEL: u32 = 3;
+
+/// 最多的层数，超过时坐标放不进 `u64`
+const MAX_LEVEL: u32 = 63;
+
+/// 四叉树的节点，下标 0 是全死的节点
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Node {
+    Empty,
+    /// 8x8 的叶子，第 `y * 8 + x` 位是 `(x, y)` 处的细胞
+    Leaf(u64),
+    /// 子节点依次是左上、右上、左下、右下
+    Branch {
+        level: u32,
+        children: [u32; 4],
+    },
+}
+
+/// 活细胞的包围盒，节点内的坐标，包含两端
+type Bounds = Option<(u64, u64, u64, u64)>;
+
+fn union(a: Bounds, b: Bounds) -> Bounds {
+    match (a, b) {
+        (Some(a), Some(b)) => Some((a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// 解析出来的四叉树
+struct Quadtree {
+    nodes: Vec<Node>,
+    /// 每个节点的包围盒，展开之前就能知道图案有多大
+    bounds: Vec<Bounds>,
+}
+
+impl Quadtree {
+    fn root(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    fn bounds_of(&self, node: &Node) -> Bounds {
+        match *node {
+            Node::Empty => None,
+            Node::Leaf(bits) => (0..64)
+                .filter(|i| bits >> i & 1 == 1)
+                .map(|i| Some((i % 8, i / 8, i % 8, i / 8)))
+                .fold(None, union),
+            Node::Branch { level, children } => {
+                let half = 1u64 << (level - 1);
+                children
+                    .iter()
+                    .enumerate()
+                    .map(|(quadrant, &child)| {
+                        let (dx, dy) = (quadrant as u64 % 2 * half, quadrant as u64 / 2 * half);
+                        self.bounds[child as usize]
+                            .map(|(x0, y0, x1, y1)| (x0 + dx, y0 + dy, x1 + dx, y1 + dy))
+                    })
+                    .fold(None, union)
+            }
+        }
+    }
+
+    /// 把 `node` 中落在 `pattern` 里的活细胞展开，`origin` 是节点左上角相对图案的位置
+    fn expand(&self, node: u32, origin: (i128, i128), pattern: &mut Pattern) {
+        let Some((x0, y0, x1, y1)) = self.bounds[node as usize] else {
+            return;
+        };
+        if origin.0 + (x1 as i128) < 0
+            || origin.1 + (y1 as i128) < 0
+            || origin.0 + x0 as i128 >= pattern.width as i128
+            || origin.1 + y0 as i128 >= pattern.height as i128
+        {
+            return;
+        }
+        match self.nodes[node as usize] {
+            Node::Empty => {}
+            Node::Leaf(bits) => {
+                for i in (0..64i128).filter(|i| bits >> i & 1 == 1) {
+                    // 包围盒已经裁剪到活细胞，叶子里的活细胞一定在图案内
+                    let x = origin.0 + i % 8;
+                    let y = origin.1 + i / 8;
+                    pattern.set(x as u32, y as u32, true);
+                }
+            }
+            Node::Branch { level, children } => {
+                let half = 1i128 << (level - 1);
+                for (quadrant, &child) in children.iter().enumerate() {
+                    let offset = (quadrant as i128 % 2 * half, quadrant as i128 / 2 * half);
+                    self.expand(child, (origin.0 + offset.0, origin.1 + offset.1), pattern);
+                }
+            }
+        }
+    }
+}
+
+/// 解析 8x8 的叶子
+fn parse_leaf(number: usize, line: &str) -> Result<u64, Error> {
+    let (mut x, mut y) = (0u32, 0u32);
+    let mut bits = 0u64;
+    for c in line.chars() {
+        match c {
+            '.' | '*' => {
+                if x >= 8 || y >= 8 {
+                    return Err(Error::parse(number, "叶子超出了 8x8 的范围"));
+                }
+                if c == '*' {
+                    bits |= 1 << (y * 8 + x);
+                }
+                x += 1;
+            }
+            '$' => {
+                y += 1;
+                x = 0;
+            }
+            c if c.is_whitespace() => {}
+            c => return Err(Error::parse(number, format!("无法识别的字符 `{c}`"))),
+        }
+    }
+    Ok(bits)
+}
+
+/// 解析 `层数 左上 右上 左下 右下`，子节点必须在前面定义过并且低一层
+fn parse_branch(number: usize, line: &str, nodes: &[Node]) -> Result<Node, Error> {
+    let numbers = line
+        .split_whitespace()
+        .map(|item| {
+            item.parse::<u32>()
+                .map_err(|_| Error::parse(number, format!("`{item}` 不是有效的数字")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let [level, nw, ne, sw, se] = numbers[..] else {
+        return Err(Error::parse(
+            number,
+            "节点应该是 `层数 左上 右上 左下 右下`",
+        ));
+    };
+    let children = [nw, ne, sw, se];
+    if level <= LEAF_LEVEL {
+        return Err(Error::parse(number, "不支持多状态规则的节点"));
+    }
+    if level > MAX_LEVEL {
+        return Err(Error::parse(number, format!("层数 {level} 太大")));
+    }
+    for &child in &children {
+        let child_level = match nodes.get(child as usize) {
+            None => {
+                return Err(Error::parse(number, format!("子节点 {child} 还没有定义")));
+            }
+            Some(Node::Empty) => continue,
+            Some(Node::Leaf(_)) => LEAF_LEVEL,
+            Some(Node::Branch { level, .. }) => *level,
+        };
+        if child_level != level - 1 {
+            return Err(Error::parse(
+                number,
+                format!("第 {level} 层节点的子节点 {child} 在第 {child_level} 层"),
+            ));
+        }
+    }
+    Ok(Node::Branch { level, children })
+}
+
+/// 构建四叉树时去掉重复的节点
+#[derive(Default)]
+struct Builder {
+    nodes: Vec<Node>,
+    indices: HashMap<Node, u32>,
+}
+
+impl Builder {
+    fn intern(&mut self, node: Node) -> u32 {
+        if node == Node::Empty {
+            return 0;
+        }
+        *self.indices.entry(node).or_insert_with(|| {
+            self.nodes.push(node);
+            self.nodes.len() as u32
+        })
+    }
+
+    /// 左上角在 `(x, y)` 的、第 `level` 层的节点，超出图案的部分是死细胞
+    fn build(&mut self, pattern: &Pattern, level: u32, (x, y): (u32, u32)) -> u32 {
+        if x >= pattern.width || y >= pattern.height {
+            return 0;
+        }
+        if level == LEAF_LEVEL {
+            let mut bits = 0u64;
+            for dy in 0..8 {
+                for dx in 0..8 {
+                    let (cx, cy) = (x + dx, y + dy);
+                    if cx < pattern.width && cy < pattern.height && pattern.get(cx, cy) {
+                        bits |= 1 << (dy * 8 + dx);
+                    }
+                }
+            }
+            return self.intern(if bits == 0 {
+                Node::Empty
+            } else {
+                Node::Leaf(bits)
+            });
+        }
+        let half = 1 << (level - 1);
+        let children = [(0, 0), (half, 0), (0, half), (half, half)]
+            .map(|(dx, dy)| self.build(pattern, level - 1, (x + dx, y + dy)));
+        self.intern(if children == [0; 4] {
+            Node::Empty
+        } else {
+            Node::Branch { level, children }
+        })
+    }
+}
+
+impl Pattern {
+    /// 解析 macrocell 格式的图案，只展开活细胞的包围盒
+    ///
+    /// 包围盒放不进 `map_size` 大小的地图时返回 `Error::TooLarge`，
+    /// 这时不会展开，所以很大的 `.mc` 文件也不会耗尽内存
+    pub fn from_macrocell(text: &str, map_size: (u32, u32)) -> Result<Self, Error> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        match lines.next() {
+            Some((_, line)) if line.starts_with("[M2]") => {}
+            Some((number, _)) => return Err(Error::parse(number, "缺少 `[M2]` 头部")),
+            None => return Err(Error::parse(1, "缺少 `[M2]` 头部")),
+        }
+
+        let mut pattern = Pattern::default();
+        let mut tree = Quadtree {
+            nodes: vec![Node::Empty],
+            bounds: vec![None],
+        };
+        for (number, line) in lines {
+            let node = if let Some(comment) = line.strip_prefix('#') {
+                let mut chars = comment.chars();
+                let kind = chars.next();
+                let content = chars.as_str().trim().to_string();
+                match kind {
+                    Some('R') => {
+                        let rule = content.split(':').next().unwrap_or_default();
+                        pattern.rule = Some(
+                            rule.parse()
+                                .map_err(|e| Error::parse(number, format!("规则无效：{e}")))?,
+                        );
+                    }
+                    Some('C' | 'D') => pattern.comments.push(content),
+                    Some('N') => pattern.name = Some(content),
+                    Some('O') => pattern.author = Some(content),
+                    // `#G` 等其他的行不影响图案本身
+                    _ => {}
+                }
+                continue;
+            } else if line.starts_with(['.', '*', '$']) {
+                Node::Leaf(parse_leaf(number, line)?)
+            } else {
+                parse_branch(number, line, &tree.nodes)?
+            };
+            let bounds = tree.bounds_of(&node);
+            tree.nodes.push(node);
+            tree.bounds.push(bounds);
+        }
+
+        let Some((x0, y0, x1, y1)) = tree.bounds[tree.root()] else {
+            return Ok(pattern);
+        };
+        let size = |start: u64, end: u64| u32::try_from(end - start + 1).unwrap_or(u32::MAX);
+        let (width, height) = (size(x0, x1), size(y0, y1));
+        if width > map_size.0 || height > map_size.1 {
+            return Err(Error::TooLarge {
+                size: (width, height),
+                offset: (0, 0),
+                map_size,
+            });
+        }
+
+        pattern.width = width;
+        pattern.height = height;
+        pattern.cells = vec![false; width as usize * height as usize];
+        let root = tree.root() as u32;
+        tree.expand(root, (-(x0 as i128), -(y0 as i128)), &mut pattern);
+        Ok(pattern)
+    }
+
+    /// 转换成 macrocell 格式，相同的子树只写一次，没有指定规则时写成 `B3/S23`
+    pub fn to_macrocell(&self) -> String {
+        let mut text = String::from("[M2] (life_game_wgpu)\n");
+        writeln!(text, "#R {}", self.rule.unwrap_or_default()).unwrap();
+        if let Some(name) = &self.name {
+            writeln!(text, "#N {name}").unwrap();
+        }
+        if let Some(author) = &self.author {
+            writeln!(text, "#O {author}").unwrap();
+        }
+        for comment in &self.comments {
+            writeln!(text, "#C {comment}").unwrap();
+        }
+
+        let mut level = LEAF_LEVEL;
+        while 1u64 << level < self.width.max(self.height) as u64 {
+            level += 1;
+        }
+        let mut builder = Builder::default();
+        builder.build(self, level, (0, 0));
+        if builder.nodes.is_empty() {
+            // 没有活细胞时写一个空的叶子，读的时候也能得到空图案
+            text.push_str("$\n");
+        }
+
+        for node in &builder.nodes {
+            match *node {
+                Node::Empty => {}
+                Node::Leaf(bits) => {
+                    let rows = (0..8).map(|y| bits >> (y * 8) & 0xff);
+                    let end = rows.clone().rposition(|row| row != 0).map_or(0, |y| y + 1);
+                    for row in rows.take(end) {
+                        let width = 8 - (row as u8).leading_zeros();
+                        for x in 0..width {
+                            text.push(if row >> x & 1 == 1 { '*' } else { '.' });
+                        }
+                        text.push('$');
+                    }
+                    text.push('\n');
+                }
+                Node::Branch { level, children } => {
+                    let [nw, ne, sw, se] = children;
+                    writeln!(text, "{level} {nw} {ne} {sw} {se}").unwrap();
+                }
+            }
+        }
+        text
+    }
+}
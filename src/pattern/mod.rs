@@ -0,0 +1,266 @@
+//! 图案：一块矩形区域内的细胞，以及各种图案文件格式的读写
+
+mod image;
+mod life;
+mod macrocell;
+mod plaintext;
+mod rle;
+
+use std::{fmt, path::Path};
+
+pub(crate) use image::encode_png;
+pub use image::DEFAULT_THRESHOLD;
+
+use crate::{
+    resources::{ALIVE, DEAD},
+    rule::Rule,
+};
+
+/// 一块矩形区域内的细胞
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pattern {
+    pub width: u32,
+    pub height: u32,
+    /// 按行储存，`true` 表示活细胞
+    pub cells: Vec<bool>,
+    /// 文件中指定的规则
+    pub rule: Option<Rule>,
+    /// 名字（RLE 中的 `#N`）
+    pub name: Option<String>,
+    /// 作者（RLE 中的 `#O`）
+    pub author: Option<String>,
+    /// 注释（RLE 中的 `#C`）
+    pub comments: Vec<String>,
+}
+
+impl Pattern {
+    /// 全是死细胞的图案
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![false; width as usize * height as usize],
+            ..Default::default()
+        }
+    }
+
+    /// 和 `new` 一样，细胞数超过 `u32::MAX` 时返回 `None`，用来检查文件中声明的大小
+    pub fn checked_new(width: u32, height: u32) -> Option<Self> {
+        (width as u64)
+            .checked_mul(height as u64)
+            .filter(|&cells| cells <= u32::MAX as u64)
+            .map(|_| Self::new(width, height))
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        self.cells[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, alive: bool) {
+        let index = self.index(x, y);
+        self.cells[index] = alive;
+    }
+
+    /// 从纹理中读回的整张地图创建图案
+    pub fn from_map(map: &[[u8; 4]], map_size: (u32, u32)) -> Self {
+        Self {
+            width: map_size.0,
+            height: map_size.1,
+            cells: map.iter().map(|cell| cell[0] > 0).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// 活细胞的包围盒 `(x, y, width, height)`，没有活细胞时为 `None`
+    pub fn bounding_box(&self) -> Option<(u32, u32, u32, u32)> {
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.get(x, y) {
+                    bounds = Some(match bounds {
+                        Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                        None => (x, y, x, y),
+                    });
+                }
+            }
+        }
+        bounds.map(|(x0, y0, x1, y1)| (x0, y0, x1 - x0 + 1, y1 - y0 + 1))
+    }
+
+    /// 截取一块矩形区域，规则和注释等信息会被保留
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        let mut pattern = Pattern {
+            rule: self.rule,
+            name: self.name.clone(),
+            author: self.author.clone(),
+            comments: self.comments.clone(),
+            ..Pattern::new(width, height)
+        };
+        for dy in 0..height {
+            for dx in 0..width {
+                pattern.set(dx, dy, self.get(x + dx, y + dy));
+            }
+        }
+        pattern
+    }
+
+    /// 截取到活细胞的包围盒
+    pub fn cropped(&self) -> Self {
+        let (x, y, width, height) = self.bounding_box().unwrap_or_default();
+        self.crop(x, y, width, height)
+    }
+
+    /// 从文件读取图案，见 `parse`
+    pub fn load(path: impl AsRef<Path>, map_size: (u32, u32)) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text, map_size)
+    }
+
+    /// 按内容识别格式并解析图案，放不进 `map_size` 大小的地图时出错
+    pub fn parse(text: &str, map_size: (u32, u32)) -> Result<Self, Error> {
+        let pattern = match Format::detect(text) {
+            Format::Rle => Self::from_rle(text, map_size)?,
+            Format::Cells => Self::from_cells(text)?,
+            Format::Life105 => Self::from_life105(text, map_size)?,
+            Format::Life106 => Self::from_life106(text, map_size)?,
+            Format::Macrocell => Self::from_macrocell(text, map_size)?,
+        };
+        pattern.check_fits((0, 0), map_size)?;
+        Ok(pattern)
+    }
+
+    /// 放在地图正中间时的偏移
+    pub fn centered_offset(&self, map_size: (u32, u32)) -> (u32, u32) {
+        (
+            map_size.0.saturating_sub(self.width) / 2,
+            map_size.1.saturating_sub(self.height) / 2,
+        )
+    }
+
+    /// 检查图案放在 `offset` 处时能不能放进地图
+    pub fn check_fits(&self, offset: (u32, u32), map_size: (u32, u32)) -> Result<(), Error> {
+        let fits = |offset: u32, size: u32, map: u32| {
+            offset.checked_add(size).is_some_and(|end| end <= map)
+        };
+        if !fits(offset.0, self.width, map_size.0) || !fits(offset.1, self.height, map_size.1) {
+            return Err(Error::TooLarge {
+                size: (self.width, self.height),
+                offset,
+                map_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// 把图案写入地图，`offset` 是图案左上角在地图上的位置
+    pub fn place(
+        &self,
+        map: &mut [[u8; 4]],
+        map_size: (u32, u32),
+        offset: (u32, u32),
+    ) -> Result<(), Error> {
+        self.check_fits(offset, map_size)?;
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = (offset.1 + y) as usize * map_size.0 as usize + (offset.0 + x) as usize;
+                map[index] = if self.get(x, y) { ALIVE } else { DEAD };
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 图案文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Rle,
+    /// 纯文本 `.cells`
+    Cells,
+    Life105,
+    Life106,
+    Macrocell,
+}
+
+impl Format {
+    /// 由第一个不为空的行判断格式，无法判断时当作 RLE
+    pub fn detect(text: &str) -> Self {
+        let first = text
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or_default();
+        if first.starts_with("#Life 1.05") {
+            Format::Life105
+        } else if first.starts_with("#Life 1.06") {
+            Format::Life106
+        } else if first.starts_with("[M2]") {
+            Format::Macrocell
+        } else if first.starts_with('!')
+            || !first.is_empty() && first.chars().all(|c| matches!(c, '.' | 'O' | '*'))
+        {
+            Format::Cells
+        } else {
+            Format::Rle
+        }
+    }
+}
+
+/// 读取或者放置图案时的错误
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// 文件内容有误，`line` 从 1 开始
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// 图片无法解码或者编码
+    Image(String),
+    /// 图案放不进地图
+    TooLarge {
+        size: (u32, u32),
+        offset: (u32, u32),
+        map_size: (u32, u32),
+    },
+}
+
+impl Error {
+    fn parse(line: usize, message: impl Into<String>) -> Self {
+        Error::Parse {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "无法读取图案文件：{error}"),
+            Error::Parse { line, message } => write!(f, "第 {line} 行：{message}"),
+            Error::Image(message) => write!(f, "无法读取或者写入图片：{message}"),
+            Error::TooLarge {
+                size,
+                offset,
+                map_size,
+            } => write!(
+                f,
+                "{}x{} 的图案放在 ({}, {}) 处超出了 {}x{} 的地图",
+                size.0, size.1, offset.0, offset.1, map_size.0, map_size.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
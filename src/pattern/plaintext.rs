@@ -0,0 +1,94 @@
+//! 纯文本 `.cells` 格式
+//!
+//! ```text
+//! !Name: Glider
+//! .O.
+//! ..O
+//! OOO
+//! ```
+
+use std::fmt::Write;
+
+use super::{Error, Pattern};
+
+impl Pattern {
+    /// 解析 `.cells` 格式的图案，`O` 或者 `*` 是活细胞，`.` 是死细胞
+    ///
+    /// `!` 开头的是注释，其中 `Name:`、`Author:` 和 `Rule:` 开头的分别是名字、作者和规则
+    pub fn from_cells(text: &str) -> Result<Self, Error> {
+        let mut pattern = Pattern::default();
+        let mut rows: Vec<Vec<bool>> = vec![];
+        for (number, line) in text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line))
+        {
+            let line = line.trim_end();
+            if let Some(comment) = line.strip_prefix('!') {
+                let comment = comment.trim();
+                if let Some(name) = comment.strip_prefix("Name:") {
+                    pattern.name = Some(name.trim().to_string());
+                } else if let Some(author) = comment.strip_prefix("Author:") {
+                    pattern.author = Some(author.trim().to_string());
+                } else if let Some(rule) = comment.strip_prefix("Rule:") {
+                    pattern.rule = Some(
+                        rule.trim()
+                            .parse()
+                            .map_err(|e| Error::parse(number, format!("规则无效：{e}")))?,
+                    );
+                } else {
+                    pattern.comments.push(comment.to_string());
+                }
+                continue;
+            }
+            let row = line
+                .chars()
+                .map(|c| match c {
+                    'O' | '*' => Ok(true),
+                    '.' => Ok(false),
+                    c => Err(Error::parse(number, format!("无法识别的字符 `{c}`"))),
+                })
+                .collect::<Result<_, _>>()?;
+            rows.push(row);
+        }
+        // 末尾的空行不算
+        while rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
+        }
+
+        // 每行末尾的死细胞可以省略
+        let width = rows.iter().map(Vec::len).max().unwrap_or_default();
+        pattern.width = width as u32;
+        pattern.height = rows.len() as u32;
+        pattern.cells = rows
+            .iter()
+            .flat_map(|row| (0..width).map(|x| row.get(x).copied().unwrap_or_default()))
+            .collect();
+        Ok(pattern)
+    }
+
+    /// 转换成 `.cells` 格式，每行末尾的死细胞会被省略
+    pub fn to_cells(&self) -> String {
+        let mut text = String::new();
+        if let Some(name) = &self.name {
+            writeln!(text, "!Name: {name}").unwrap();
+        }
+        if let Some(author) = &self.author {
+            writeln!(text, "!Author: {author}").unwrap();
+        }
+        if let Some(rule) = self.rule {
+            writeln!(text, "!Rule: {rule}").unwrap();
+        }
+        for comment in &self.comments {
+            writeln!(text, "!{comment}").unwrap();
+        }
+        for y in 0..self.height {
+            let row = (0..self.width)
+                .map(|x| if self.get(x, y) { 'O' } else { '.' })
+                .collect::<String>();
+            text.push_str(row.trim_end_matches('.'));
+            text.push('\n');
+        }
+        text
+    }
+}
@@ -0,0 +1,227 @@
+//! RLE 格式
+//!
+//! ```text
+//! #N Glider
+//! #C 注释
+//! x = 3, y = 3, rule = B3/S23
+//! bob$2bo$3o!
+//! ```
+
+use std::fmt::Write;
+
+use super::{Error, Pattern};
+
+/// 每行最多的字符数
+const LINE_WIDTH: usize = 70;
+
+/// 添加 `count` 个 `tag`，和前一段相同时合并
+fn push_run(runs: &mut Vec<(u32, char)>, count: u32, tag: char) {
+    match runs.last_mut() {
+        Some((last_count, last_tag)) if *last_tag == tag => *last_count += count,
+        _ if count > 0 => runs.push((count, tag)),
+        _ => {}
+    }
+}
+
+impl Pattern {
+    /// 解析 RLE 格式的图案
+    ///
+    /// 头部声明的大小放不进 `map_size` 大小的地图时返回 `Error::TooLarge`，
+    /// 这时还没有分配细胞，所以头部很大的文件也不会耗尽内存
+    pub fn from_rle(text: &str, map_size: (u32, u32)) -> Result<Self, Error> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line));
+
+        let mut name = None;
+        let mut author = None;
+        let mut comments = vec![];
+
+        // 头部之前只能是 `#` 开头的注释
+        let (header_line, header) = loop {
+            let Some((number, line)) = lines.next() else {
+                return Err(Error::parse(text.lines().count(), "缺少 `x = , y = ` 头部"));
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some(comment) = line.strip_prefix('#') else {
+                break (number, line);
+            };
+            let mut chars = comment.chars();
+            let kind = chars.next();
+            let content = chars.as_str().trim().to_string();
+            match kind {
+                Some('C' | 'c') => comments.push(content),
+                Some('N') => name = Some(content),
+                Some('O') => author = Some(content),
+                // `#P`、`#R` 等其他的行不影响图案本身
+                _ => {}
+            }
+        };
+
+        // 规则放在最后，而且 Golly 的 `:T` 后缀里可能会有逗号
+        let (sizes, rule) = match header.find("rule") {
+            Some(index) => (&header[..index], Some(&header[index..])),
+            None => (header, None),
+        };
+
+        let mut width = None;
+        let mut height = None;
+        for item in sizes.split(',').filter(|item| !item.trim().is_empty()) {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| Error::parse(header_line, format!("头部中的 `{item}` 缺少 `=`")))?;
+            let value = value.trim();
+            let size = value
+                .parse::<u32>()
+                .map_err(|_| Error::parse(header_line, format!("`{value}` 不是有效的大小")))?;
+            match key.trim() {
+                "x" => width = Some(size),
+                "y" => height = Some(size),
+                _ => {}
+            }
+        }
+
+        let rule = rule
+            .map(|rule| {
+                let (_, value) = rule
+                    .split_once('=')
+                    .ok_or_else(|| Error::parse(header_line, "头部中的 `rule` 缺少 `=`"))?;
+                // Golly 会在规则后面用 `:T` 之类的后缀指定边界，这里忽略它
+                let value = value.split(':').next().unwrap_or_default();
+                value
+                    .parse()
+                    .map_err(|e| Error::parse(header_line, format!("规则无效：{e}")))
+            })
+            .transpose()?;
+
+        let (Some(width), Some(height)) = (width, height) else {
+            return Err(Error::parse(header_line, "头部缺少 `x` 或者 `y`"));
+        };
+
+        if width > map_size.0 || height > map_size.1 {
+            return Err(Error::TooLarge {
+                size: (width, height),
+                offset: (0, 0),
+                map_size,
+            });
+        }
+        let mut pattern = Pattern::checked_new(width, height)
+            .ok_or_else(|| Error::parse(header_line, format!("{width}x{height} 的图案太大")))?;
+        pattern.rule = rule;
+        pattern.name = name;
+        pattern.author = author;
+        pattern.comments = comments;
+
+        let (mut x, mut y) = (0u32, 0u32);
+        // 重复次数可能和后面的标记不在同一行
+        let mut count: Option<u32> = None;
+
+        'lines: for (number, line) in lines {
+            let outside = || {
+                Error::parse(
+                    number,
+                    format!("细胞超出了头部声明的 {width}x{height} 范围"),
+                )
+            };
+            for c in line.chars() {
+                let run = || count.unwrap_or(1);
+                match c {
+                    '0'..='9' => {
+                        let digit = c.to_digit(10).unwrap();
+                        count = Some(
+                            count
+                                .unwrap_or(0)
+                                .checked_mul(10)
+                                .and_then(|n| n.checked_add(digit))
+                                .ok_or_else(|| Error::parse(number, "重复次数太大"))?,
+                        );
+                        continue;
+                    }
+                    'b' | '.' => x = x.checked_add(run()).ok_or_else(outside)?,
+                    'o' | 'A' => {
+                        let end = x
+                            .checked_add(run())
+                            .filter(|&end| y < height && end <= width)
+                            .ok_or_else(outside)?;
+                        for x in x..end {
+                            pattern.set(x, y, true);
+                        }
+                        x = end;
+                    }
+                    '$' => {
+                        y = y.checked_add(run()).ok_or_else(outside)?;
+                        x = 0;
+                    }
+                    '!' => break 'lines,
+                    c if c.is_whitespace() => continue,
+                    c => return Err(Error::parse(number, format!("无法识别的字符 `{c}`"))),
+                }
+                count = None;
+            }
+        }
+
+        Ok(pattern)
+    }
+
+    /// 转换成 RLE 格式，没有指定规则时写成 `B3/S23`
+    pub fn to_rle(&self) -> String {
+        let mut text = String::new();
+        if let Some(name) = &self.name {
+            writeln!(text, "#N {name}").unwrap();
+        }
+        if let Some(author) = &self.author {
+            writeln!(text, "#O {author}").unwrap();
+        }
+        for comment in &self.comments {
+            writeln!(text, "#C {comment}").unwrap();
+        }
+        writeln!(
+            text,
+            "x = {}, y = {}, rule = {}",
+            self.width,
+            self.height,
+            self.rule.unwrap_or_default()
+        )
+        .unwrap();
+
+        let mut runs = vec![];
+        for y in 0..self.height {
+            if y > 0 {
+                push_run(&mut runs, 1, '$');
+            }
+            // 每行末尾的死细胞可以省略
+            let end = (0..self.width)
+                .rev()
+                .find(|&x| self.get(x, y))
+                .map_or(0, |x| x + 1);
+            for x in 0..end {
+                push_run(&mut runs, 1, if self.get(x, y) { 'o' } else { 'b' });
+            }
+        }
+        if let Some((_, '$')) = runs.last() {
+            runs.pop();
+        }
+
+        let mut line = String::new();
+        for (count, tag) in runs {
+            let item = match count {
+                1 => tag.to_string(),
+                count => format!("{count}{tag}"),
+            };
+            if line.len() + item.len() > LINE_WIDTH {
+                text.push_str(&line);
+                text.push('\n');
+                line.clear();
+            }
+            line.push_str(&item);
+        }
+        line.push('!');
+        text.push_str(&line);
+        text.push('\n');
+        text
+    }
+}
@@ -0,0 +1,96 @@
+//! 判断地图是否已经稳定：记住最近若干代的散列值，重复出现时就找到了周期
+//!
+//! 散列值由 `stats.wgsl` 在每一代之后计算，见 `Simulator::enable_period_detection`
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::stats::Sample;
+
+/// 找到的周期
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    /// 第一次发现重复的那一代
+    pub generation: u64,
+    /// 周期，1 表示静物
+    pub period: u64,
+}
+
+/// 最近若干代的散列值
+#[derive(Debug, Clone)]
+pub struct PeriodDetector {
+    /// 散列值和活细胞数量 -> 最近一次出现的代数
+    table: HashMap<(u64, u64), u64>,
+    /// 按代数排列，超出容量时从前面丢掉
+    order: VecDeque<((u64, u64), u64)>,
+    capacity: usize,
+    /// 上一个结果
+    last: Option<Sample>,
+    period: Option<Period>,
+}
+
+impl PeriodDetector {
+    /// 最多记住 `capacity` 代，所以只能找到不超过 `capacity` 的周期
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            table: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            last: None,
+            period: None,
+        }
+    }
+
+    /// 当前的周期，地图还没有稳定时为 `None`
+    pub fn period(&self) -> Option<Period> {
+        self.period
+    }
+
+    /// 忘掉所有的散列值
+    pub fn clear(&mut self) {
+        self.table.clear();
+        self.order.clear();
+        self.last = None;
+        self.period = None;
+    }
+
+    /// 记录一代的结果，返回当前的周期
+    ///
+    /// 结果要按代数连续，否则找到的不一定是最小的周期。
+    /// 代数倒退或者同一代的地图变了（编辑过细胞）时，之前的散列值都作废
+    pub fn observe(&mut self, sample: &Sample) -> Option<Period> {
+        if let Some(last) = &self.last {
+            if sample.generation == last.generation && sample.hash == last.hash {
+                return self.period;
+            }
+            if sample.generation <= last.generation {
+                self.clear();
+            }
+        }
+        self.last = Some(*sample);
+
+        let key = (sample.hash, sample.population);
+        self.period = match self.table.get(&key) {
+            Some(&generation) => Some(Period {
+                // 一直重复时保留第一次发现的代数
+                generation: match self.period {
+                    Some(period) if period.period == sample.generation - generation => {
+                        period.generation
+                    }
+                    _ => sample.generation,
+                },
+                period: sample.generation - generation,
+            }),
+            None => None,
+        };
+
+        if self.order.len() == self.capacity {
+            let (old_key, old_generation) = self.order.pop_front().unwrap();
+            if self.table.get(&old_key) == Some(&old_generation) {
+                self.table.remove(&old_key);
+            }
+        }
+        self.table.insert(key, sample.generation);
+        self.order.push_back((key, sample.generation));
+        self.period
+    }
+}
@@ -0,0 +1,375 @@
+//! 缩小显示用的金字塔：把地图一层层 2x2 归约，每层记下每块的最大值和平均密度
+//!
+//! 缩得很小时一个像素要覆盖很多格，最近邻采样只取到其中一格，稀疏的图案会闪烁或者消失。
+//! 渲染时按缩放取合适的一层，见 `Simulator::enable_pyramid`
+
+use std::str::FromStr;
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    resources::{map_read, PackedCells},
+    Cells, Result, Storage,
+};
+
+/// 缩小时每个像素显示一块里的什么
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Density {
+    /// 块里有活细胞就显示为活细胞，一个细胞也不会丢
+    #[default]
+    Max,
+    /// 按活细胞的比例显示为灰度
+    Average,
+}
+
+impl FromStr for Density {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "max" => Ok(Self::Max),
+            "average" => Ok(Self::Average),
+            _ => Err(format!("未知的缩小显示方式 {s}，应为 max 或 average")),
+        }
+    }
+}
+
+/// 金字塔的纹理和计算管线
+///
+/// 第 `level` 层的一个纹素对应地图上 `2^(level+1)` 见方的一块，
+/// 宽或高为奇数时每行或每列最后一个纹素多管一个上一层的纹素
+pub struct Pyramid {
+    base_bind_group_layout: wgpu::BindGroupLayout,
+    base_pipeline: wgpu::ComputePipeline,
+    reduce_pipeline: wgpu::ComputePipeline,
+    /// 从第 `level` 层归约到第 `level + 1` 层的绑定组
+    reduce_bind_groups: Vec<wgpu::BindGroup>,
+    map_size_uniform: wgpu::Buffer,
+    /// 第 0 层的视图，地图归约到这里
+    base_view: wgpu::TextureView,
+    /// 每一层是一级 mip，每个纹素用 `pack2x16float` 存放最大值和平均值
+    pub texture: wgpu::Texture,
+    /// 所有层的视图，渲染时绑定
+    pub view: wgpu::TextureView,
+}
+
+impl Pyramid {
+    /// 金字塔纹理的格式。最大值和平均值各是一个半精度浮点数，很稀疏时平均值会被舍入成 0，
+    /// 但最大值不会丢掉细胞
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        map_size: (u32, u32),
+        storage: Storage,
+    ) -> Result<Self> {
+        let size = wgpu::Extent3d {
+            width: (map_size.0 / 2).max(1),
+            height: (map_size.1 / 2).max(1),
+            depth_or_array_layers: 1,
+        };
+        let max = device.limits().max_texture_dimension_2d;
+        if size.width > max || size.height > max {
+            return Err(format!(
+                "{}x{} 的地图需要 {}x{} 的金字塔纹理，超出了设备支持的最大纹理尺寸 {max}",
+                map_size.0, map_size.1, size.width, size.height
+            )
+            .into());
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: size.max_mips(wgpu::TextureDimension::D2),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        // 归约的目标是只含一级 mip 的视图；来源是从第 0 级到上一级的视图，按级别读取，
+        // 因为有的后端（GL）不能采样不从第 0 级开始的视图
+        let mip_view = |base_mip_level, mip_level_count| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                base_mip_level,
+                mip_level_count: Some(mip_level_count),
+                ..Default::default()
+            })
+        };
+
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let destination_entry = wgpu::BindGroupLayoutEntry {
+            binding: 5,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: Self::FORMAT,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let base_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    match storage {
+                        Storage::Texture => {
+                            texture_entry(0, wgpu::TextureSampleType::Float { filterable: true })
+                        }
+                        Storage::Packed => wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    destination_entry,
+                ],
+            });
+        let reduce_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    texture_entry(4, wgpu::TextureSampleType::Uint),
+                    destination_entry,
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let map_size_uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[
+                map_size.0,
+                map_size.1,
+                map_size.0 / PackedCells::CELLS_PER_WORD,
+                0,
+            ]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let reduce_bind_groups = (1..texture.mip_level_count())
+            .map(|level| {
+                // GL 不支持 `textureNumLevels`，上一层的级别放在 uniform 中
+                let source_level = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents: bytemuck::cast_slice(&[level - 1, 0, 0, 0]),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &reduce_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: wgpu::BindingResource::TextureView(&mip_view(0, level)),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 5,
+                            resource: wgpu::BindingResource::TextureView(&mip_view(level, 1)),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 6,
+                            resource: source_level.as_entire_binding(),
+                        },
+                    ],
+                })
+            })
+            .collect();
+
+        let shader_module = device.create_shader_module(wgpu::include_wgsl!("pyramid.wgsl"));
+        let pipeline = |layout: &wgpu::BindGroupLayout, entry_point| {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(&layout),
+                module: &shader_module,
+                entry_point,
+            })
+        };
+        let base_pipeline = pipeline(
+            &base_bind_group_layout,
+            match storage {
+                Storage::Texture => "cs_texture",
+                Storage::Packed => "cs_packed",
+            },
+        );
+        let reduce_pipeline = pipeline(&reduce_bind_group_layout, "cs_reduce");
+
+        Ok(Self {
+            base_bind_group_layout,
+            base_pipeline,
+            reduce_pipeline,
+            reduce_bind_groups,
+            map_size_uniform,
+            base_view: mip_view(0, 1),
+            texture,
+            view,
+        })
+    }
+
+    /// 层数，最上面一层只有一个纹素
+    pub fn level_count(&self) -> u32 {
+        self.texture.mip_level_count()
+    }
+
+    /// 第 `level` 层的大小
+    pub fn level_size(&self, level: u32) -> (u32, u32) {
+        let size = self
+            .texture
+            .size()
+            .mip_level_size(level, wgpu::TextureDimension::D2);
+        (size.width, size.height)
+    }
+
+    /// 在 `encoder` 中记录从 `cells` 重建所有层
+    pub(crate) fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        cells: Cells,
+    ) {
+        let map_entry = match cells {
+            Cells::Texture(texture) => wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+            Cells::Packed(cells) => wgpu::BindGroupEntry {
+                binding: 2,
+                resource: cells.buffer.as_entire_binding(),
+            },
+        };
+        let base_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.base_bind_group_layout,
+            entries: &[
+                map_entry,
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.map_size_uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&self.base_view),
+                },
+            ],
+        });
+
+        // 每一层读上一层，直接写入自己的那一级 mip，分成不同的计算通道
+        let passes = std::iter::once((&self.base_pipeline, &base_bind_group)).chain(
+            self.reduce_bind_groups
+                .iter()
+                .map(|bind_group| (&self.reduce_pipeline, bind_group)),
+        );
+        for (level, (pipeline, bind_group)) in passes.enumerate() {
+            let (width, height) = self.level_size(level as u32);
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            cpass.set_pipeline(pipeline);
+            cpass.set_bind_group(0, bind_group, &[]);
+            cpass.dispatch_workgroups(width.div_ceil(16), height.div_ceil(16), 1);
+        }
+    }
+
+    /// 读回第 `level` 层，每个纹素是块里的最大值和平均值，按行排列
+    pub(crate) fn read(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        level: u32,
+    ) -> Result<Vec<(f32, f32)>> {
+        let (width, height) = self.level_size(level);
+        let unpadded_bytes_per_row = width * std::mem::size_of::<u32>() as u32;
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: level,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        Ok(map_read(device, &buffer)?
+            .chunks(padded_bytes_per_row as usize)
+            .flat_map(|row| {
+                bytemuck::cast_slice::<_, u32>(&row[..unpadded_bytes_per_row as usize])
+                    .iter()
+                    .map(|&texel| (f16_to_f32(texel as u16), f16_to_f32((texel >> 16) as u16)))
+                    .collect::<Vec<_>>()
+            })
+            .collect())
+    }
+}
+
+/// `pack2x16float` 中的一个半精度浮点数转换成 `f32`，金字塔中的值都在 0 到 1 之间
+fn f16_to_f32(bits: u16) -> f32 {
+    let exponent = (bits >> 10 & 0x1f) as i32;
+    let fraction = (bits & 0x3ff) as f32 / 1024.0;
+    match exponent {
+        // 非规格化数
+        0 => fraction * 2f32.powi(-14),
+        _ => (1.0 + fraction) * 2f32.powi(exponent - 15),
+    }
+}
@@ -0,0 +1,101 @@
+// 缩小显示用的金字塔，见 `pyramid.rs`
+//
+// 每一层的一个纹素是上一层 2x2 个纹素的归约：r 是最大值（块里有没有活细胞），
+// g 是平均值（活细胞的密度），两个值用 `pack2x16float` 放进一个 u32，每一层是同一个纹理的一级 mip。
+// 宽或高为奇数时，每行或每列最后一个纹素多管一个，所以不会漏掉任何细胞
+
+// 纹理储存时的地图
+@group(0) @binding(0)
+var map_texture : texture_2d<f32>;
+
+// 按位储存时的地图，每个 u32 是同一行的 32 个细胞
+@group(0) @binding(2)
+var<storage, read> cells : array<u32>;
+
+// 地图的宽、高和每行的字数
+@group(0) @binding(3)
+var<uniform> map_size : vec4u;
+
+// 从第 0 级到上一层的视图，上一层是其中的最后一级
+@group(0) @binding(4)
+var source : texture_2d<u32>;
+
+@group(0) @binding(5)
+var destination : texture_storage_2d<r32uint, write>;
+
+// 上一层的级别，x 分量
+@group(0) @binding(6)
+var<uniform> source_level : vec4u;
+
+// 目标纹素 `id` 在大小为 `source_size` 的上一层中管的范围，不含结尾
+fn block(id: vec2u, source_size: vec2u) -> array<vec2u, 2> {
+    let size = textureDimensions(destination);
+    let start = id * 2u;
+    let end = select(min(start + 2u, source_size), source_size, id == size - 1u);
+    return array(start, end);
+}
+
+fn store(id: vec2u, maximum: f32, sum: f32, count: u32) {
+    textureStore(destination, id, vec4(pack2x16float(vec2(maximum, sum / f32(count))), 0u, 0u, 0u));
+}
+
+@compute
+@workgroup_size(16, 16)
+fn cs_texture(@builtin(global_invocation_id) id: vec3u) {
+    if any(id.xy >= textureDimensions(destination)) {
+        return;
+    }
+    let range = block(id.xy, map_size.xy);
+    var maximum = 0.0;
+    var sum = 0.0;
+    for (var y = range[0].y; y < range[1].y; y++) {
+        for (var x = range[0].x; x < range[1].x; x++) {
+            let alive = textureLoad(map_texture, vec2u(x, y), 0).r;
+            maximum = max(maximum, alive);
+            sum += alive;
+        }
+    }
+    let count = (range[1].x - range[0].x) * (range[1].y - range[0].y);
+    store(id.xy, maximum, sum, count);
+}
+
+@compute
+@workgroup_size(16, 16)
+fn cs_packed(@builtin(global_invocation_id) id: vec3u) {
+    if any(id.xy >= textureDimensions(destination)) {
+        return;
+    }
+    let range = block(id.xy, map_size.xy);
+    var maximum = 0.0;
+    var sum = 0.0;
+    for (var y = range[0].y; y < range[1].y; y++) {
+        for (var x = range[0].x; x < range[1].x; x++) {
+            let alive = f32((cells[y * map_size.z + x / 32u] >> (x % 32u)) & 1u);
+            maximum = max(maximum, alive);
+            sum += alive;
+        }
+    }
+    let count = (range[1].x - range[0].x) * (range[1].y - range[0].y);
+    store(id.xy, maximum, sum, count);
+}
+
+@compute
+@workgroup_size(16, 16)
+fn cs_reduce(@builtin(global_invocation_id) id: vec3u) {
+    if any(id.xy >= textureDimensions(destination)) {
+        return;
+    }
+    let level = i32(source_level.x);
+    let range = block(id.xy, textureDimensions(source, level));
+    var maximum = 0.0;
+    var sum = 0.0;
+    for (var y = range[0].y; y < range[1].y; y++) {
+        for (var x = range[0].x; x < range[1].x; x++) {
+            let texel = unpack2x16float(textureLoad(source, vec2u(x, y), level).r);
+            maximum = max(maximum, texel.r);
+            sum += texel.g;
+        }
+    }
+    let count = (range[1].x - range[0].x) * (range[1].y - range[0].y);
+    store(id.xy, maximum, sum, count);
+}
@@ -0,0 +1,215 @@
+//! 录制：把一帧帧 RGBA 图片编码成 GIF 或者 APNG 动画，或者写成编号的 PNG 图片序列
+//!
+//! 图片可以是窗口渲染出来的画面，也可以是 `board_frame` 读出的一个像素一个细胞的地图区域。
+//! 录制不依赖窗口，批处理中也可以用
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{pattern, Result, Simulator};
+
+/// 录制的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// GIF 动画，颜色不超过 256 种时是无损的
+    Gif,
+    /// APNG 动画，无损，所有帧在结束时一起写入
+    Apng,
+    /// 一个目录中的 `00000.png`、`00001.png`、……
+    Sequence,
+}
+
+impl Format {
+    /// 按扩展名区分：`.gif` 是 GIF，`.png` 和 `.apng` 是 APNG，其他的是图片序列的目录
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("gif") => Format::Gif,
+            Some("png" | "apng") => Format::Apng,
+            _ => Format::Sequence,
+        }
+    }
+}
+
+/// 正在录制的动画或者图片序列，`finish` 之后文件才完整
+pub struct Recorder {
+    path: PathBuf,
+    format: Format,
+    delay: Duration,
+    /// 第一帧的大小，之后的帧必须一样大
+    size: Option<(u32, u32)>,
+    frames: u32,
+    /// 收到第一帧时才知道大小，才能创建
+    gif: Option<gif::Encoder<BufWriter<File>>>,
+    /// APNG 要先写入帧数，所以先把压缩过的帧留在内存中
+    apng: Vec<Vec<u8>>,
+}
+
+impl Recorder {
+    /// 开始录制到 `path`，`delay` 是每帧显示的时间。图片序列的目录不存在时创建
+    pub fn new(path: impl Into<PathBuf>, format: Format, delay: Duration) -> Result<Self> {
+        let path = path.into();
+        if format == Format::Sequence {
+            std::fs::create_dir_all(&path)?;
+        }
+        Ok(Self {
+            path,
+            format,
+            delay,
+            size: None,
+            frames: 0,
+            gif: None,
+            apng: vec![],
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// 已经录制的帧数
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// 加入一帧 `size` 大小的 RGBA 图片
+    pub fn push(&mut self, size: (u32, u32), rgba: &[u8]) -> Result<()> {
+        if rgba.len() != size.0 as usize * size.1 as usize * 4 {
+            return Err(format!(
+                "{}x{} 的图片应该有 {} 字节",
+                size.0,
+                size.1,
+                size.0 * size.1 * 4
+            )
+            .into());
+        }
+        match self.size {
+            None => self.size = Some(size),
+            Some(first) if first != size => {
+                return Err(format!(
+                    "这一帧是 {}x{}，和第一帧（{}x{}）不一样大",
+                    size.0, size.1, first.0, first.1
+                )
+                .into())
+            }
+            Some(_) => {}
+        }
+
+        match self.format {
+            Format::Gif => self.push_gif(size, rgba)?,
+            Format::Apng => self
+                .apng
+                .push(miniz_oxide::deflate::compress_to_vec(rgba, 1)),
+            Format::Sequence => std::fs::write(
+                self.path.join(format!("{:05}.png", self.frames)),
+                pattern::encode_png(size, png::ColorType::Rgba, rgba)?,
+            )?,
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn push_gif(&mut self, size: (u32, u32), rgba: &[u8]) -> Result<()> {
+        let (Ok(width), Ok(height)) = (u16::try_from(size.0), u16::try_from(size.1)) else {
+            return Err(
+                format!("GIF 最大只能是 65535x65535，这一帧是 {}x{}", size.0, size.1).into(),
+            );
+        };
+        let encoder = match &mut self.gif {
+            Some(encoder) => encoder,
+            None => {
+                let file = BufWriter::new(File::create(&self.path)?);
+                let mut encoder = gif::Encoder::new(file, width, height, &[])?;
+                encoder.set_repeat(gif::Repeat::Infinite)?;
+                self.gif.insert(encoder)
+            }
+        };
+
+        // 颜色不多时（例如一个像素一个细胞的地图）直接用这些颜色作调色板，否则量化
+        let mut palette: HashMap<[u8; 3], u8> = HashMap::new();
+        let indices: Option<Vec<u8>> = rgba
+            .chunks_exact(4)
+            .map(|pixel| {
+                let color = [pixel[0], pixel[1], pixel[2]];
+                let next = palette.len();
+                match palette.get(&color) {
+                    Some(&index) => Some(index),
+                    None if next < 256 => Some(*palette.entry(color).or_insert(next as u8)),
+                    None => None,
+                }
+            })
+            .collect();
+        let mut frame = match indices {
+            Some(indices) => {
+                let mut colors = vec![0; palette.len() * 3];
+                for (color, index) in palette {
+                    colors[index as usize * 3..][..3].copy_from_slice(&color);
+                }
+                gif::Frame::from_palette_pixels(width, height, indices, colors, None)
+            }
+            None => gif::Frame::from_rgba_speed(width, height, &mut rgba.to_vec(), 10),
+        };
+        // GIF 的延迟以百分之一秒为单位
+        frame.delay = (self.delay.as_millis() / 10).clamp(1, u16::MAX as u128) as u16;
+        encoder.write_frame(&frame)?;
+        Ok(())
+    }
+
+    /// 结束录制，写完文件，返回录制的帧数
+    pub fn finish(self) -> Result<u32> {
+        match self.format {
+            Format::Gif => {
+                if let Some(encoder) = self.gif {
+                    encoder.into_inner()?.flush()?;
+                }
+            }
+            Format::Apng => {
+                if let Some(size) = self.size {
+                    let file = BufWriter::new(File::create(&self.path)?);
+                    let mut encoder = png::Encoder::new(file, size.0, size.1);
+                    encoder.set_color(png::ColorType::Rgba);
+                    encoder.set_depth(png::BitDepth::Eight);
+                    encoder.set_animated(self.frames, 0)?;
+                    let delay = self.delay.as_millis().clamp(1, u16::MAX as u128) as u16;
+                    encoder.set_frame_delay(delay, 1000)?;
+                    let mut writer = encoder.write_header()?;
+                    for frame in &self.apng {
+                        let rgba = miniz_oxide::inflate::decompress_to_vec(frame)
+                            .map_err(|e| format!("录制的帧无法解压：{:?}", e.status))?;
+                        writer.write_image_data(&rgba)?;
+                    }
+                    writer.finish()?;
+                }
+            }
+            Format::Sequence => {}
+        }
+        Ok(self.frames)
+    }
+}
+
+/// 读出当前这一代中 `origin` 开始、`size` 大小的区域，一个像素一个细胞的 RGBA 图片，
+/// 活细胞是黑色，死细胞是白色
+pub fn board_frame(sim: &Simulator, origin: (u32, u32), size: (u32, u32)) -> Result<Vec<u8>> {
+    // 只读回这块区域，不用每帧读回整张地图
+    let cells = sim.read_region(origin, size)?;
+    Ok(cells
+        .cells
+        .iter()
+        .flat_map(|&alive| {
+            let value = if alive { 0 } else { 255 };
+            [value, value, value, 255]
+        })
+        .collect())
+}
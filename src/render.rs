@@ -1,50 +1,171 @@
-use crate::{
-    resources::{Texture, Vertex},
-    DeviceExt, State,
+use life_game_wgpu::{
+    pyramid::{Density, Pyramid},
+    resources::{map_read, ColorMap, Gradient, Grid, PackedCells, Vertex},
+    Cells, Result, Simulator, Storage,
 };
 
+use crate::{DeviceExt, State};
+
 pub struct Render {
     render_bind_group_layout: wgpu::BindGroupLayout,
     render_pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     camera_uniform: wgpu::Buffer,
+    map_size_uniform: wgpu::Buffer,
+    color_map_uniform: wgpu::Buffer,
+    heatmap_uniform: wgpu::Buffer,
+    grid_uniform: wgpu::Buffer,
+    /// 没有热度图时绑定的空缓冲区
+    empty_heat: wgpu::Buffer,
+    /// 显示金字塔的哪个通道和金字塔的层数
+    zoom_uniform: wgpu::Buffer,
+    /// 没有金字塔时绑定的空纹理
+    empty_pyramid: wgpu::TextureView,
+    /// 渲染目标的格式，窗口的或者录制时屏幕外纹理的
+    format: wgpu::TextureFormat,
     // offset : vec2f
     indicens_len: u32,
 }
 
+/// 地图所在的正方形的顶点和索引
+fn map_quad() -> ([Vertex; 4], [u16; 6]) {
+    (
+        [
+            [-1., 1.0, 0., 0.].into(), // 左上
+            [1.0, 1.0, 1., 0.].into(), // 右上
+            [1.0, -1., 1., 1.].into(), // 右下
+            [-1., -1., 0., 1.].into(), // 左下
+        ],
+        [0, 1, 2, 0, 2, 3],
+    )
+}
+
 impl Render {
     pub fn new(
-        state: &State,
-        vertices: &[Vertex],
-        indicens: &[u16],
+        sim: &Simulator,
+        format: wgpu::TextureFormat,
         view_proj: glam::Mat4,
+        color_map: &ColorMap,
+        gradient: &Gradient,
+        grid: &Grid,
     ) -> Self {
+        let storage = sim.storage();
+        let (vertices, indicens) = map_quad();
+
+        // 纹理和采样器，或者按位储存的缓冲区和地图大小，然后是摄像机、颜色表、热度图、网格线和金字塔
+        let map_entries = match storage {
+            Storage::Texture => [
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::all(),
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::all(),
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            Storage::Packed => [
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        };
         let render_bind_group_layout =
-            state
-                .device
+            sim.device
                 .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                     label: None,
                     entries: &[
+                        map_entries[0],
+                        map_entries[1],
                         wgpu::BindGroupLayoutEntry {
-                            binding: 0,
+                            binding: 2,
                             visibility: wgpu::ShaderStages::all(),
-                            ty: wgpu::BindingType::Texture {
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                multisampled: false,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
                             },
                             count: None,
                         },
                         wgpu::BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: wgpu::ShaderStages::all(),
-                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                            binding: 3,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
                             count: None,
                         },
                         wgpu::BindGroupLayoutEntry {
-                            binding: 2,
-                            visibility: wgpu::ShaderStages::all(),
+                            binding: 4,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 5,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 6,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 7,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Uint,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 8,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
                             ty: wgpu::BindingType::Buffer {
                                 ty: wgpu::BufferBindingType::Uniform,
                                 has_dynamic_offset: false,
@@ -55,23 +176,38 @@ impl Render {
                     ],
                 });
 
-        let vertex_buffer = state
+        // 按位储存时片段着色器需要地图的大小来找到细胞所在的位
+        let map_size = sim.map_size();
+        let map_size_uniform = sim
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&[
+                    map_size.0,
+                    map_size.1,
+                    map_size.0 / PackedCells::CELLS_PER_WORD,
+                    0,
+                ]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+
+        let vertex_buffer = sim
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: None,
-                contents: bytemuck::cast_slice(vertices),
+                contents: bytemuck::cast_slice(&vertices),
                 usage: wgpu::BufferUsages::VERTEX,
             });
 
-        let index_buffer = state
+        let index_buffer = sim
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: None,
-                contents: bytemuck::cast_slice(indicens),
+                contents: bytemuck::cast_slice(&indicens),
                 usage: wgpu::BufferUsages::INDEX,
             });
 
-        let camera_uniform = state
+        let camera_uniform = sim
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: None,
@@ -79,22 +215,77 @@ impl Render {
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             });
 
-        let render_shader_module = state
+        let color_map_uniform = sim
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::bytes_of(color_map),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        // 窗口在渲染时按模拟器的热度图设置
+        let heatmap_uniform = sim
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::bytes_of(gradient),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+        let grid_uniform = sim
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::bytes_of(grid),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+        let empty_heat = sim.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        // 显示方式见 `set_density`，层数在渲染时按模拟器的金字塔设置
+        let zoom_uniform = sim
             .device
-            .create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&[Density::default() as u32, 0, 0, 0]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+        let empty_pyramid = sim
+            .device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Pyramid::FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&Default::default());
+
+        let render_shader_module = sim.device.create_shader_module(match storage {
+            Storage::Texture => wgpu::include_wgsl!("shader.wgsl"),
+            Storage::Packed => wgpu::include_wgsl!("shader_packed.wgsl"),
+        });
 
         let render_pipeline = {
             let pipeline_layout =
-                state
-                    .device
+                sim.device
                     .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                         label: None,
                         bind_group_layouts: &[&render_bind_group_layout],
                         push_constant_ranges: &[],
                     });
 
-            state
-                .device
+            sim.device
                 .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                     label: None,
                     layout: Some(&pipeline_layout),
@@ -107,7 +298,7 @@ impl Render {
                         module: &render_shader_module,
                         entry_point: "fs_main",
                         targets: &[Some(wgpu::ColorTargetState {
-                            format: state.config.format,
+                            format,
                             blend: Some(wgpu::BlendState {
                                 color: wgpu::BlendComponent::REPLACE,
                                 alpha: wgpu::BlendComponent::REPLACE,
@@ -128,30 +319,159 @@ impl Render {
             index_buffer,
             indicens_len: indicens.len() as u32,
             camera_uniform,
+            map_size_uniform,
+            color_map_uniform,
+            heatmap_uniform,
+            grid_uniform,
+            empty_heat,
+            zoom_uniform,
+            empty_pyramid,
+            format,
         }
     }
 
-    pub fn update_camera_uniform(&self, state: &State, view_proj: glam::Mat4) {
-        state.queue.write_buffer(
+    pub fn update_camera_uniform(&self, sim: &Simulator, view_proj: glam::Mat4) {
+        sim.queue.write_buffer(
             &self.camera_uniform,
             0,
             bytemuck::cast_slice(&view_proj.to_cols_array()),
         );
     }
 
-    pub fn render(&self, state: &State, [texture1, texture2]: &[Texture; 2]) {
-        let gen_render_binding_group = |texture: &Texture| {
-            state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+    /// 换一个颜色表，下一帧生效
+    pub fn set_color_map(&self, sim: &Simulator, color_map: &ColorMap) {
+        sim.queue
+            .write_buffer(&self.color_map_uniform, 0, bytemuck::bytes_of(color_map));
+    }
+
+    /// 换一种网格线，下一帧生效
+    pub fn set_grid(&self, sim: &Simulator, grid: &Grid) {
+        sim.queue
+            .write_buffer(&self.grid_uniform, 0, bytemuck::bytes_of(grid));
+    }
+
+    /// 换一种缩小显示方式，下一帧生效
+    pub fn set_density(&self, sim: &Simulator, density: Density) {
+        sim.queue
+            .write_buffer(&self.zoom_uniform, 0, bytemuck::bytes_of(&(density as u32)));
+    }
+
+    /// 渲染到窗口并显示
+    pub fn render(&self, state: &State) {
+        let frame = state.surface.get_current_texture().unwrap();
+        let view = frame.texture.create_view(&Default::default());
+        self.draw(&state.sim, &view);
+        frame.present();
+    }
+
+    /// 用和窗口一样的格式渲染到屏幕外的纹理，读回 `size` 大小的 RGBA 图片，录制时用
+    ///
+    /// 不需要窗口，会阻塞到GPU完成渲染为止
+    pub fn capture(&self, sim: &Simulator, size: (u32, u32)) -> Result<Vec<u8>> {
+        let extent = wgpu::Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        };
+        let texture = sim.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        self.draw(sim, &texture.create_view(&Default::default()));
+
+        // 复制到缓冲区时每行的字节数必须对齐
+        let unpadded_bytes_per_row = size.0 * 4;
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = sim.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (padded_bytes_per_row * size.1) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = sim.device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(size.1),
+                },
+            },
+            extent,
+        );
+        sim.queue.submit(Some(encoder.finish()));
+
+        let mut rgba: Vec<u8> = map_read(&sim.device, &buffer)?
+            .chunks(padded_bytes_per_row as usize)
+            .flat_map(|row| row[..unpadded_bytes_per_row as usize].to_vec())
+            .collect();
+        // 窗口常用 BGRA 格式
+        if matches!(
+            self.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in rgba.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Ok(rgba)
+    }
+
+    /// 渲染当前这一代到 `view`，格式必须是创建时的格式
+    fn draw(&self, sim: &Simulator, view: &wgpu::TextureView) {
+        // 没有热度图时窗口为 0，着色器不会读取热度
+        let (heat, window) = match sim.heatmap() {
+            Some(heatmap) => (&heatmap.buffer, heatmap.window()),
+            None => (&self.empty_heat, 0),
+        };
+        sim.queue.write_buffer(
+            &self.heatmap_uniform,
+            std::mem::offset_of!(Gradient, window) as wgpu::BufferAddress,
+            bytemuck::bytes_of(&window),
+        );
+        // 没有金字塔时层数为 0，着色器只用最近邻采样
+        let (pyramid, levels) = match sim.pyramid() {
+            Some(pyramid) => (&pyramid.view, pyramid.level_count()),
+            None => (&self.empty_pyramid, 0),
+        };
+        sim.queue.write_buffer(
+            &self.zoom_uniform,
+            std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            bytemuck::bytes_of(&levels),
+        );
+
+        let gen_render_binding_group = |cells: Cells| {
+            let [map_resource0, map_resource1] = match cells {
+                Cells::Texture(texture) => [
+                    wgpu::BindingResource::TextureView(&texture.view),
+                    wgpu::BindingResource::Sampler(&texture.sampler),
+                ],
+                Cells::Packed(cells) => [
+                    cells.buffer.as_entire_binding(),
+                    self.map_size_uniform.as_entire_binding(),
+                ],
+            };
+            sim.device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: None,
                 layout: &self.render_bind_group_layout,
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&texture.view),
+                        resource: map_resource0,
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                        resource: map_resource1,
                     },
                     wgpu::BindGroupEntry {
                         binding: 2,
@@ -159,28 +479,46 @@ impl Render {
                             self.camera_uniform.as_entire_buffer_binding(),
                         ),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: self.color_map_uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: heat.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: self.heatmap_uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: self.grid_uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: wgpu::BindingResource::TextureView(pyramid),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 8,
+                        resource: self.zoom_uniform.as_entire_binding(),
+                    },
                 ],
             })
         };
 
         // 渲染部分不参与翻转texture
         // cycle_render_binding_group = !cycle_render_binding_group;
-        let render_bind_group = if state.cycle_render_binding_group {
-            gen_render_binding_group(texture2)
-        } else {
-            gen_render_binding_group(texture1)
-        };
+        let render_bind_group = gen_render_binding_group(sim.current_cells());
 
-        let frame = state.surface.get_current_texture().unwrap();
-        let view = frame.texture.create_view(&Default::default());
-        let mut encoder = state
+        let mut encoder = sim
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
         {
             let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -203,7 +541,68 @@ impl Render {
             rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
             rpass.draw_indexed(0..self.indicens_len, 0, 0..1);
         }
-        state.queue.submit(Some(encoder.finish()));
-        frame.present();
+        sim.queue.submit(Some(encoder.finish()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use life_game_wgpu::{rule::Rule, topology::Topology, Engine};
+
+    use super::*;
+
+    #[test]
+    fn heatmap_when_zoomed_out() {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        if pollster::block_on(life_game_wgpu::request_adapter(&instance, None)).is_err() {
+            return;
+        }
+        drop(instance);
+
+        for storage in [Storage::Texture, Storage::Packed] {
+            let mut sim = pollster::block_on(Simulator::new(
+                (256, 256),
+                Rule::CONWAY,
+                Topology::Dead,
+                storage,
+            ))
+            .unwrap();
+            if sim.enable_heatmap(4).is_err() || sim.enable_pyramid().is_err() {
+                assert!(sim.is_cpu_fallback());
+                return;
+            }
+            // 闪烁器的两种相位都在第 12 行第 12 列的像素里
+            for x in 100..103 {
+                sim.set_cell((x, 100), true).unwrap();
+            }
+            sim.step(3);
+
+            // 整张地图画在 32x32 的图片上，一个像素覆盖 8x8 格
+            let capture = |gradient: &Gradient| {
+                let render = Render::new(
+                    &sim,
+                    wgpu::TextureFormat::Rgba8Unorm,
+                    glam::Mat4::IDENTITY,
+                    &ColorMap::default(),
+                    gradient,
+                    &Grid::OFF,
+                );
+                render.capture(&sim, (32, 32)).unwrap()
+            };
+            let mut hidden = Gradient::default();
+            hidden.opacity = 0.0;
+            let (with_heat, without) = (capture(&Gradient::default()), capture(&hidden));
+            let changed: Vec<_> = with_heat
+                .chunks(4)
+                .zip(without.chunks(4))
+                .enumerate()
+                .filter(|(_, (a, b))| a != b)
+                .map(|(i, _)| (i % 32, i / 32))
+                .collect();
+            assert_eq!(changed, [(12, 12)], "{storage:?}");
+        }
     }
 }
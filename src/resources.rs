@@ -1,5 +1,3 @@
-use crate::State;
-
 #[repr(C)]
 #[derive(Default, Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
@@ -32,21 +30,236 @@ impl From<[f32; 4]> for Vertex {
         [[position_x, position_y], [uvx, uvy]].into()
     }
 }
+// 纹理中每个纹素的四个通道：
+// r: 255 表示活细胞
+// g: 活细胞已经存活的代数，最多 255
+// b: 死细胞的尾迹，刚死去时是 255，之后每代减一
+// a: 和 r 相同
+
+/// 活细胞在纹理中的值，编辑出来的细胞算作刚出生
+pub const ALIVE: [u8; 4] = [255, 0, 0, 255];
+/// 死细胞在纹理中的值，没有尾迹
+pub const DEAD: [u8; 4] = [0, 0, 0, 0];
+
+/// 活细胞已经存活的代数，死细胞为 `None`
+pub fn age([alive, age, _, _]: [u8; 4]) -> Option<u8> {
+    (alive > 0).then_some(age)
+}
+
+/// 死细胞的尾迹，刚死去时为 255，活细胞和很久以前死去的细胞为 0
+pub fn trail([alive, _, trail, _]: [u8; 4]) -> u8 {
+    if alive > 0 {
+        0
+    } else {
+        trail
+    }
+}
+
+/// 片段着色器中的颜色表，和 `shader.wgsl` 中的 `ColorMap` 对应
+///
+/// 活细胞从 `newborn` 渐变到 `stable`，死细胞从 `trail` 渐变到 `background`。
+/// 按位储存时没有年龄和尾迹，活细胞都用 `stable`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ColorMap {
+    /// 刚出生的细胞
+    pub newborn: [f32; 4],
+    /// 存活了 `stable_age` 代以上的细胞
+    pub stable: [f32; 4],
+    /// 刚死去的细胞
+    pub trail: [f32; 4],
+    /// 死了很久或者从来没有活过的细胞
+    pub background: [f32; 4],
+    /// 从 `newborn` 渐变到 `stable` 的代数，最多 255
+    pub stable_age: f32,
+    /// 尾迹持续的代数，最多 255，为 0 时没有尾迹
+    pub trail_length: f32,
+    _padding: [f32; 2],
+}
+
+impl ColorMap {
+    /// 只有黑白两色
+    pub const MONOCHROME: Self = Self {
+        newborn: [1.0, 1.0, 1.0, 1.0],
+        stable: [1.0, 1.0, 1.0, 1.0],
+        trail: [0.0, 0.0, 0.0, 1.0],
+        background: [0.0, 0.0, 0.0, 1.0],
+        stable_age: 1.0,
+        trail_length: 0.0,
+        _padding: [0.0; 2],
+    };
+
+    /// 新生的细胞是黄色，稳定的区域是蓝色，刚死去的细胞留下暗红色的尾迹
+    pub const AGE: Self = Self {
+        newborn: [1.0, 0.9, 0.3, 1.0],
+        stable: [0.2, 0.5, 1.0, 1.0],
+        trail: [0.5, 0.1, 0.1, 1.0],
+        background: [0.0, 0.0, 0.0, 1.0],
+        stable_age: 16.0,
+        trail_length: 32.0,
+        _padding: [0.0; 2],
+    };
+}
+
+impl Default for ColorMap {
+    fn default() -> Self {
+        Self::AGE
+    }
+}
+
+/// 放大时格子之间的网格线，和 `shader.wgsl` 中的 `Grid` 对应
+///
+/// 每格在屏幕上的像素数由纹理坐标的屏幕空间导数得到，超过 `min_cell_pixels` 时才画线
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Grid {
+    /// 格子之间的线，alpha 是不透明度
+    pub color: [f32; 4],
+    /// 每隔 `major_every` 格的主线
+    pub major_color: [f32; 4],
+    /// 每格至少占多少像素时画线
+    pub min_cell_pixels: f32,
+    /// 每隔多少格画一条主线，为 0 时不画
+    pub major_every: u32,
+    enabled: u32,
+    _padding: u32,
+}
+
+impl Grid {
+    /// 不画线
+    pub const OFF: Self = Self {
+        enabled: 0,
+        ..Self::LINES
+    };
+
+    /// 只在格子之间画线
+    pub const LINES: Self = Self {
+        color: [0.3, 0.3, 0.3, 1.0],
+        major_color: [0.6, 0.6, 0.6, 1.0],
+        min_cell_pixels: 6.0,
+        major_every: 0,
+        enabled: 1,
+        _padding: 0,
+    };
+
+    /// 另外每隔 10 格画一条更亮的主线
+    pub const MAJOR_LINES: Self = Self {
+        major_every: 10,
+        ..Self::LINES
+    };
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled != 0
+    }
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self::LINES
+    }
+}
+
+/// 热度图的渐变色，和 `shader.wgsl` 中的 `Heatmap` 对应
+///
+/// 窗口内变化的次数从少到多，颜色依次经过各个色标，再按 `opacity` 叠加到地图上
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Gradient {
+    stops: [[f32; 4]; Gradient::MAX_STOPS],
+    stop_count: u32,
+    /// 不透明度，0 到 1
+    pub opacity: f32,
+    /// 数变化次数的窗口，由渲染时的热度图决定，为 0 时不显示
+    pub window: u32,
+    _padding: u32,
+}
+
+impl Gradient {
+    /// 最多的色标数
+    pub const MAX_STOPS: usize = 4;
+
+    /// 从蓝色经过黄色到红色
+    pub const HEAT: Self = Self {
+        stops: [
+            [0.0, 0.2, 1.0, 1.0],
+            [1.0, 0.9, 0.0, 1.0],
+            [1.0, 0.1, 0.0, 1.0],
+            [0.0; 4],
+        ],
+        stop_count: 3,
+        opacity: 0.7,
+        window: 0,
+        _padding: 0,
+    };
+
+    /// 2 到 `MAX_STOPS` 个色标，均匀地分布在渐变中
+    pub fn new(stops: &[[f32; 4]], opacity: f32) -> Result<Self, Box<dyn std::error::Error>> {
+        if !(2..=Self::MAX_STOPS).contains(&stops.len()) {
+            return Err(format!("渐变需要 2 到 {} 个颜色", Self::MAX_STOPS).into());
+        }
+        let mut gradient = Self {
+            stop_count: stops.len() as u32,
+            opacity,
+            ..Self::HEAT
+        };
+        gradient.stops = [[0.0; 4]; Self::MAX_STOPS];
+        gradient.stops[..stops.len()].copy_from_slice(stops);
+        Ok(gradient)
+    }
+
+    pub fn stops(&self) -> &[[f32; 4]] {
+        &self.stops[..self.stop_count as usize]
+    }
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Self::HEAT
+    }
+}
+
+impl std::str::FromStr for Gradient {
+    type Err = Box<dyn std::error::Error>;
+
+    /// 逗号分隔的 `#rrggbb` 或者 `#rrggbbaa`，例如 `#0033ff,#ffe600,#ff1a00`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let stops = s
+            .split(',')
+            .map(|color| {
+                let color = color.trim();
+                let hex = color.strip_prefix('#').unwrap_or(color);
+                if !matches!(hex.len(), 6 | 8) {
+                    return Err(format!("颜色 `{color}` 应写成 `#rrggbb` 或者 `#rrggbbaa`"));
+                }
+                let mut rgba = [1.0; 4];
+                for (channel, value) in rgba.iter_mut().zip((0..hex.len()).step_by(2)) {
+                    let byte = u8::from_str_radix(&hex[value..value + 2], 16)
+                        .map_err(|_| format!("颜色 `{color}` 不是有效的十六进制数"))?;
+                    *channel = byte as f32 / 255.0;
+                }
+                Ok(rgba)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(&stops, Self::HEAT.opacity)
+    }
+}
+
 pub struct Texture {
     pub sampler: wgpu::Sampler,
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
+    pub size: (u32, u32),
 }
 
 impl Texture {
-    pub fn new(state: &State, map_size: (u32, u32)) -> Texture {
+    pub fn new(device: &wgpu::Device, map_size: (u32, u32)) -> Texture {
         let texture_size = wgpu::Extent3d {
             width: map_size.0,
             height: map_size.1,
             depth_or_array_layers: 1,
         };
 
-        let texture = state.device.create_texture(&wgpu::TextureDescriptor {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size: texture_size,
             mip_level_count: 1,
@@ -54,6 +267,7 @@ impl Texture {
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8Unorm,
             usage: wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
                 | wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::STORAGE_BINDING,
             view_formats: &[],
@@ -61,7 +275,7 @@ impl Texture {
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-        let sampler = state.device.create_sampler(&wgpu::SamplerDescriptor {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
@@ -74,6 +288,207 @@ impl Texture {
             sampler,
             texture,
             view,
+            size: map_size,
+        }
+    }
+
+    /// 写入一块矩形区域，`origin` 是区域左上角的位置
+    pub fn write(
+        &self,
+        queue: &wgpu::Queue,
+        origin: (u32, u32),
+        size: (u32, u32),
+        data: &[[u8; 4]],
+    ) {
+        queue.write_texture(
+            wgpu::ImageCopyTextureBase {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: origin.0,
+                    y: origin.1,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(data),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.0 * std::mem::size_of::<u32>() as u32),
+                rows_per_image: Some(size.1),
+            },
+            wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// 把纹理的内容读回内存，会阻塞到GPU完成复制为止
+    pub fn read(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Vec<[u8; 4]>, wgpu::BufferAsyncError> {
+        self.read_region(device, queue, (0, 0), self.size)
+    }
+
+    /// 只读回 `origin` 开始、`size` 大小的一块矩形区域，按行排列
+    pub fn read_region(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        origin: (u32, u32),
+        (width, height): (u32, u32),
+    ) -> Result<Vec<[u8; 4]>, wgpu::BufferAsyncError> {
+        // 复制到缓冲区时每行的字节数必须对齐
+        let unpadded_bytes_per_row = width * std::mem::size_of::<[u8; 4]>() as u32;
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: origin.0,
+                    y: origin.1,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let data = map_read(device, &buffer)?
+            .chunks(padded_bytes_per_row as usize)
+            .flat_map(|row| bytemuck::cast_slice(&row[..unpadded_bytes_per_row as usize]).to_vec())
+            .collect();
+        Ok(data)
+    }
+}
+
+/// 映射一个 `MAP_READ` 的缓冲区并复制出全部内容，会阻塞到GPU完成之前提交的命令为止
+pub fn map_read(
+    device: &wgpu::Device,
+    buffer: &wgpu::Buffer,
+) -> Result<Vec<u8>, wgpu::BufferAsyncError> {
+    let slice = buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv().unwrap_or(Err(wgpu::BufferAsyncError))?;
+
+    let data = slice.get_mapped_range().to_vec();
+    buffer.unmap();
+    Ok(data)
+}
+
+/// 按位储存在存储缓冲区中的地图
+///
+/// 每行占 `width / 32` 个 `u32`，第 `x` 列的细胞是第 `x / 32` 个字的第 `x % 32` 位
+pub struct PackedCells {
+    pub buffer: wgpu::Buffer,
+    pub size: (u32, u32),
+}
+
+impl PackedCells {
+    /// 一个字中的细胞数
+    pub const CELLS_PER_WORD: u32 = u32::BITS;
+
+    /// `map_size.0` 必须是 32 的倍数
+    pub fn new(device: &wgpu::Device, map_size: (u32, u32)) -> PackedCells {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: Self::byte_size(map_size),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        PackedCells {
+            buffer,
+            size: map_size,
         }
     }
+
+    /// 整张地图占用的字节数
+    pub fn byte_size(map_size: (u32, u32)) -> wgpu::BufferAddress {
+        (map_size.0 / Self::CELLS_PER_WORD) as wgpu::BufferAddress
+            * map_size.1 as wgpu::BufferAddress
+            * std::mem::size_of::<u32>() as wgpu::BufferAddress
+    }
+
+    /// 每行的字数
+    pub fn words_per_row(&self) -> u32 {
+        self.size.0 / Self::CELLS_PER_WORD
+    }
+
+    /// 从第 `first_row` 行开始写入若干整行
+    pub fn write_rows(&self, queue: &wgpu::Queue, first_row: u32, words: &[u32]) {
+        let offset = first_row as wgpu::BufferAddress
+            * self.words_per_row() as wgpu::BufferAddress
+            * std::mem::size_of::<u32>() as wgpu::BufferAddress;
+        queue.write_buffer(&self.buffer, offset, bytemuck::cast_slice(words));
+    }
+
+    /// 读回 `rows` 范围内的整行，会阻塞到GPU完成复制为止
+    pub fn read_rows(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rows: std::ops::Range<u32>,
+    ) -> Result<Vec<u32>, wgpu::BufferAsyncError> {
+        let bytes_per_row = self.words_per_row() as wgpu::BufferAddress
+            * std::mem::size_of::<u32>() as wgpu::BufferAddress;
+        let size = bytes_per_row * rows.len() as wgpu::BufferAddress;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(
+            &self.buffer,
+            rows.start as wgpu::BufferAddress * bytes_per_row,
+            &buffer,
+            0,
+            size,
+        );
+        queue.submit(Some(encoder.finish()));
+
+        // 映射出来的字节不一定按 `u32` 对齐，不能直接转换
+        Ok(map_read(device, &buffer)?
+            .chunks_exact(std::mem::size_of::<u32>())
+            .map(|word| u32::from_ne_bytes(word.try_into().unwrap()))
+            .collect())
+    }
 }
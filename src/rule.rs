@@ -0,0 +1,237 @@
+use std::{fmt, str::FromStr};
+
+/// 生命游戏的演化规则（B/S 表示法）
+///
+/// `birth` 和 `survival` 都是位掩码：第 `n` 位为 1 表示邻居数为 `n` 时出生/存活
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rule {
+    pub birth: u16,
+    pub survival: u16,
+}
+
+impl Rule {
+    /// 康威生命游戏 B3/S23
+    pub const CONWAY: Rule = Rule {
+        birth: 1 << 3,
+        survival: 1 << 2 | 1 << 3,
+    };
+
+    /// 传给着色器的 uniform 数据
+    pub fn to_uniform(self) -> [u32; 4] {
+        [self.birth as u32, self.survival as u32, 0, 0]
+    }
+
+    /// 邻居数为 `neighbors` 时，细胞下一代是否存活
+    pub fn next_state(self, alive: bool, neighbors: u32) -> bool {
+        let mask = if alive { self.survival } else { self.birth };
+        mask >> neighbors & 1 == 1
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Self::CONWAY
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = |mask: u16| {
+            (0..=8)
+                .filter(|n| mask >> n & 1 == 1)
+                .map(|n| char::from(b'0' + n as u8))
+                .collect::<String>()
+        };
+        write!(f, "B{}/S{}", digits(self.birth), digits(self.survival))
+    }
+}
+
+/// 解析规则字符串时的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseRuleError {
+    /// 规则字符串为空
+    Empty,
+    /// 邻居数只能是 0 到 8
+    InvalidDigit(char),
+    /// 出现了 B、S、数字和 `/` 以外的字符
+    UnexpectedChar(char),
+    /// 同一个部分（B 或者 S）出现了两次
+    Duplicate(char),
+    /// 不带 B/S 前缀的写法必须是 `存活/出生` 两段
+    MissingSlash,
+}
+
+impl fmt::Display for ParseRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseRuleError::Empty => write!(f, "规则字符串为空"),
+            ParseRuleError::InvalidDigit(c) => {
+                write!(f, "邻居数 `{c}` 无效，只能是 0 到 8")
+            }
+            ParseRuleError::UnexpectedChar(c) => {
+                write!(f, "规则中出现了无法识别的字符 `{c}`")
+            }
+            ParseRuleError::Duplicate(c) => write!(f, "规则中 `{c}` 部分出现了两次"),
+            ParseRuleError::MissingSlash => {
+                write!(f, "缺少 `/`，应写成 `B3/S23` 或者 `23/3` 的形式")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseRuleError {}
+
+/// 把一串数字解析成位掩码
+fn parse_digits(digits: &str) -> Result<u16, ParseRuleError> {
+    digits.chars().try_fold(0u16, |mask, c| match c.to_digit(10) {
+        Some(n @ 0..=8) => Ok(mask | 1 << n),
+        Some(_) => Err(ParseRuleError::InvalidDigit(c)),
+        None => Err(ParseRuleError::UnexpectedChar(c)),
+    })
+}
+
+impl FromStr for Rule {
+    type Err = ParseRuleError;
+
+    /// 支持以下写法（不区分大小写）：
+    ///
+    /// * `B36/S23`、`S23/B36`、`B36S23`（Golly 风格）
+    /// * `23/36`（传统写法，先存活后出生）
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(ParseRuleError::Empty);
+        }
+
+        // 传统写法：没有 B/S 前缀
+        if !s.chars().any(|c| c.is_ascii_alphabetic()) {
+            let (survival, birth) = s.split_once('/').ok_or(ParseRuleError::MissingSlash)?;
+            return Ok(Rule {
+                birth: parse_digits(birth)?,
+                survival: parse_digits(survival)?,
+            });
+        }
+
+        let mut birth = None;
+        let mut survival = None;
+        // 当前正在读取的部分
+        let mut current: Option<(char, String)> = None;
+
+        let mut finish = |part: Option<(char, String)>| -> Result<(), ParseRuleError> {
+            if let Some((name, digits)) = part {
+                let slot = if name == 'B' { &mut birth } else { &mut survival };
+                if slot.is_some() {
+                    return Err(ParseRuleError::Duplicate(name));
+                }
+                *slot = Some(parse_digits(&digits)?);
+            }
+            Ok(())
+        };
+
+        for c in s.chars() {
+            match c.to_ascii_uppercase() {
+                name @ ('B' | 'S') => finish(current.replace((name, String::new())))?,
+                '/' => finish(current.take())?,
+                d if d.is_ascii_digit() => match &mut current {
+                    Some((_, digits)) => digits.push(d),
+                    None => return Err(ParseRuleError::UnexpectedChar(c)),
+                },
+                _ => return Err(ParseRuleError::UnexpectedChar(c)),
+            }
+        }
+        finish(current)?;
+
+        Ok(Rule {
+            birth: birth.unwrap_or(0),
+            survival: survival.unwrap_or(0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 由邻居数组成的规则
+    fn rule(birth: &[u16], survival: &[u16]) -> Rule {
+        Rule {
+            birth: birth.iter().map(|n| 1 << n).sum(),
+            survival: survival.iter().map(|n| 1 << n).sum(),
+        }
+    }
+
+    #[test]
+    fn notations() {
+        let high_life = rule(&[3, 6], &[2, 3]);
+        for text in ["B36/S23", "S23/B36", "B36S23", "b36s23", " 23/36 ", "S23B36"] {
+            assert_eq!(text.parse(), Ok(high_life), "{text}");
+        }
+        assert_eq!("B3/S23".parse(), Ok(Rule::CONWAY));
+        assert_eq!("23/3".parse(), Ok(Rule::CONWAY));
+        // 空的部分和缺少的部分都是没有邻居数
+        assert_eq!("B/S012345678".parse(), Ok(rule(&[], &[0, 1, 2, 3, 4, 5, 6, 7, 8])));
+        assert_eq!("B2".parse(), Ok(rule(&[2], &[])));
+        assert_eq!("/2".parse(), Ok(rule(&[2], &[])));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for text in ["B3/S23", "B36/S23", "B/S", "B012345678/S012345678"] {
+            let rule: Rule = text.parse().unwrap();
+            assert_eq!(rule.to_string(), text);
+        }
+        assert_eq!("23/36".parse::<Rule>().unwrap().to_string(), "B36/S23");
+    }
+
+    #[test]
+    fn errors() {
+        for (text, error, message) in [
+            ("", ParseRuleError::Empty, "规则字符串为空"),
+            ("  ", ParseRuleError::Empty, "规则字符串为空"),
+            (
+                "B39/S23",
+                ParseRuleError::InvalidDigit('9'),
+                "邻居数 `9` 无效，只能是 0 到 8",
+            ),
+            (
+                "23/9",
+                ParseRuleError::InvalidDigit('9'),
+                "邻居数 `9` 无效，只能是 0 到 8",
+            ),
+            (
+                "B3/S2x",
+                ParseRuleError::UnexpectedChar('x'),
+                "规则中出现了无法识别的字符 `x`",
+            ),
+            (
+                "3B/S23",
+                ParseRuleError::UnexpectedChar('3'),
+                "规则中出现了无法识别的字符 `3`",
+            ),
+            (
+                "2-3/3",
+                ParseRuleError::UnexpectedChar('-'),
+                "规则中出现了无法识别的字符 `-`",
+            ),
+            (
+                "B3/S23/B6",
+                ParseRuleError::Duplicate('B'),
+                "规则中 `B` 部分出现了两次",
+            ),
+            (
+                "s23S2b3",
+                ParseRuleError::Duplicate('S'),
+                "规则中 `S` 部分出现了两次",
+            ),
+            (
+                "233",
+                ParseRuleError::MissingSlash,
+                "缺少 `/`，应写成 `B3/S23` 或者 `23/3` 的形式",
+            ),
+        ] {
+            let result = text.parse::<Rule>();
+            assert_eq!(result, Err(error), "{text:?}");
+            assert_eq!(result.unwrap_err().to_string(), message, "{text:?}");
+        }
+    }
+}
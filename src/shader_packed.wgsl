@@ -0,0 +1,206 @@
+// 按位储存的地图的显示，顶点部分和 `shader.wgsl` 相同
+struct VertexInput {
+    @location(0) position: vec2f,
+    @location(1) texcorrd: vec2f,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) texcorrd: vec2f,
+}
+
+// 每行由连续的 u32 组成，第 x 列的细胞是第 x / 32 个字的第 x % 32 位
+@group(0) @binding(0)
+var<storage, read> map_cells : array<u32>;
+
+struct MapSize {
+    map_size: vec2u,
+    // 每行的字数
+    words_per_row: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(1)
+var<uniform> map_size : MapSize;
+
+struct CameraUnifrorm {
+    view_proj: mat4x4f,
+}
+
+@group(0) @binding(2)
+var<uniform> camera : CameraUnifrorm;
+
+// 颜色表，见 `resources.rs` 中的 `ColorMap`
+struct ColorMap {
+    newborn: vec4f,
+    stable: vec4f,
+    trail: vec4f,
+    background: vec4f,
+    stable_age: f32,
+    trail_length: f32,
+    _padding: vec2f,
+}
+
+@group(0) @binding(3)
+var<uniform> color_map : ColorMap;
+
+// 热度图，见 `resources.rs` 中的 `Gradient`
+struct Heatmap {
+    stops: array<vec4f, 4>,
+    stop_count: u32,
+    opacity: f32,
+    // 为 0 时不显示
+    window: u32,
+    _padding: u32,
+}
+
+// 每个细胞最近 32 代的变化，见 `heatmap.wgsl`
+@group(0) @binding(4)
+var<storage, read> heat : array<u32>;
+
+@group(0) @binding(5)
+var<uniform> heatmap : Heatmap;
+
+// 网格线，见 `resources.rs` 中的 `Grid`
+struct Grid {
+    color: vec4f,
+    major_color: vec4f,
+    min_cell_pixels: f32,
+    // 每隔多少格画一条主线，为 0 时不画
+    major_every: u32,
+    enabled: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(6)
+var<uniform> grid : Grid;
+
+// 缩小显示用的金字塔，见 `pyramid.wgsl`，每个纹素用 `pack2x16float` 存放每块的最大值和平均值
+@group(0) @binding(7)
+var pyramid : texture_2d<u32>;
+
+struct Zoom {
+    // 0 显示最大值，1 显示平均值
+    density: u32,
+    // 金字塔的层数，为 0 时没有金字塔
+    levels: u32,
+    _padding: vec2u,
+}
+
+@group(0) @binding(8)
+var<uniform> zoom : Zoom;
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4(in.position, 0.0, 1.0);
+    out.texcorrd = in.texcorrd;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    // 和纹理的最近邻采样一样，取纹理坐标所在的格子
+    let size = map_size.map_size;
+    let position = in.texcorrd * vec2f(size);
+    let cells_per_pixel = fwidth(position);
+    // 一个像素覆盖不止一格时改为显示金字塔中的密度，热度取这一块中最热的细胞
+    let scale = max(cells_per_pixel.x, cells_per_pixel.y);
+    if zoom.levels > 0u && scale > 1.0 {
+        let color = zoomed_out(position, scale, size);
+        return grid_lines(color, position, cells_per_pixel);
+    }
+    let cell = min(vec2u(position), size - 1u);
+    let word = map_cells[cell.y * map_size.words_per_row + cell.x / 32u];
+    let life = ((word >> (cell.x % 32u)) & 1u) == 1u;
+
+    // 按位储存时没有年龄和尾迹
+    let color = select(color_map.background, color_map.stable, life);
+    return grid_lines(overlay(color, cell.y * size.x + cell.x), position, cells_per_pixel);
+}
+
+// 把第 `index` 个细胞的热度叠加到 `color` 上
+fn overlay(color: vec4f, index: u32) -> vec4f {
+    if heatmap.window == 0u {
+        return color;
+    }
+    return heat_overlay(color, changes(index));
+}
+
+// 第 `index` 个细胞在窗口内变化的次数，窗口不能为 0
+fn changes(index: u32) -> u32 {
+    return countOneBits(heat[index] & (0xffffffffu >> (32u - heatmap.window)));
+}
+
+// 按变化的次数从渐变中取色，叠加到 `color` 上
+fn heat_overlay(color: vec4f, changes: u32) -> vec4f {
+    if changes == 0u {
+        return color;
+    }
+    let position = f32(changes) / f32(heatmap.window) * f32(heatmap.stop_count - 1u);
+    let stop = min(u32(position), heatmap.stop_count - 2u);
+    let heat_color = mix(heatmap.stops[stop], heatmap.stops[stop + 1u], position - f32(stop));
+    return mix(color, vec4(heat_color.rgb, color.a), heatmap.opacity * heat_color.a);
+}
+
+// 缩小时每一块在每个方向上最多看这么多个细胞的热度
+const HEAT_SAMPLES: u32 = 8u;
+
+// 一个像素覆盖 `scale` 格时，从金字塔中取一块的密度，再叠加这一块的热度
+fn zoomed_out(position: vec2f, scale: f32, size: vec2u) -> vec4f {
+    // 第 level 层的一块是 2^(level+1) 见方，取不小于一个像素的那一层
+    let level = i32(min(u32(ceil(log2(scale))) - 1u, zoom.levels - 1u));
+    let block = 2u << u32(level);
+    let last = textureDimensions(pyramid, level) - 1u;
+    let coords = min(vec2u(position) / block, last);
+    let texel = unpack2x16float(textureLoad(pyramid, coords, level).r);
+    let density = select(texel.g, texel.r, zoom.density == 0u);
+    let color = mix(color_map.background, color_map.stable, density);
+    if heatmap.window == 0u {
+        return color;
+    }
+
+    // 和金字塔一样，最后一行和最后一列的块一直管到地图边上
+    let start = coords * block;
+    let end = select(min(start + block, size), size, coords == last);
+    // 块比较大时隔几格看一个，每个像素最多读 HEAT_SAMPLES^2 次
+    let step = max((end - start + HEAT_SAMPLES - 1u) / HEAT_SAMPLES, vec2(1u));
+    var hottest = 0u;
+    for (var y = start.y; y < end.y; y += step.y) {
+        for (var x = start.x; x < end.x; x += step.x) {
+            hottest = max(hottest, changes(y * size.x + x));
+        }
+    }
+    return heat_overlay(color, hottest);
+}
+
+// 在格子之间画线，`position` 是以格为单位的坐标，`cells_per_pixel` 是它在屏幕上的导数
+fn grid_lines(color: vec4f, position: vec2f, cells_per_pixel: vec2f) -> vec4f {
+    if grid.enabled == 0u {
+        return color;
+    }
+    let cell_pixels = 1.0 / max(cells_per_pixel.x, cells_per_pixel.y);
+    var result = color;
+    // 格子足够大时才画，刚过阈值时淡入
+    if cell_pixels >= grid.min_cell_pixels {
+        let fade = clamp(cell_pixels / grid.min_cell_pixels - 1.0, 0.0, 1.0);
+        if line_distance(position, cells_per_pixel, 1.0) < 1.0 {
+            result = mix(result, vec4(grid.color.rgb, result.a), grid.color.a * fade);
+        }
+    }
+    // 主线在每一块足够大时就画
+    let every = f32(grid.major_every);
+    if grid.major_every > 0u && cell_pixels * every >= grid.min_cell_pixels {
+        if line_distance(position, cells_per_pixel, every) < 1.0 {
+            result = mix(result, vec4(grid.major_color.rgb, result.a), grid.major_color.a);
+        }
+    }
+    return result;
+}
+
+// 到最近的每隔 `every` 格一条的线的距离，单位是像素
+fn line_distance(position: vec2f, cells_per_pixel: vec2f, every: f32) -> f32 {
+    let offset = fract(position / every);
+    let to_line = min(offset, 1.0 - offset) * every / cells_per_pixel;
+    return min(to_line.x, to_line.y);
+}
@@ -0,0 +1,1107 @@
+use std::path::Path;
+
+use crate::{
+    compute::Compute,
+    cpu::CpuEngine,
+    heatmap::Heatmap,
+    history::{History, Keyframe, KeyframeKind, Snapshot},
+    packed::PackedCompute,
+    pattern::{self, Pattern},
+    period::{Period, PeriodDetector},
+    pyramid::Pyramid,
+    resources::{PackedCells, Texture, ALIVE, DEAD},
+    rule::Rule,
+    snapshot::{self, View},
+    stats::{Reduction, Sample, Stats},
+    topology::Topology,
+    Engine, Result,
+};
+
+/// 细胞的储存方式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    /// 每个细胞是 `Rgba8Unorm` 纹理中的一个纹素
+    #[default]
+    Texture,
+    /// 每 32 个细胞打包成存储缓冲区中的一个 `u32`，占用的内存只有纹理的 1/32，
+    /// 地图的大小也不受纹理尺寸的限制
+    ///
+    /// 地图的宽度必须是 32 的倍数
+    Packed,
+}
+
+/// 演化使用的后端
+enum Backend {
+    /// 计算管线
+    Gpu {
+        compute: Box<Compute>,
+        textures: [Texture; 2],
+    },
+    /// 设备不支持计算管线时，在CPU上演化，再把结果上传到纹理
+    Cpu {
+        engine: CpuEngine,
+        textures: [Texture; 2],
+    },
+    /// 按位储存的计算管线
+    Packed {
+        compute: Box<PackedCompute>,
+        buffers: [PackedCells; 2],
+    },
+}
+
+/// 当前这一代的细胞所在的资源，渲染时绑定它
+pub enum Cells<'a> {
+    Texture(&'a Texture),
+    Packed(&'a PackedCells),
+}
+
+/// 不依赖窗口和展示平面的模拟器
+///
+/// 持有设备、命令队列、两份交替读写的地图和计算管线
+pub struct Simulator {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    backend: Backend,
+    map_size: (u32, u32),
+    rule: Rule,
+    topology: Topology,
+
+    /// 本质上是创建两个纹理 交替读写来进行更新
+    /// 通过来回取反这个量来做到翻转
+    cycle_render_binding_group: bool,
+
+    /// 当前是第几代
+    generation: u64,
+
+    /// 统计活细胞的计算管线，在CPU上演化时为 `None`
+    reduction: Option<Reduction>,
+    stats: Stats,
+
+    /// 倒退用的历史，见 `enable_history`
+    history: Option<History>,
+
+    /// 判断地图是否已经稳定，见 `enable_period_detection`
+    periods: Option<PeriodDetector>,
+
+    /// 热度图，见 `enable_heatmap`
+    heatmap: Option<Heatmap>,
+
+    /// 缩小显示用的金字塔，见 `enable_pyramid`
+    pyramid: Option<Pyramid>,
+}
+
+/// `backend` 中的两份地图中的一份
+fn cells(backend: &Backend, second: bool) -> Cells<'_> {
+    match backend {
+        Backend::Gpu { textures, .. } | Backend::Cpu { textures, .. } => {
+            Cells::Texture(pick(textures, second))
+        }
+        Backend::Packed { buffers, .. } => Cells::Packed(pick(buffers, second)),
+    }
+}
+
+/// 两份地图中的一份，`second` 为 `true` 时是第二份
+fn pick<T>([first, second_one]: &[T; 2], second: bool) -> &T {
+    if second {
+        second_one
+    } else {
+        first
+    }
+}
+
+/// 请求适配器，没有合适的硬件适配器时退回到软件实现的适配器
+pub async fn request_adapter(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface>,
+) -> Result<wgpu::Adapter> {
+    for force_fallback_adapter in [false, true] {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter,
+                compatible_surface,
+            })
+            .await;
+        if let Some(adapter) = adapter {
+            return Ok(adapter);
+        }
+    }
+    Err("没有合适的适配器".into())
+}
+
+/// 创建设备和命令队列，使用适配器支持的最大限制，这样地图的大小只受硬件限制
+pub async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue)> {
+    Ok(adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits: adapter.limits(),
+            },
+            None,
+        )
+        .await?)
+}
+
+impl Simulator {
+    /// 创建不需要窗口的模拟器，地图初始全是死细胞
+    pub async fn new(
+        map_size: (u32, u32),
+        rule: Rule,
+        topology: Topology,
+        storage: Storage,
+    ) -> Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        let adapter = request_adapter(&instance, None).await?;
+        let (device, queue) = request_device(&adapter).await?;
+        Self::from_device(&adapter, device, queue, map_size, rule, topology, storage)
+    }
+
+    /// 使用已有的设备创建模拟器，比如窗口程序中和展示平面共用的设备
+    ///
+    /// `adapter` 不支持计算管线时不管 `storage` 是什么，都使用CPU演化，结果放在纹理中
+    pub fn from_device(
+        adapter: &wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        map_size: (u32, u32),
+        rule: Rule,
+        topology: Topology,
+        storage: Storage,
+    ) -> Result<Self> {
+        let limits = device.limits();
+        let compute_shaders = adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+
+        let backend = if compute_shaders && storage == Storage::Packed {
+            if !map_size.0.is_multiple_of(PackedCells::CELLS_PER_WORD) {
+                return Err(format!(
+                    "按位储存时地图的宽度必须是 {} 的倍数，而不是 {}",
+                    PackedCells::CELLS_PER_WORD,
+                    map_size.0
+                )
+                .into());
+            }
+            let size = PackedCells::byte_size(map_size);
+            let max = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+            if size > max {
+                return Err(format!(
+                    "{}x{} 的地图需要 {size} 字节的存储缓冲区，设备最多支持 {max} 字节",
+                    map_size.0, map_size.1
+                )
+                .into());
+            }
+            Backend::Packed {
+                compute: Box::new(PackedCompute::new(&device, map_size, rule, topology)),
+                buffers: [
+                    PackedCells::new(&device, map_size),
+                    PackedCells::new(&device, map_size),
+                ],
+            }
+        } else {
+            let max = limits.max_texture_dimension_2d;
+            if map_size.0 > max || map_size.1 > max {
+                return Err(format!(
+                    "{}x{} 的地图超出了设备支持的最大纹理尺寸 {max}，可以改用按位储存",
+                    map_size.0, map_size.1
+                )
+                .into());
+            }
+            let textures = [
+                Texture::new(&device, map_size),
+                Texture::new(&device, map_size),
+            ];
+            if compute_shaders {
+                Backend::Gpu {
+                    compute: Box::new(Compute::new(&device, map_size, rule, topology)),
+                    textures,
+                }
+            } else {
+                Backend::Cpu {
+                    engine: CpuEngine::new(map_size, rule, topology),
+                    textures,
+                }
+            }
+        };
+        let reduction = match backend {
+            Backend::Cpu { .. } => None,
+            Backend::Gpu { .. } => Some(Reduction::new(&device, map_size, Storage::Texture)),
+            Backend::Packed { .. } => Some(Reduction::new(&device, map_size, Storage::Packed)),
+        };
+        Ok(Self {
+            device,
+            queue,
+            backend,
+            map_size,
+            rule,
+            topology,
+            cycle_render_binding_group: false,
+            generation: 0,
+            reduction,
+            stats: Stats::default(),
+            periods: None,
+            heatmap: None,
+            pyramid: None,
+            history: None,
+        })
+    }
+
+    pub fn map_size(&self) -> (u32, u32) {
+        self.map_size
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// 细胞实际的储存方式
+    pub fn storage(&self) -> Storage {
+        match self.backend {
+            Backend::Packed { .. } => Storage::Packed,
+            _ => Storage::Texture,
+        }
+    }
+
+    /// 是否因为设备不支持计算管线而在CPU上演化
+    pub fn is_cpu_fallback(&self) -> bool {
+        matches!(self.backend, Backend::Cpu { .. })
+    }
+
+    /// 当前这一代所在的纹理或者缓冲区，也就是正在显示的那一个
+    pub fn current_cells(&self) -> Cells<'_> {
+        cells(&self.backend, self.cycle_render_binding_group)
+    }
+
+    /// 已经读回的统计结果，GPU上的结果会晚几帧才出现，见 `poll_stats`
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// 收集已经从GPU读回的统计结果，不会阻塞，适合每帧调用一次
+    pub fn poll_stats(&mut self) -> &Stats {
+        self.collect_stats(false)
+    }
+
+    /// 等待所有统计结果读回
+    pub fn wait_stats(&mut self) -> &Stats {
+        self.collect_stats(true)
+    }
+
+    fn collect_stats(&mut self, wait: bool) -> &Stats {
+        if let Some(reduction) = &mut self.reduction {
+            for sample in reduction.collect(&self.device, wait) {
+                self.record(sample);
+            }
+        }
+        &self.stats
+    }
+
+    fn record(&mut self, sample: Sample) {
+        if let Some(periods) = &mut self.periods {
+            periods.observe(&sample);
+        }
+        self.stats.push(sample);
+    }
+
+    /// 开始判断地图是否已经稳定：每一代之后都在GPU上计算地图的散列值，
+    /// 记住最近 `capacity` 代，散列值重复时就找到了周期
+    ///
+    /// 每一代都要多一次统计，会慢一些。结果写在GPU上的环形缓冲区中，每次提交只读回一次
+    pub fn enable_period_detection(&mut self, capacity: usize) {
+        self.periods = Some(PeriodDetector::new(capacity));
+    }
+
+    /// 当前的周期，1 表示静物，和统计结果一样会晚几帧才出现
+    ///
+    /// 地图还没有稳定或者没有调用 `enable_period_detection` 时为 `None`
+    pub fn period(&self) -> Option<Period> {
+        self.periods.as_ref()?.period()
+    }
+
+    /// 在 `encoder` 的最后统计当前这一代，然后提交 `encoder`
+    ///
+    /// GPU上的结果在之后的 `poll_stats` 中读回
+    fn measure(&mut self, mut encoder: wgpu::CommandEncoder) {
+        match (&mut self.reduction, &self.backend) {
+            (Some(reduction), backend) => {
+                if let Some(pyramid) = &self.pyramid {
+                    pyramid.encode(
+                        &self.device,
+                        &mut encoder,
+                        cells(backend, self.cycle_render_binding_group),
+                    );
+                }
+                reduction.encode(
+                    &self.device,
+                    &mut encoder,
+                    cells(backend, self.cycle_render_binding_group),
+                    self.generation,
+                );
+                reduction.flush(&self.device, &mut encoder);
+                self.queue.submit(Some(encoder.finish()));
+                reduction.start_readback();
+            }
+            (None, Backend::Cpu { engine, .. }) => {
+                self.queue.submit(Some(encoder.finish()));
+                let sample = Sample::of(&engine.read_cells().unwrap_or_default(), self.generation);
+                self.record(sample);
+            }
+            (None, _) => unreachable!("只有在CPU上演化时没有统计用的计算管线"),
+        }
+    }
+
+    /// 开始记录热度图：每一代之后多一次计算，记下每个细胞是否变化了，
+    /// 显示时数最近 `window` 代中变化的次数，`window` 最多为 32
+    ///
+    /// 已经在记录时重新开始。在CPU上演化时不支持
+    pub fn enable_heatmap(&mut self, window: u32) -> Result<()> {
+        if self.is_cpu_fallback() {
+            return Err("在CPU上演化时没有热度图".into());
+        }
+        self.heatmap = Some(Heatmap::new(
+            &self.device,
+            self.map_size,
+            self.storage(),
+            window,
+        )?);
+        Ok(())
+    }
+
+    /// 停止记录热度图，释放它占用的显存
+    pub fn disable_heatmap(&mut self) {
+        self.heatmap = None;
+    }
+
+    pub fn heatmap(&self) -> Option<&Heatmap> {
+        self.heatmap.as_ref()
+    }
+
+    /// 读回每个细胞在窗口内变化的次数，按行排列，没有记录热度图时为 `None`
+    pub fn read_heatmap(&self) -> Result<Option<Vec<u32>>> {
+        self.heatmap
+            .as_ref()
+            .map(|heatmap| heatmap.read(&self.device, &self.queue))
+            .transpose()
+    }
+
+    /// 开始维护缩小显示用的金字塔，之后每次演化或者编辑都重建一次
+    ///
+    /// 在CPU上演化时不支持
+    pub fn enable_pyramid(&mut self) -> Result<()> {
+        if self.is_cpu_fallback() {
+            return Err("在CPU上演化时没有缩小显示用的金字塔".into());
+        }
+        let pyramid = Pyramid::new(&self.device, self.map_size, self.storage())?;
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        pyramid.encode(&self.device, &mut encoder, self.current_cells());
+        self.queue.submit(Some(encoder.finish()));
+        self.pyramid = Some(pyramid);
+        Ok(())
+    }
+
+    pub fn disable_pyramid(&mut self) {
+        self.pyramid = None;
+    }
+
+    pub fn pyramid(&self) -> Option<&Pyramid> {
+        self.pyramid.as_ref()
+    }
+
+    /// 读回金字塔的第 `level` 层，每个纹素是块里的最大值和平均值，没有金字塔时为 `None`
+    pub fn read_pyramid(&self, level: u32) -> Result<Option<Vec<(f32, f32)>>> {
+        let Some(pyramid) = &self.pyramid else {
+            return Ok(None);
+        };
+        if level >= pyramid.level_count() {
+            return Err(format!("金字塔只有 {} 层", pyramid.level_count()).into());
+        }
+        pyramid.read(&self.device, &self.queue, level).map(Some)
+    }
+
+    /// 开始记录倒退用的历史：每隔 `interval` 代保存一个关键帧，最多保存 `capacity` 个
+    ///
+    /// 关键帧和地图一样大，放在GPU上，关键帧之间的代在倒退时重新演化得到
+    pub fn enable_history(&mut self, interval: u64, capacity: usize) {
+        self.history = Some(History::new(interval, capacity));
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        self.push_keyframe(KeyframeKind::Start, &mut encoder);
+        self.queue.submit(Some(encoder.finish()));
+    }
+
+    /// 能倒退到的范围 `(最早的关键帧所在的代, 当前这一代)`，没有记录历史时为 `None`
+    pub fn history_range(&self) -> Option<(u64, u64)> {
+        let history = self.history.as_ref()?;
+        let first = history.keyframes.front()?;
+        Some((first.generation, self.generation))
+    }
+
+    /// 倒退一代，这一代编辑过细胞时先撤销编辑
+    ///
+    /// 已经退到最早的关键帧时返回 `false`
+    pub fn step_back(&mut self) -> bool {
+        let Some(history) = &mut self.history else {
+            return false;
+        };
+        let last = history.keyframes.len() - 1;
+        let keyframe = &history.keyframes[last];
+        let (index, target) = if history.dirty {
+            // 编辑前的地图就是这一段演化到当前这一代的结果
+            (last, self.generation)
+        } else if self.generation > keyframe.generation {
+            (last, self.generation - 1)
+        } else if last == 0 {
+            return false;
+        } else {
+            // 已经在这一段的开头，退到上一段的末尾
+            let target = match keyframe.kind {
+                KeyframeKind::Edit => self.generation,
+                KeyframeKind::Start | KeyframeKind::Periodic => self.generation - 1,
+            };
+            (last - 1, target)
+        };
+        history.dirty = false;
+        history.truncate(index);
+        self.rewind(index, target);
+        true
+    }
+
+    /// 跳到第 `generation` 代，用来在历史中拖动
+    ///
+    /// 比当前这一代新时直接演化过去，比最早的关键帧还早时返回 `false`。
+    /// 倒退之后，原来更新的那些代就不再是历史了
+    pub fn seek(&mut self, generation: u64) -> bool {
+        if generation >= self.generation {
+            self.run_until(generation);
+            return true;
+        }
+        let Some(history) = &mut self.history else {
+            return false;
+        };
+        let Some(index) = history.segment_of(generation) else {
+            return false;
+        };
+        history.dirty = false;
+        history.truncate(index);
+        self.rewind(index, generation);
+        true
+    }
+
+    /// 恢复第 `index` 个关键帧，再演化到第 `target` 代
+    fn rewind(&mut self, index: usize, target: u64) {
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        self.restore(index, &mut encoder);
+        self.advance(&mut encoder, target - self.generation);
+        self.measure(encoder);
+    }
+
+    /// 把第 `index` 个关键帧复制到当前这一代
+    fn restore(&mut self, index: usize, encoder: &mut wgpu::CommandEncoder) {
+        let keyframe = &self.history.as_ref().unwrap().keyframes[index];
+        let current = self.cycle_render_binding_group;
+        match (&mut self.backend, &keyframe.snapshot) {
+            (Backend::Gpu { textures, .. }, Snapshot::Texture(copy)) => {
+                encoder.copy_texture_to_texture(
+                    copy.texture.as_image_copy(),
+                    pick(textures, current).texture.as_image_copy(),
+                    copy.texture.size(),
+                );
+            }
+            (Backend::Packed { buffers, .. }, Snapshot::Packed(copy)) => {
+                encoder.copy_buffer_to_buffer(
+                    &copy.buffer,
+                    0,
+                    &pick(buffers, current).buffer,
+                    0,
+                    PackedCells::byte_size(self.map_size),
+                );
+            }
+            (Backend::Cpu { engine, textures }, Snapshot::Cpu(pattern)) => {
+                // 整张地图，不会放不下
+                let _ = engine.write_cells((0, 0), pattern);
+                pick(textures, current).write(&self.queue, (0, 0), self.map_size, &engine.texels());
+            }
+            _ => unreachable!("关键帧和地图的储存方式相同"),
+        }
+        self.generation = keyframe.generation;
+    }
+
+    /// 把当前这一代保存为新的关键帧
+    fn push_keyframe(&mut self, kind: KeyframeKind, encoder: &mut wgpu::CommandEncoder) {
+        let Some(history) = &mut self.history else {
+            return;
+        };
+        let spare = history.take_spare();
+        let snapshot = match (
+            &self.backend,
+            cells(&self.backend, self.cycle_render_binding_group),
+        ) {
+            (Backend::Cpu { engine, .. }, _) => {
+                Snapshot::Cpu(engine.read_cells().unwrap_or_default())
+            }
+            (_, Cells::Texture(texture)) => {
+                let copy = match spare {
+                    Some(Snapshot::Texture(copy)) => copy,
+                    _ => Texture::new(&self.device, self.map_size),
+                };
+                encoder.copy_texture_to_texture(
+                    texture.texture.as_image_copy(),
+                    copy.texture.as_image_copy(),
+                    texture.texture.size(),
+                );
+                Snapshot::Texture(copy)
+            }
+            (_, Cells::Packed(cells)) => {
+                let copy = match spare {
+                    Some(Snapshot::Packed(copy)) => copy,
+                    _ => PackedCells::new(&self.device, self.map_size),
+                };
+                encoder.copy_buffer_to_buffer(
+                    &cells.buffer,
+                    0,
+                    &copy.buffer,
+                    0,
+                    PackedCells::byte_size(self.map_size),
+                );
+                Snapshot::Packed(copy)
+            }
+        };
+        history.push(Keyframe {
+            generation: self.generation,
+            kind,
+            snapshot,
+        });
+    }
+
+    /// 编辑过细胞时，把编辑后的地图保存为新的一段
+    fn commit_edits(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(history) = &mut self.history {
+            if history.dirty {
+                history.dirty = false;
+                self.push_keyframe(KeyframeKind::Edit, encoder);
+            }
+        }
+    }
+
+    /// 编辑细胞之后调用
+    fn edited(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.dirty = true;
+        }
+        self.measure(self.device.create_command_encoder(&Default::default()));
+    }
+
+    /// 在 `encoder` 中记录 `n` 次演化
+    ///
+    /// 判断周期时，每一代之后都统计一次；记录热度图时，每一代之后都更新热度图
+    fn advance(&mut self, encoder: &mut wgpu::CommandEncoder, n: u64) {
+        let reduction = match self.periods {
+            Some(_) => self.reduction.as_mut(),
+            None => None,
+        };
+        match &mut self.backend {
+            Backend::Gpu { compute, textures } => {
+                // 两个方向的绑定组，每一代交替使用，和纹理一样用翻转的量来选
+                let [texture1, texture2] = textures;
+                let bind_groups = [
+                    compute.bind_group(&self.device, texture2, texture1),
+                    compute.bind_group(&self.device, texture1, texture2),
+                ];
+                let heatmap = self.heatmap.as_ref().map(|heatmap| {
+                    let groups = [
+                        heatmap.bind_group(
+                            &self.device,
+                            Cells::Texture(texture2),
+                            Cells::Texture(texture1),
+                        ),
+                        heatmap.bind_group(
+                            &self.device,
+                            Cells::Texture(texture1),
+                            Cells::Texture(texture2),
+                        ),
+                    ];
+                    (heatmap, groups)
+                });
+                let mut reduction = reduction.map(|reduction| {
+                    let groups = [
+                        reduction.bind_group(&self.device, Cells::Texture(texture1)),
+                        reduction.bind_group(&self.device, Cells::Texture(texture2)),
+                    ];
+                    (reduction, groups)
+                });
+                for i in 1..=n {
+                    self.cycle_render_binding_group = !self.cycle_render_binding_group;
+                    let bind_group = pick(&bind_groups, self.cycle_render_binding_group);
+                    compute.dispatch(encoder, bind_group);
+                    if let Some((heatmap, groups)) = &heatmap {
+                        heatmap.dispatch(encoder, pick(groups, self.cycle_render_binding_group));
+                    }
+                    if let Some((reduction, groups)) = &mut reduction {
+                        let bind_group = pick(groups, self.cycle_render_binding_group);
+                        reduction.dispatch(&self.device, encoder, bind_group, self.generation + i);
+                    }
+                }
+            }
+            Backend::Packed { compute, buffers } => {
+                let [buffer1, buffer2] = buffers;
+                let bind_groups = [
+                    compute.bind_group(&self.device, buffer2, buffer1),
+                    compute.bind_group(&self.device, buffer1, buffer2),
+                ];
+                let heatmap = self.heatmap.as_ref().map(|heatmap| {
+                    let groups = [
+                        heatmap.bind_group(
+                            &self.device,
+                            Cells::Packed(buffer2),
+                            Cells::Packed(buffer1),
+                        ),
+                        heatmap.bind_group(
+                            &self.device,
+                            Cells::Packed(buffer1),
+                            Cells::Packed(buffer2),
+                        ),
+                    ];
+                    (heatmap, groups)
+                });
+                let mut reduction = reduction.map(|reduction| {
+                    let groups = [
+                        reduction.bind_group(&self.device, Cells::Packed(buffer1)),
+                        reduction.bind_group(&self.device, Cells::Packed(buffer2)),
+                    ];
+                    (reduction, groups)
+                });
+                for i in 1..=n {
+                    self.cycle_render_binding_group = !self.cycle_render_binding_group;
+                    let bind_group = pick(&bind_groups, self.cycle_render_binding_group);
+                    compute.dispatch(encoder, bind_group);
+                    if let Some((heatmap, groups)) = &heatmap {
+                        heatmap.dispatch(encoder, pick(groups, self.cycle_render_binding_group));
+                    }
+                    if let Some((reduction, groups)) = &mut reduction {
+                        let bind_group = pick(groups, self.cycle_render_binding_group);
+                        reduction.dispatch(&self.device, encoder, bind_group, self.generation + i);
+                    }
+                }
+            }
+            Backend::Cpu { engine, textures } => {
+                if let Some(periods) = &mut self.periods {
+                    for i in 1..=n {
+                        engine.update();
+                        let cells = engine.read_cells().unwrap_or_default();
+                        let sample = Sample::of(&cells, self.generation + i);
+                        periods.observe(&sample);
+                        self.stats.push(sample);
+                    }
+                } else {
+                    engine.step(n);
+                }
+                // 保持和GPU相同的翻转规律，然后只上传最后一代
+                if n % 2 == 1 {
+                    self.cycle_render_binding_group = !self.cycle_render_binding_group;
+                }
+                pick(textures, self.cycle_render_binding_group).write(
+                    &self.queue,
+                    (0, 0),
+                    self.map_size,
+                    &engine.texels(),
+                );
+            }
+        }
+        self.generation += n;
+    }
+
+    /// 一直演化到第 `generation` 代，已经超过时什么也不做
+    pub fn run_until(&mut self, generation: u64) {
+        if generation > self.generation {
+            self.step(generation - self.generation);
+        }
+    }
+
+    /// 演化一代
+    pub fn update(&mut self) {
+        self.step(1);
+    }
+
+    /// 保存当前的完整状态，`view` 是窗口程序中摄像机的位置和缩放
+    pub fn snapshot(&self, view: View) -> Result<snapshot::Snapshot> {
+        let cells = match self.current_cells() {
+            Cells::Texture(_) => bytemuck::cast_slice(&self.read_texels()?).to_vec(),
+            Cells::Packed(cells) => {
+                let words = cells.read_rows(&self.device, &self.queue, 0..self.map_size.1)?;
+                words.iter().flat_map(|word| word.to_le_bytes()).collect()
+            }
+        };
+        Ok(snapshot::Snapshot {
+            map_size: self.map_size,
+            rule: self.rule,
+            topology: self.topology,
+            storage: self.storage(),
+            generation: self.generation,
+            view,
+            second: self.cycle_render_binding_group,
+            cells,
+        })
+    }
+
+    /// 从快照继续演化：恢复代数、地图（包括年龄和尾迹），以及当前这一代在两份地图中的哪一份，
+    /// 所以之后的演化和保存快照时接着演化完全一样
+    ///
+    /// 地图大小、规则、拓扑和储存方式必须和模拟器一致。历史、周期和热度图从这一代重新开始
+    pub fn resume(&mut self, snapshot: &snapshot::Snapshot) -> Result<()> {
+        if (
+            snapshot.map_size,
+            snapshot.rule,
+            snapshot.topology,
+            snapshot.storage,
+        ) != (self.map_size, self.rule, self.topology, self.storage())
+        {
+            return Err(format!(
+                "快照是 {}x{}、{}、{}、{:?} 的地图，和模拟器（{}x{}、{}、{}、{:?}）不一致",
+                snapshot.map_size.0,
+                snapshot.map_size.1,
+                snapshot.rule,
+                snapshot.topology.name(),
+                snapshot.storage,
+                self.map_size.0,
+                self.map_size.1,
+                self.rule,
+                self.topology.name(),
+                self.storage(),
+            )
+            .into());
+        }
+
+        self.cycle_render_binding_group = snapshot.second;
+        self.generation = snapshot.generation;
+        match &mut self.backend {
+            Backend::Gpu { textures, .. } => {
+                let texels: &[[u8; 4]] = bytemuck::cast_slice(&snapshot.cells);
+                pick(textures, snapshot.second).write(&self.queue, (0, 0), self.map_size, texels);
+            }
+            Backend::Cpu { engine, textures } => {
+                let texels: &[[u8; 4]] = bytemuck::cast_slice(&snapshot.cells);
+                engine.write_texels((0, 0), self.map_size, texels);
+                pick(textures, snapshot.second).write(&self.queue, (0, 0), self.map_size, texels);
+            }
+            Backend::Packed { buffers, .. } => {
+                let words: Vec<u32> = snapshot
+                    .cells
+                    .chunks_exact(4)
+                    .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+                    .collect();
+                pick(buffers, snapshot.second).write_rows(&self.queue, 0, &words);
+            }
+        }
+
+        self.stats = Stats::default();
+        if let Some(periods) = &mut self.periods {
+            periods.clear();
+        }
+        if let Some(heatmap) = &self.heatmap {
+            self.enable_heatmap(heatmap.window())?;
+        }
+        if let Some(history) = &self.history {
+            self.enable_history(history.interval, history.capacity());
+        }
+        self.measure(self.device.create_command_encoder(&Default::default()));
+        Ok(())
+    }
+
+    /// 把当前这一代读回内存，格式和纹理中的一样
+    pub fn read_texels(&self) -> Result<Vec<[u8; 4]>> {
+        match (&self.backend, self.current_cells()) {
+            (Backend::Cpu { engine, .. }, _) => Ok(engine.texels()),
+            (_, Cells::Texture(texture)) => Ok(texture.read(&self.device, &self.queue)?),
+            (_, Cells::Packed(_)) => Ok(self
+                .read_bits()?
+                .into_iter()
+                .map(|alive| if alive { ALIVE } else { DEAD })
+                .collect()),
+        }
+    }
+
+    /// 只读回当前这一代中 `origin` 开始、`size` 大小的一块矩形区域
+    ///
+    /// 纹理只复制这块区域，按位储存时只读回涉及到的行
+    pub fn read_region(&self, origin: (u32, u32), size: (u32, u32)) -> Result<Pattern> {
+        if origin
+            .0
+            .checked_add(size.0)
+            .is_none_or(|x| x > self.map_size.0)
+            || origin
+                .1
+                .checked_add(size.1)
+                .is_none_or(|y| y > self.map_size.1)
+            || size.0 == 0
+            || size.1 == 0
+        {
+            return Err(format!(
+                "区域 {},{},{}x{} 不在 {}x{} 的地图中",
+                origin.0, origin.1, size.0, size.1, self.map_size.0, self.map_size.1
+            )
+            .into());
+        }
+        let cells = match (&self.backend, self.current_cells()) {
+            (Backend::Cpu { engine, .. }, _) => {
+                let map = engine.read_cells()?;
+                (origin.1..origin.1 + size.1)
+                    .flat_map(|y| (origin.0..origin.0 + size.0).map(move |x| (x, y)))
+                    .map(|(x, y)| map.get(x, y))
+                    .collect()
+            }
+            (_, Cells::Texture(texture)) => texture
+                .read_region(&self.device, &self.queue, origin, size)?
+                .iter()
+                .map(|texel| texel[0] > 0)
+                .collect(),
+            (_, Cells::Packed(cells)) => {
+                let words_per_row = cells.words_per_row();
+                let words =
+                    cells.read_rows(&self.device, &self.queue, origin.1..origin.1 + size.1)?;
+                (0..size.1)
+                    .flat_map(|y| (origin.0..origin.0 + size.0).map(move |x| (x, y)))
+                    .map(|(x, y)| {
+                        let word =
+                            words[(y * words_per_row + x / PackedCells::CELLS_PER_WORD) as usize];
+                        (word >> (x % PackedCells::CELLS_PER_WORD)) & 1 == 1
+                    })
+                    .collect()
+            }
+        };
+        Ok(Pattern {
+            width: size.0,
+            height: size.1,
+            cells,
+            rule: Some(self.rule),
+            ..Default::default()
+        })
+    }
+
+    /// 写入当前这一代的一块矩形区域，`origin` 是区域左上角的位置
+    ///
+    /// 按位储存时需要先读回涉及到的行，所以可能会失败
+    pub fn write_texels(
+        &mut self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        data: &[[u8; 4]],
+    ) -> Result<()> {
+        if let Backend::Cpu { engine, .. } = &mut self.backend {
+            engine.write_texels(origin, size, data);
+        }
+        match self.current_cells() {
+            Cells::Texture(texture) => texture.write(&self.queue, origin, size, data),
+            Cells::Packed(_) => {
+                let bits: Vec<bool> = data.iter().map(|texel| texel[0] > 0).collect();
+                self.write_bits(origin, size, &bits)?;
+            }
+        }
+        self.edited();
+        Ok(())
+    }
+
+    /// 设置一个细胞的状态
+    pub fn set_cell(&mut self, (x, y): (u32, u32), alive: bool) -> Result<()> {
+        self.write_texels((x, y), (1, 1), &[if alive { ALIVE } else { DEAD }])
+    }
+
+    /// 把一组细胞都设置成同一个状态，例如拖动鼠标时一次经过的格子，最后只统计一次
+    ///
+    /// 按位储存时只读回和写回这些细胞所在的几行
+    pub fn set_cells(&mut self, cells: &[(u32, u32)], alive: bool) -> Result<()> {
+        if let Some(&(x, y)) = cells
+            .iter()
+            .find(|(x, y)| *x >= self.map_size.0 || *y >= self.map_size.1)
+        {
+            return Err(format!(
+                "细胞 {x},{y} 不在 {}x{} 的地图中",
+                self.map_size.0, self.map_size.1
+            )
+            .into());
+        }
+        let texel = if alive { ALIVE } else { DEAD };
+        if let Backend::Cpu { engine, .. } = &mut self.backend {
+            for &cell in cells {
+                engine.write_texels(cell, (1, 1), &[texel]);
+            }
+        }
+        match self.current_cells() {
+            Cells::Texture(texture) => {
+                for &cell in cells {
+                    texture.write(&self.queue, cell, (1, 1), &[texel]);
+                }
+            }
+            Cells::Packed(packed) => {
+                let (Some(top), Some(bottom)) = (
+                    cells.iter().map(|&(_, y)| y).min(),
+                    cells.iter().map(|&(_, y)| y).max(),
+                ) else {
+                    return Ok(());
+                };
+                let words_per_row = packed.words_per_row();
+                let mut words = packed.read_rows(&self.device, &self.queue, top..bottom + 1)?;
+                for &(x, y) in cells {
+                    let word = &mut words
+                        [((y - top) * words_per_row + x / PackedCells::CELLS_PER_WORD) as usize];
+                    let mask = 1 << (x % PackedCells::CELLS_PER_WORD);
+                    if alive {
+                        *word |= mask;
+                    } else {
+                        *word &= !mask;
+                    }
+                }
+                packed.write_rows(&self.queue, top, &words);
+            }
+        }
+        if !cells.is_empty() {
+            self.edited();
+        }
+        Ok(())
+    }
+
+    /// 按位储存时读回整张地图，按行排列
+    fn read_bits(&self) -> Result<Vec<bool>> {
+        let Cells::Packed(cells) = self.current_cells() else {
+            unreachable!("只有按位储存时才会读取位");
+        };
+        let words = cells.read_rows(&self.device, &self.queue, 0..self.map_size.1)?;
+        Ok(words
+            .iter()
+            .flat_map(|word| {
+                (0..PackedCells::CELLS_PER_WORD).map(move |bit| (word >> bit) & 1 == 1)
+            })
+            .collect())
+    }
+
+    /// 按位储存时写入一块矩形区域
+    ///
+    /// 区域两边的字里还有区域外的细胞，所以先读回涉及到的行，改完再整行写回
+    fn write_bits(&self, origin: (u32, u32), size: (u32, u32), bits: &[bool]) -> Result<()> {
+        let Cells::Packed(cells) = self.current_cells() else {
+            unreachable!("只有按位储存时才会写入位");
+        };
+        let words_per_row = cells.words_per_row();
+        let mut words = cells.read_rows(&self.device, &self.queue, origin.1..origin.1 + size.1)?;
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                let column = origin.0 + x;
+                let word =
+                    &mut words[(y * words_per_row + column / PackedCells::CELLS_PER_WORD) as usize];
+                let mask = 1 << (column % PackedCells::CELLS_PER_WORD);
+                if bits[(y * size.0 + x) as usize] {
+                    *word |= mask;
+                } else {
+                    *word &= !mask;
+                }
+            }
+        }
+        cells.write_rows(&self.queue, origin.1, &words);
+        Ok(())
+    }
+
+    /// 把当前这一代裁剪到活细胞的包围盒，保存成 RLE、`.cells` 或者 macrocell 文件（按扩展名区分）
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut pattern = self.read_cells()?.cropped();
+        pattern
+            .comments
+            .push(format!("generation {}", self.generation));
+
+        let path = path.as_ref();
+        let text = match path.extension() {
+            Some(extension) if extension == "cells" => pattern.to_cells(),
+            Some(extension) if extension == "mc" => pattern.to_macrocell(),
+            _ => pattern.to_rle(),
+        };
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// 把整张地图保存成一个像素一个细胞的 PNG 图片
+    ///
+    /// `channels` 为 `false` 时是黑白图片，活细胞是黑色，可以用 `Pattern::from_png` 读回来；
+    /// 为 `true` 时保存纹理中的原始数据：R 是否存活，G 年龄，B 尾迹，A 是否存活。
+    /// 按位储存时没有年龄和尾迹，这两个通道总是 0
+    pub fn save_image(&self, path: impl AsRef<Path>, channels: bool) -> Result<()> {
+        let bytes = if channels {
+            let texels = self.read_texels()?;
+            pattern::encode_png(
+                self.map_size,
+                png::ColorType::Rgba,
+                bytemuck::cast_slice(&texels),
+            )?
+        } else {
+            self.read_cells()?.to_png()?
+        };
+        std::fs::write(path, bytes)?;
+        Ok(())
+    }
+}
+
+impl Engine for Simulator {
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// GPU上的 `n` 次演化全部记录在同一个命令编码器中，一次提交
+    ///
+    /// 记录历史时，在经过的关键帧处复制地图
+    fn step(&mut self, n: u64) {
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        if let Some(history) = &self.history {
+            let interval = history.interval;
+            let capacity = history.capacity() as u64;
+            let last = (self.generation + n) / interval;
+
+            self.commit_edits(&mut encoder);
+            let mut remaining = n;
+            while remaining > 0 {
+                let chunk = remaining.min(interval - self.generation % interval);
+                self.advance(&mut encoder, chunk);
+                remaining -= chunk;
+                // 一次演化很多代时，前面的关键帧反正会被挤掉，不用复制
+                if self.generation.is_multiple_of(interval)
+                    && last - self.generation / interval < capacity
+                {
+                    self.push_keyframe(KeyframeKind::Periodic, &mut encoder);
+                }
+            }
+        } else {
+            self.advance(&mut encoder, n);
+        }
+        self.measure(encoder);
+    }
+
+    fn read_cells(&self) -> Result<Pattern> {
+        let mut pattern = match self.current_cells() {
+            Cells::Packed(_) => Pattern {
+                width: self.map_size.0,
+                height: self.map_size.1,
+                cells: self.read_bits()?,
+                ..Default::default()
+            },
+            Cells::Texture(_) => Pattern::from_map(&self.read_texels()?, self.map_size),
+        };
+        pattern.rule = Some(self.rule);
+        Ok(pattern)
+    }
+
+    fn write_cells(&mut self, offset: (u32, u32), pattern: &Pattern) -> Result<()> {
+        pattern.check_fits(offset, self.map_size)?;
+        if let Cells::Packed(_) = self.current_cells() {
+            self.write_bits(offset, (pattern.width, pattern.height), &pattern.cells)?;
+            self.edited();
+            return Ok(());
+        }
+        let mut data = vec![DEAD; (pattern.width * pattern.height) as usize];
+        pattern.place(&mut data, (pattern.width, pattern.height), (0, 0))?;
+        self.write_texels(offset, (pattern.width, pattern.height), &data)
+    }
+}
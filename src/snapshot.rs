@@ -0,0 +1,213 @@
+//! 快照：暂停一次很长的演化，之后从同一代接着演化
+//!
+//! 文件格式（小端序）：
+//!
+//! | 字节数 | 内容 |
+//! |---|---|
+//! | 8 | `LIFESNAP` |
+//! | 4 | 版本，目前是 1 |
+//! | 4 + 4 | 地图的宽和高 |
+//! | 2 + 2 | 规则的出生和存活掩码 |
+//! | 1 | 拓扑，`Topology::ALL` 中的下标 |
+//! | 1 | 储存方式，0 是纹理，1 是按位储存 |
+//! | 1 | 当前这一代在两份地图中的哪一份，也就是 `cycle_render_binding_group` |
+//! | 1 | 是否是正交摄像机 |
+//! | 8 | 代数 |
+//! | 4 x 7 | 摄像机，见 `View` |
+//! | 8 | 压缩后的地图的字节数 |
+//! | ... | 用 zlib 压缩的地图：纹理储存时是每个细胞 4 字节的纹素，按位储存时是每行的 `u32` |
+
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
+
+use crate::{rule::Rule, topology::Topology, Result, Storage};
+
+const MAGIC: &[u8; 8] = b"LIFESNAP";
+
+/// 当前的版本，格式改变时加一
+pub const VERSION: u32 = 1;
+
+/// 摄像机的位置和缩放，模拟器不关心它们的含义，只是原样保存
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct View {
+    /// 是否是正交摄像机
+    pub orthographic: bool,
+    /// 正交摄像机对准的位置，按格子算
+    pub center: [f32; 2],
+    /// 正交摄像机每个像素的格子数
+    pub cells_per_pixel: f32,
+    /// 透视摄像机的位置
+    pub position: [f32; 3],
+    /// 透视摄像机滚轮的缩放
+    pub scale: f32,
+}
+
+/// 模拟器在某一代的完整状态，见 `Simulator::snapshot` 和 `Simulator::resume`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub map_size: (u32, u32),
+    pub rule: Rule,
+    pub topology: Topology,
+    pub storage: Storage,
+    pub generation: u64,
+    pub view: View,
+    /// 当前这一代在两份地图中的哪一份
+    pub(crate) second: bool,
+    /// 没有压缩的地图，格式和 GPU 上的一样
+    pub(crate) cells: Vec<u8>,
+}
+
+/// 按顺序读取小端序的数
+struct Reader<R>(R);
+
+impl<R: Read> Reader<R> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.0
+            .read_exact(&mut bytes)
+            .map_err(|e| format!("快照文件不完整：{e}"))?;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.bytes()?))
+    }
+}
+
+impl Snapshot {
+    /// 地图解压之后应该有的字节数，地图大到放不进内存时返回 `None`
+    fn cells_size(map_size: (u32, u32), storage: Storage) -> Option<usize> {
+        let (width, height) = (map_size.0 as u64, map_size.1 as u64);
+        let size = match storage {
+            Storage::Texture => width.checked_mul(height)?.checked_mul(4)?,
+            Storage::Packed => width.div_ceil(32).checked_mul(4 * height)?,
+        };
+        usize::try_from(size).ok()
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> Result<()> {
+        let view = &self.view;
+        let mut header = Vec::new();
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&self.map_size.0.to_le_bytes());
+        header.extend_from_slice(&self.map_size.1.to_le_bytes());
+        header.extend_from_slice(&self.rule.birth.to_le_bytes());
+        header.extend_from_slice(&self.rule.survival.to_le_bytes());
+        header.push(
+            Topology::ALL
+                .iter()
+                .position(|&t| t == self.topology)
+                .unwrap() as u8,
+        );
+        header.push(match self.storage {
+            Storage::Texture => 0,
+            Storage::Packed => 1,
+        });
+        header.push(self.second as u8);
+        header.push(view.orthographic as u8);
+        header.extend_from_slice(&self.generation.to_le_bytes());
+        for value in [view.center[0], view.center[1], view.cells_per_pixel]
+            .into_iter()
+            .chain(view.position)
+            .chain([view.scale])
+        {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let cells = miniz_oxide::deflate::compress_to_vec_zlib(&self.cells, 6);
+        header.extend_from_slice(&(cells.len() as u64).to_le_bytes());
+        writer.write_all(&header)?;
+        writer.write_all(&cells)?;
+        Ok(())
+    }
+
+    pub fn read(reader: impl Read) -> Result<Self> {
+        let mut reader = Reader(reader);
+        if &reader.bytes::<8>()? != MAGIC {
+            return Err("不是快照文件".into());
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(format!("不支持第 {version} 版的快照，只支持第 {VERSION} 版").into());
+        }
+        let map_size = (reader.u32()?, reader.u32()?);
+        let rule = Rule {
+            birth: reader.u16()?,
+            survival: reader.u16()?,
+        };
+        let topology = *Topology::ALL
+            .get(reader.u8()? as usize)
+            .ok_or("快照中的拓扑无效")?;
+        let storage = match reader.u8()? {
+            0 => Storage::Texture,
+            1 => Storage::Packed,
+            storage => return Err(format!("快照中的储存方式 {storage} 无效").into()),
+        };
+        let second = reader.u8()? != 0;
+        let orthographic = reader.u8()? != 0;
+        let generation = reader.u64()?;
+        let view = View {
+            orthographic,
+            center: [reader.f32()?, reader.f32()?],
+            cells_per_pixel: reader.f32()?,
+            position: [reader.f32()?, reader.f32()?, reader.f32()?],
+            scale: reader.f32()?,
+        };
+
+        let compressed = reader.u64()?;
+        let mut cells = Vec::new();
+        reader.0.take(compressed).read_to_end(&mut cells)?;
+        if cells.len() as u64 != compressed {
+            return Err("快照文件不完整".into());
+        }
+        let size = Self::cells_size(map_size, storage)
+            .ok_or_else(|| format!("快照中的地图 {}x{} 太大了", map_size.0, map_size.1))?;
+        let cells = miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&cells, size)
+            .map_err(|e| format!("快照中的地图无法解压：{:?}", e.status))?;
+        if cells.len() != size {
+            return Err(format!("快照中的地图有 {} 字节，应该是 {size} 字节", cells.len()).into());
+        }
+
+        Ok(Self {
+            map_size,
+            rule,
+            topology,
+            storage,
+            generation,
+            view,
+            second,
+            cells,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Self::read(std::io::BufReader::new(file))
+    }
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+/// 和帧率无关的演化速度
+///
+/// 每帧把经过的时间换算成代数累积起来，攒够一代就演化，落后时一帧演化多代
+pub struct Speed {
+    generations_per_second: f64,
+    /// 还没有演化的代数，不满一代的部分留到下一帧
+    accumulator: f64,
+}
+
+impl Speed {
+    /// 最慢的速度
+    pub const MIN: f64 = 0.5;
+    /// 最快的速度
+    pub const MAX: f64 = 65536.0;
+
+    /// 最多补上多少秒的落后，卡顿之后不会一下子演化太多代
+    const MAX_BACKLOG: f64 = 0.25;
+
+    pub fn new(generations_per_second: f64) -> Self {
+        Self {
+            generations_per_second: generations_per_second.clamp(Self::MIN, Self::MAX),
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn generations_per_second(&self) -> f64 {
+        self.generations_per_second
+    }
+
+    /// 速度加倍
+    pub fn faster(&mut self) {
+        self.generations_per_second = (self.generations_per_second * 2.0).min(Self::MAX);
+    }
+
+    /// 速度减半
+    pub fn slower(&mut self) {
+        self.generations_per_second = (self.generations_per_second / 2.0).max(Self::MIN);
+    }
+
+    /// 丢掉累积的代数，暂停之后重新开始时使用
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+    }
+
+    /// 经过 `dt` 之后应该演化的代数
+    pub fn advance(&mut self, dt: Duration) -> u64 {
+        let max = (self.generations_per_second * Self::MAX_BACKLOG).max(1.0);
+        self.accumulator =
+            (self.accumulator + dt.as_secs_f64() * self.generations_per_second).min(max);
+        let generations = self.accumulator.floor();
+        self.accumulator -= generations;
+        generations as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn carries_fractions() {
+        // 每秒 2.5 代，每帧 0.25 代：第 4 帧攒够一代，不满一代的部分留下来
+        let mut speed = Speed::new(2.5);
+        let generations: Vec<u64> = (0..8).map(|_| speed.advance(FRAME)).collect();
+        assert_eq!(generations, [0, 0, 0, 1, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn reset_drops_the_accumulator() {
+        let mut speed = Speed::new(2.5);
+        for _ in 0..3 {
+            assert_eq!(speed.advance(FRAME), 0);
+        }
+        speed.reset();
+        for _ in 0..3 {
+            assert_eq!(speed.advance(FRAME), 0);
+        }
+        assert_eq!(speed.advance(FRAME), 1);
+    }
+
+    #[test]
+    fn limits_the_backlog() {
+        // 卡顿一秒之后最多补上四分之一秒，但至少一代
+        assert_eq!(Speed::new(100.0).advance(Duration::from_secs(1)), 25);
+        assert_eq!(Speed::new(Speed::MIN).advance(Duration::from_secs(10)), 1);
+    }
+
+    #[test]
+    fn doubles_and_halves_within_limits() {
+        let mut speed = Speed::new(60.0);
+        speed.faster();
+        assert_eq!(speed.generations_per_second(), 120.0);
+        speed.slower();
+        speed.slower();
+        assert_eq!(speed.generations_per_second(), 30.0);
+
+        let mut speed = Speed::new(Speed::MAX * 2.0);
+        assert_eq!(speed.generations_per_second(), Speed::MAX);
+        speed.faster();
+        assert_eq!(speed.generations_per_second(), Speed::MAX);
+
+        let mut speed = Speed::new(0.0);
+        assert_eq!(speed.generations_per_second(), Speed::MIN);
+        speed.slower();
+        assert_eq!(speed.generations_per_second(), Speed::MIN);
+    }
+}
@@ -0,0 +1,445 @@
+//! 每一代的统计信息：活细胞的数量、包围盒和散列值
+//!
+//! 在GPU上用 `stats.wgsl` 归约，结果异步地读回，不会阻塞渲染
+
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{self, Receiver},
+};
+
+use wgpu::util::DeviceExt;
+
+use crate::{pattern::Pattern, resources::PackedCells, Cells, Storage};
+
+/// 一代的统计结果
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub generation: u64,
+    /// 活细胞的数量
+    pub population: u64,
+    /// 活细胞的包围盒 `(x, y, width, height)`，没有活细胞时为 `None`
+    pub bounding_box: Option<(u32, u32, u32, u32)>,
+    /// 整张地图的散列值，地图相同时一定相同，见 `cell_hash`
+    pub hash: u64,
+}
+
+impl Sample {
+    /// 在CPU上统计一张地图
+    pub fn of(pattern: &Pattern, generation: u64) -> Self {
+        let mut population = 0;
+        let mut hash = 0;
+        for y in 0..pattern.height {
+            for x in 0..pattern.width {
+                if pattern.get(x, y) {
+                    population += 1;
+                    hash = add_hash(hash, cell_hash((x, y), pattern.width));
+                }
+            }
+        }
+        Self {
+            generation,
+            population,
+            bounding_box: pattern.bounding_box(),
+            hash,
+        }
+    }
+}
+
+/// 一个活细胞的散列值，整张地图的散列值是所有活细胞的散列值之和（高低 32 位分别相加）
+///
+/// 和 `stats.wgsl` 中的 `cell_hash` 相同
+pub fn cell_hash((x, y): (u32, u32), map_width: u32) -> u64 {
+    fn mix(value: u32) -> u32 {
+        let mut x = value;
+        x ^= x >> 16;
+        x = x.wrapping_mul(0x7feb352d);
+        x ^= x >> 15;
+        x = x.wrapping_mul(0x846ca68b);
+        x ^= x >> 16;
+        x
+    }
+    let index = y.wrapping_mul(map_width).wrapping_add(x);
+    (mix(index ^ 0x9e3779b9) as u64) << u32::BITS | mix(index) as u64
+}
+
+/// 把一个活细胞的散列值加到整张地图的散列值上
+pub fn add_hash(hash: u64, cell: u64) -> u64 {
+    let low = (hash as u32).wrapping_add(cell as u32);
+    let high = ((hash >> u32::BITS) as u32).wrapping_add((cell >> u32::BITS) as u32);
+    (high as u64) << u32::BITS | low as u64
+}
+
+/// 最近若干代的统计结果，按测量的先后排列
+#[derive(Debug, Clone)]
+pub struct Stats {
+    samples: VecDeque<Sample>,
+    capacity: usize,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl Stats {
+    /// 默认保留的代数
+    pub const DEFAULT_CAPACITY: usize = 4096;
+
+    /// 最多保留 `capacity` 个结果，更早的会被丢掉
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, sample: Sample) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// 最近一次的结果
+    pub fn latest(&self) -> Option<&Sample> {
+        self.samples.back()
+    }
+
+    pub fn samples(&self) -> &VecDeque<Sample> {
+        &self.samples
+    }
+}
+
+/// 已经提交、还没有读回的统计结果，从 `generation` 开始连续的 `count` 代
+struct Pending {
+    generation: u64,
+    count: u64,
+    buffer: wgpu::Buffer,
+    /// 映射完成时收到结果，还没有开始映射时为 `None`
+    receiver: Option<Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+/// 结果缓冲区中的值，见 `stats.wgsl` 中的 `Result`
+const RESULT_SIZE: wgpu::BufferAddress = 8 * std::mem::size_of::<u32>() as wgpu::BufferAddress;
+
+/// 环形缓冲区中的位置数，一次提交中最多连续统计这么多代，更多时分几次读回
+const RING_SLOTS: u64 = 256;
+
+/// 统计用的计算管线和读回用的缓冲区
+///
+/// 每一代的结果写入GPU上的环形缓冲区中第 `代数 % RING_SLOTS` 个位置，
+/// 提交之前把还没有读回的部分一次复制到读回缓冲区
+pub(crate) struct Reduction {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+    storage: Storage,
+    map_size: (u32, u32),
+    map_size_uniform: wgpu::Buffer,
+    ring: wgpu::Buffer,
+    /// 环形缓冲区中相邻位置的距离，要满足动态偏移的对齐要求
+    stride: wgpu::BufferAddress,
+    /// 已经写入环形缓冲区、还没有复制出来的代：第一代和代数
+    queued: Option<(u64, u64)>,
+    /// 可以重复使用的读回缓冲区
+    free: Vec<wgpu::Buffer>,
+    pending: VecDeque<Pending>,
+}
+
+impl Reduction {
+    pub fn new(device: &wgpu::Device, map_size: (u32, u32), storage: Storage) -> Self {
+        let map_entry = match storage {
+            Storage::Texture => wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            Storage::Packed => wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                map_entry,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(RESULT_SIZE),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let map_size_uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[
+                map_size.0,
+                map_size.1,
+                map_size.0 / PackedCells::CELLS_PER_WORD,
+                0,
+            ]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let stride = RESULT_SIZE.max(device.limits().min_storage_buffer_offset_alignment as u64);
+        let ring = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: RING_SLOTS * stride,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader_module = device.create_shader_module(wgpu::include_wgsl!("stats.wgsl"));
+        let pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(&layout),
+                module: &shader_module,
+                entry_point: match storage {
+                    Storage::Texture => "cs_texture",
+                    Storage::Packed => "cs_packed",
+                },
+            })
+        };
+
+        Self {
+            bind_group_layout,
+            pipeline,
+            storage,
+            map_size,
+            map_size_uniform,
+            ring,
+            stride,
+            queued: None,
+            free: vec![],
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// 统计 `cells` 用的绑定组，演化时每个方向创建一次，每一代重复使用
+    pub fn bind_group(&self, device: &wgpu::Device, cells: Cells) -> wgpu::BindGroup {
+        let map_binding = match cells {
+            Cells::Texture(texture) => wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+            Cells::Packed(cells) => wgpu::BindGroupEntry {
+                binding: 1,
+                resource: cells.buffer.as_entire_binding(),
+            },
+        };
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: &[
+                map_binding,
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &self.ring,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(RESULT_SIZE),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.map_size_uniform.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// 在 `encoder` 中记录对 `bind_group` 中的地图的统计，结果写入环形缓冲区
+    ///
+    /// 这一代已经统计过时什么都不做。提交之前要调用 `flush`，提交之后调用 `start_readback`
+    pub fn dispatch(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_group: &wgpu::BindGroup,
+        generation: u64,
+    ) {
+        match self.queued {
+            Some((first, count)) if first + count - 1 == generation => return,
+            // 不连续或者环形缓冲区满了时，先把已有的结果复制出来
+            Some((first, count)) if first + count != generation || count == RING_SLOTS => {
+                self.flush(device, encoder);
+            }
+            _ => {}
+        }
+        self.queued = Some(match self.queued {
+            Some((first, count)) => (first, count + 1),
+            None => (generation, 1),
+        });
+
+        let offset = generation % RING_SLOTS * self.stride;
+        encoder.clear_buffer(&self.ring, offset, wgpu::BufferSize::new(RESULT_SIZE));
+        let workgroup_count = match self.storage {
+            Storage::Texture => (self.map_size.0.div_ceil(16), self.map_size.1.div_ceil(16)),
+            Storage::Packed => (
+                (self.map_size.0 / PackedCells::CELLS_PER_WORD).div_ceil(16),
+                self.map_size.1.div_ceil(16),
+            ),
+        };
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+        cpass.set_pipeline(&self.pipeline);
+        cpass.set_bind_group(0, bind_group, &[offset as u32]);
+        cpass.dispatch_workgroups(workgroup_count.0, workgroup_count.1, 1);
+    }
+
+    /// 在 `encoder` 中记录对 `cells` 的统计，见 `dispatch`
+    pub fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        cells: Cells,
+        generation: u64,
+    ) {
+        let bind_group = self.bind_group(device, cells);
+        self.dispatch(device, encoder, &bind_group, generation);
+    }
+
+    /// 把环形缓冲区中还没有复制出来的结果按代数的顺序复制到一个读回缓冲区
+    pub fn flush(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let Some((generation, count)) = self.queued.take() else {
+            return;
+        };
+        let buffer = self.free.pop().unwrap_or_else(|| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: RING_SLOTS * self.stride,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            })
+        });
+        // 绕过环形缓冲区的末尾时分成两段
+        let start = generation % RING_SLOTS;
+        let head = count.min(RING_SLOTS - start);
+        encoder.copy_buffer_to_buffer(
+            &self.ring,
+            start * self.stride,
+            &buffer,
+            0,
+            head * self.stride,
+        );
+        if head < count {
+            encoder.copy_buffer_to_buffer(
+                &self.ring,
+                0,
+                &buffer,
+                head * self.stride,
+                (count - head) * self.stride,
+            );
+        }
+        self.pending.push_back(Pending {
+            generation,
+            count,
+            buffer,
+            receiver: None,
+        });
+    }
+
+    /// 开始映射刚刚提交的读回缓冲区，不等待映射完成
+    pub fn start_readback(&mut self) {
+        for pending in self.pending.iter_mut().filter(|p| p.receiver.is_none()) {
+            let (sender, receiver) = mpsc::channel();
+            pending
+                .buffer
+                .slice(..pending.count * self.stride)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+            pending.receiver = Some(receiver);
+        }
+    }
+
+    /// 取出已经读回的结果，`wait` 为 `true` 时等待所有结果读回
+    pub fn collect(&mut self, device: &wgpu::Device, wait: bool) -> Vec<Sample> {
+        device.poll(if wait {
+            wgpu::Maintain::Wait
+        } else {
+            wgpu::Maintain::Poll
+        });
+
+        let mut samples = vec![];
+        while let Some(pending) = self.pending.front() {
+            let Some(receiver) = &pending.receiver else {
+                break;
+            };
+            // 结果按提交的顺序完成
+            let mapped = match receiver.try_recv() {
+                Ok(result) => result.is_ok(),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => false,
+            };
+            let pending = self.pending.pop_front().unwrap();
+            if !mapped {
+                continue;
+            }
+
+            {
+                let range = pending
+                    .buffer
+                    .slice(..pending.count * self.stride)
+                    .get_mapped_range();
+                for (i, slot) in range.chunks_exact(self.stride as usize).enumerate() {
+                    let values: Vec<u32> = slot[..RESULT_SIZE as usize]
+                        .chunks_exact(std::mem::size_of::<u32>())
+                        .map(|word| u32::from_ne_bytes(word.try_into().unwrap()))
+                        .collect();
+                    let [low, high, min_x_inverted, min_y_inverted, max_x_end, max_y_end, hash_low, hash_high] =
+                        values[..]
+                    else {
+                        unreachable!("每个结果有 8 个 u32");
+                    };
+                    let population = (high as u64) << u32::BITS | low as u64;
+                    let (min_x, min_y) = (!min_x_inverted, !min_y_inverted);
+                    samples.push(Sample {
+                        generation: pending.generation + i as u64,
+                        population,
+                        bounding_box: (population > 0)
+                            .then(|| (min_x, min_y, max_x_end - min_x, max_y_end - min_y)),
+                        hash: (hash_high as u64) << u32::BITS | hash_low as u64,
+                    });
+                }
+            }
+            pending.buffer.unmap();
+            self.free.push(pending.buffer);
+        }
+        samples
+    }
+}
@@ -0,0 +1,135 @@
+// 统计活细胞的数量、包围盒和整张地图的散列值
+// 每个工作组先用共享内存中的原子量归约，再由一个调用合并到结果中
+
+// 纹理和按位储存的缓冲区，两个入口各用其中一个
+@group(0) @binding(0)
+var map_textre : texture_2d<f32>;
+
+@group(0) @binding(1)
+var<storage, read> map_cells : array<u32>;
+
+// 缓冲区清零后就是初始值：最小坐标按位取反后再取最大值，最大坐标加一
+struct Result {
+    population_low: atomic<u32>,
+    population_high: atomic<u32>,
+    min_x_inverted: atomic<u32>,
+    min_y_inverted: atomic<u32>,
+    max_x_end: atomic<u32>,
+    max_y_end: atomic<u32>,
+    // 每个活细胞的两个散列值分别相加，和细胞的顺序无关
+    hash_low: atomic<u32>,
+    hash_high: atomic<u32>,
+}
+
+@group(0) @binding(2)
+var<storage, read_write> result : Result;
+
+struct MapSize {
+    map_size: vec2u,
+    // 按位储存时每行的字数
+    words_per_row: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(3)
+var<uniform> map_size : MapSize;
+
+var<workgroup> group_population: atomic<u32>;
+var<workgroup> group_min_x_inverted: atomic<u32>;
+var<workgroup> group_min_y_inverted: atomic<u32>;
+var<workgroup> group_max_x_end: atomic<u32>;
+var<workgroup> group_max_y_end: atomic<u32>;
+var<workgroup> group_hash_low: atomic<u32>;
+var<workgroup> group_hash_high: atomic<u32>;
+
+struct ComputeInput {
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+}
+
+// 每个调用负责一个纹素
+@compute @workgroup_size(16, 16)
+fn cs_texture(in: ComputeInput) {
+    let cell = in.global_id.xy;
+    // 工作组可能会超出地图的范围，但所有调用都要走到 `finish` 中的屏障
+    if all(cell < map_size.map_size) && textureLoad(map_textre, vec2<i32>(cell), 0).r > 0.0 {
+        accumulate(1u, cell, cell + 1u, cell_hash(cell));
+    }
+    finish(in.local_index);
+}
+
+// 每个调用负责一个字
+@compute @workgroup_size(16, 16)
+fn cs_packed(in: ComputeInput) {
+    let word_index = in.global_id.x;
+    let y = in.global_id.y;
+    if word_index < map_size.words_per_row && y < map_size.map_size.y {
+        let word = map_cells[y * map_size.words_per_row + word_index];
+        if word != 0u {
+            let x = word_index * 32u;
+            // 散列值要和纹理的一样，所以逐个细胞计算
+            var hash = vec2(0u);
+            var rest = word;
+            while rest != 0u {
+                hash += cell_hash(vec2(x + firstTrailingBit(rest), y));
+                rest &= rest - 1u;
+            }
+            accumulate(
+                countOneBits(word),
+                vec2(x + firstTrailingBit(word), y),
+                vec2(x + firstLeadingBit(word) + 1u, y + 1u),
+                hash,
+            );
+        }
+    }
+    finish(in.local_index);
+}
+
+// 一个活细胞的两个散列值，和 `stats.rs` 中的 `cell_hash` 相同
+fn cell_hash(cell: vec2u) -> vec2u {
+    let index = cell.y * map_size.map_size.x + cell.x;
+    return vec2(mix(index), mix(index ^ 0x9e3779b9u));
+}
+
+// lowbias32
+fn mix(value: u32) -> u32 {
+    var x = value;
+    x ^= x >> 16u;
+    x *= 0x7feb352du;
+    x ^= x >> 15u;
+    x *= 0x846ca68bu;
+    x ^= x >> 16u;
+    return x;
+}
+
+fn accumulate(population: u32, min: vec2u, max_end: vec2u, hash: vec2u) {
+    atomicAdd(&group_population, population);
+    atomicAdd(&group_hash_low, hash.x);
+    atomicAdd(&group_hash_high, hash.y);
+    atomicMax(&group_min_x_inverted, ~min.x);
+    atomicMax(&group_min_y_inverted, ~min.y);
+    atomicMax(&group_max_x_end, max_end.x);
+    atomicMax(&group_max_y_end, max_end.y);
+}
+
+fn finish(local_index: u32) {
+    workgroupBarrier();
+    if local_index != 0u {
+        return;
+    }
+    let population = atomicLoad(&group_population);
+    if population == 0u {
+        return;
+    }
+    // 数量可能超过 u32 的范围，溢出时进位到高位
+    let old = atomicAdd(&result.population_low, population);
+    if old > 0xffffffffu - population {
+        atomicAdd(&result.population_high, 1u);
+    }
+    atomicMax(&result.min_x_inverted, atomicLoad(&group_min_x_inverted));
+    atomicMax(&result.min_y_inverted, atomicLoad(&group_min_y_inverted));
+    atomicMax(&result.max_x_end, atomicLoad(&group_max_x_end));
+    atomicMax(&result.max_y_end, atomicLoad(&group_max_y_end));
+    atomicAdd(&result.hash_low, atomicLoad(&group_hash_low));
+    atomicAdd(&result.hash_high, atomicLoad(&group_hash_high));
+}
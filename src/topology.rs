@@ -0,0 +1,138 @@
+use std::{fmt, str::FromStr};
+
+/// 地图边界的拓扑结构，决定边缘的细胞如何寻找越界的邻居
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Topology {
+    /// 边界外全是死细胞
+    #[default]
+    Dead,
+    /// 环面：左右相接，上下相接
+    Torus,
+    /// 克莱因瓶：左右相接，上下相接时左右翻转
+    KleinBottle,
+    /// 射影平面：左右相接时上下翻转，上下相接时左右翻转
+    ///
+    /// 四个角在这里不是普通的点：斜着越过角的邻居是死细胞，角上的细胞越过左右边和越过上下边
+    /// 的邻居都是对角的细胞，只算一次，所以角上的细胞只有 6 个邻居
+    CrossSurface,
+}
+
+impl Topology {
+    pub const ALL: [Topology; 4] = [
+        Topology::Dead,
+        Topology::Torus,
+        Topology::KleinBottle,
+        Topology::CrossSurface,
+    ];
+
+    /// 命令行和文件中使用的名字
+    pub fn name(self) -> &'static str {
+        match self {
+            Topology::Dead => "dead",
+            Topology::Torus => "torus",
+            Topology::KleinBottle => "klein",
+            Topology::CrossSurface => "cross",
+        }
+    }
+
+    /// 越界的坐标在地图上对应的位置，邻居是死细胞时返回 `None`
+    ///
+    /// 和 `compute.wgsl` 中的 `is_life` 保持一致
+    pub fn locate(self, (x, y): (i64, i64), map_size: (u32, u32)) -> Option<(u32, u32)> {
+        let (width, height) = (map_size.0 as i64, map_size.1 as i64);
+        let (mut x, mut y) = (x, y);
+
+        let outside_x = x < 0 || x >= width;
+        let outside_y = y < 0 || y >= height;
+        if outside_x || outside_y {
+            if self == Topology::Dead {
+                return None;
+            }
+            // 射影平面上斜着越过角的位置不在地图上
+            if self == Topology::CrossSurface && outside_x && outside_y {
+                return None;
+            }
+            // 从左右两边越界
+            if x < 0 || x >= width {
+                x = x.rem_euclid(width);
+                if self == Topology::CrossSurface {
+                    y = height - 1 - y;
+                }
+            }
+            // 从上下两边越界
+            if y < 0 || y >= height {
+                y = y.rem_euclid(height);
+                if matches!(self, Topology::KleinBottle | Topology::CrossSurface) {
+                    x = width - 1 - x;
+                }
+            }
+        }
+
+        Some((x as u32, y as u32))
+    }
+
+    /// (x, y) 在 (dx, dy) 方向上的邻居，邻居是死细胞或者和别的方向重复时返回 `None`
+    ///
+    /// 射影平面上角的细胞越过上下边的邻居和越过左右边的是同一个，只算左右的那个。
+    /// 和 `compute.wgsl` 中的 `cs_main` 保持一致
+    pub fn neighbor(
+        self,
+        (x, y): (i64, i64),
+        (dx, dy): (i64, i64),
+        map_size: (u32, u32),
+    ) -> Option<(u32, u32)> {
+        let (width, height) = (map_size.0 as i64, map_size.1 as i64);
+        if self == Topology::CrossSurface
+            && dx == 0
+            && (x == 0 || x == width - 1)
+            && (y + dy < 0 || y + dy >= height)
+        {
+            return None;
+        }
+        self.locate((x + dx, y + dy), map_size)
+    }
+
+    /// 着色器中 `MapSize.topology` 的值
+    pub fn to_uniform(self) -> u32 {
+        match self {
+            Topology::Dead => 0,
+            Topology::Torus => 1,
+            Topology::KleinBottle => 2,
+            Topology::CrossSurface => 3,
+        }
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// 无法识别的拓扑名字
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTopologyError(String);
+
+impl fmt::Display for ParseTopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "未知的拓扑 `{}`，可选的有 dead、torus、klein 和 cross",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseTopologyError {}
+
+impl FromStr for Topology {
+    type Err = ParseTopologyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        Topology::ALL
+            .into_iter()
+            .find(|topology| topology.name() == name)
+            .ok_or_else(|| ParseTopologyError(s.to_string()))
+    }
+}
@@ -0,0 +1,45 @@
+//! 细胞的年龄和尾迹：放在纹理的 g 和 b 通道中，由计算管线每代更新
+
+mod common;
+
+use life_game_wgpu::{
+    pattern::Pattern,
+    resources::{age, trail},
+    rule::Rule,
+    topology::Topology,
+    Engine, Storage,
+};
+
+#[test]
+fn ages_and_trails() {
+    let Some(mut sim) = common::simulator((32, 32), Rule::CONWAY, Topology::Dead, Storage::Texture)
+    else {
+        return;
+    };
+    if sim.is_cpu_fallback() {
+        eprintln!("在CPU上演化时没有年龄，跳过");
+        return;
+    }
+    let pattern = |rle: &str| Pattern::from_rle(&format!("x = 3, y = 3\n{rle}"), (32, 32)).unwrap();
+    // 方块、闪光灯和一个会立刻死掉的细胞
+    sim.write_cells((10, 10), &pattern("2o$2o!")).unwrap();
+    sim.write_cells((20, 10), &pattern("3o!")).unwrap();
+    sim.set_cell((5, 5), true).unwrap();
+
+    sim.step(5);
+    let texels = sim.read_texels().unwrap();
+    let at = |x: u32, y: u32| texels[(y * 32 + x) as usize];
+
+    assert_eq!(age(at(10, 10)), Some(5));
+    assert_eq!(age(at(11, 11)), Some(5));
+    // 闪光灯竖着，中间的细胞一直活着，两头刚出生，横着的两头刚死去
+    assert_eq!(age(at(21, 10)), Some(5));
+    assert_eq!(age(at(21, 9)), Some(0));
+    assert_eq!(age(at(21, 11)), Some(0));
+    assert_eq!(age(at(20, 10)), None);
+    assert_eq!(trail(at(20, 10)), 255);
+    // 第 1 代死去，之后每代减一
+    assert_eq!(trail(at(5, 5)), 251);
+    // 从来没有活过的细胞没有尾迹
+    assert_eq!(trail(at(0, 0)), 0);
+}
@@ -0,0 +1,291 @@
+//! 在GPU（`Simulator`）和CPU（`CpuEngine`）上演化同样的图案，逐个细胞比较结果
+//!
+//! 没有可用的适配器时只运行CPU的部分
+
+mod common;
+
+use life_game_wgpu::{
+    cpu::CpuEngine, pattern::Pattern, rule::Rule, stats::Sample, topology::Topology, Engine,
+    Simulator, Storage,
+};
+
+const BLINKER: &str = "x = 3, y = 1\n3o!";
+const GLIDER: &str = "x = 3, y = 3\nbo$2bo$3o!";
+const R_PENTOMINO: &str = "x = 3, y = 3\nb2o$2o$bo!";
+const GOSPER_GLIDER_GUN: &str = "\
+x = 36, y = 9, rule = B3/S23
+24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4b
+obo$10bo5bo7bo$11bo3bo$12b2o!";
+
+/// 同一个图案分别放在GPU和CPU上
+struct Engines {
+    gpu: Option<Simulator>,
+    cpu: CpuEngine,
+}
+
+/// 地图能使用的储存方式，按位储存要求宽度是 32 的倍数
+///
+/// 测试依次使用每一种方式，同时存在多个实例时，有的驱动在其中一个销毁后会出错
+fn storages(map_size: (u32, u32)) -> Vec<Storage> {
+    if map_size.0.is_multiple_of(32) {
+        vec![Storage::Texture, Storage::Packed]
+    } else {
+        vec![Storage::Texture]
+    }
+}
+
+impl Engines {
+    fn new(map_size: (u32, u32), rule: Rule, topology: Topology, storage: Storage) -> Self {
+        Self {
+            gpu: common::simulator(map_size, rule, topology, storage),
+            cpu: CpuEngine::new(map_size, rule, topology),
+        }
+    }
+
+    fn write(&mut self, offset: (u32, u32), rle: &str) {
+        let pattern = Pattern::from_rle(rle, self.cpu.map_size()).unwrap();
+        if let Some(gpu) = &mut self.gpu {
+            gpu.write_cells(offset, &pattern).unwrap();
+        }
+        self.cpu.write_cells(offset, &pattern).unwrap();
+    }
+
+    fn step(&mut self, n: u64) {
+        if let Some(gpu) = &mut self.gpu {
+            gpu.step(n);
+        }
+        self.cpu.step(n);
+    }
+
+    /// 逐个细胞比较，再比较统计结果，返回CPU上的结果
+    fn compare(&mut self) -> Pattern {
+        let cpu = self.cpu.read_cells().unwrap();
+        if let Some(gpu) = &mut self.gpu {
+            let sample = *gpu.wait_stats().latest().unwrap();
+            assert_eq!(sample.generation, self.cpu.generation());
+            assert_eq!(sample.population, population(&cpu) as u64);
+            assert_eq!(sample.bounding_box, cpu.bounding_box());
+            assert_eq!(sample.hash, Sample::of(&cpu, 0).hash);
+
+            assert_eq!(gpu.generation(), self.cpu.generation());
+            let storage = gpu.storage();
+            let gpu = gpu.read_cells().unwrap();
+            if let Some(index) = (0..cpu.cells.len()).find(|&i| cpu.cells[i] != gpu.cells[i]) {
+                panic!(
+                    "第 {} 代的 ({}, {}) 不一致：GPU（{:?}）{}，CPU {}",
+                    self.cpu.generation(),
+                    index as u32 % cpu.width,
+                    index as u32 / cpu.width,
+                    storage,
+                    gpu.cells[index],
+                    cpu.cells[index],
+                );
+            }
+        }
+        cpu
+    }
+}
+
+/// 固定种子的伪随机汤
+fn soup(width: u32, height: u32) -> Pattern {
+    let mut seed = 0x2545_f491_u32;
+    let mut soup = Pattern::new(width, height);
+    for cell in &mut soup.cells {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        *cell = seed & 1 == 0;
+    }
+    soup
+}
+
+/// 把每个细胞 (x, y) 移到 `to(x, y)`
+fn transform(pattern: &Pattern, to: impl Fn(u32, u32) -> (u32, u32)) -> Pattern {
+    let mut result = Pattern::new(pattern.width, pattern.height);
+    for y in 0..pattern.height {
+        for x in 0..pattern.width {
+            let (to_x, to_y) = to(x, y);
+            result.cells[(to_y * pattern.width + to_x) as usize] = pattern.get(x, y);
+        }
+    }
+    result
+}
+
+fn population(pattern: &Pattern) -> usize {
+    pattern.cells.iter().filter(|&&alive| alive).count()
+}
+
+#[test]
+fn blinker() {
+    for storage in storages((32, 16)) {
+        let mut engines = Engines::new((32, 16), Rule::CONWAY, Topology::Dead, storage);
+        engines.write((14, 7), BLINKER);
+        let start = engines.compare();
+        for _ in 0..4 {
+            engines.step(1);
+            let pattern = engines.compare();
+            assert_eq!(population(&pattern), 3);
+            assert_eq!(engines.cpu.generation().is_multiple_of(2), pattern == start);
+        }
+    }
+}
+
+#[test]
+fn painting_cells() {
+    // 一次画一组细胞（跨过按位储存的字边界），再擦掉其中几个，和CPU上逐个写入的结果一样
+    let line: Vec<(u32, u32)> = (20..50).map(|x| (x, x / 3)).collect();
+    for storage in storages((64, 24)) {
+        let mut engines = Engines::new((64, 24), Rule::CONWAY, Topology::Torus, storage);
+        let mut expected = Pattern::new(64, 24);
+        for &(x, y) in &line {
+            expected.cells[(y * 64 + x) as usize] = x % 7 != 0;
+        }
+        if let Some(gpu) = &mut engines.gpu {
+            let before = gpu.wait_stats().samples().len();
+            gpu.set_cells(&line, true).unwrap();
+            let erased: Vec<(u32, u32)> =
+                line.iter().copied().filter(|(x, _)| x % 7 == 0).collect();
+            gpu.set_cells(&erased, false).unwrap();
+            gpu.set_cells(&[], true).unwrap();
+            assert!(gpu.set_cells(&[(10, 10), (64, 0)], true).is_err());
+            // 每次画都只统计一次
+            assert_eq!(gpu.wait_stats().samples().len(), before + 2);
+        }
+        engines.cpu.write_cells((0, 0), &expected).unwrap();
+        assert_eq!(engines.compare().cells, expected.cells);
+        engines.step(8);
+        engines.compare();
+    }
+}
+
+#[test]
+fn glider_crosses_every_topology() {
+    for topology in Topology::ALL {
+        for storage in storages((32, 24)) {
+            // 宽和高不同，可以发现弄反了宽高的错误
+            let mut engines = Engines::new((32, 24), Rule::CONWAY, topology, storage);
+            engines.write((26, 18), GLIDER);
+            for _ in 0..20 {
+                engines.step(10);
+                engines.compare();
+            }
+        }
+    }
+}
+
+#[test]
+fn glider_returns_on_square_maps() {
+    // 不依赖 `locate` 的检查：滑翔机每 4 代沿对角线移动一格，在 W×W 的地图上 4W 代之后
+    // 横竖各穿过一次边界。环面上回到原处，克莱因瓶上左右翻转，交叉帽上旋转 180 度
+    const SIZE: u32 = 32;
+    let topologies = [
+        Topology::Torus,
+        Topology::KleinBottle,
+        Topology::CrossSurface,
+    ];
+    for topology in topologies {
+        for storage in storages((SIZE, SIZE)) {
+            let mut engines = Engines::new((SIZE, SIZE), Rule::CONWAY, topology, storage);
+            // 离对角线远一些，不经过角
+            engines.write((4, 14), GLIDER);
+            let start = engines.compare();
+            engines.step(4 * SIZE as u64);
+            let expected = match topology {
+                Topology::KleinBottle => transform(&start, |x, y| (SIZE - 1 - x, y)),
+                Topology::CrossSurface => transform(&start, |x, y| (SIZE - 1 - x, SIZE - 1 - y)),
+                _ => start,
+            };
+            assert_eq!(engines.compare().cells, expected.cells, "{topology:?}");
+        }
+    }
+}
+
+#[test]
+fn cross_surface_corners() {
+    // 角上的细胞只有 6 个邻居：自己不算，对角的细胞只算一次。B1/S1 下孤立的角细胞死去，
+    // 6 个邻居出生
+    const SIZE: u32 = 32;
+    let last = SIZE - 1;
+    for corner in [(0, 0), (last, 0), (0, last), (last, last)] {
+        for storage in storages((SIZE, SIZE)) {
+            let rule = "B1/S1".parse().unwrap();
+            let mut engines = Engines::new((SIZE, SIZE), rule, Topology::CrossSurface, storage);
+            engines.write(corner, "x = 1, y = 1\no!");
+            engines.step(1);
+
+            let inward = |(x, y): (u32, u32), (dx, dy): (i64, i64)| {
+                let step = |v: u32, d: i64| if v == 0 { v as i64 + d } else { v as i64 - d };
+                (step(x, dx) as u32, step(y, dy) as u32)
+            };
+            let opposite = (last - corner.0, last - corner.1);
+            let mut expected = Pattern::new(SIZE, SIZE);
+            for cell in [
+                inward(corner, (1, 0)),
+                inward(corner, (0, 1)),
+                inward(corner, (1, 1)),
+                opposite,
+                inward(opposite, (1, 0)),
+                inward(opposite, (0, 1)),
+            ] {
+                expected.cells[(cell.1 * SIZE + cell.0) as usize] = true;
+            }
+            assert_eq!(engines.compare().cells, expected.cells, "{corner:?}");
+        }
+    }
+}
+
+#[test]
+fn r_pentomino_stabilizes_at_1103() {
+    for storage in storages((768, 768)) {
+        // 逃逸的滑翔机在 1103 代时离中心将近 300 格
+        let mut engines = Engines::new((768, 768), Rule::CONWAY, Topology::Dead, storage);
+        engines.write((383, 383), R_PENTOMINO);
+        engines.step(1103);
+        assert_eq!(population(&engines.compare()), 116);
+    }
+}
+
+#[test]
+fn gosper_glider_gun() {
+    for storage in storages((128, 128)) {
+        let mut engines = Engines::new((128, 128), Rule::CONWAY, Topology::Dead, storage);
+        engines.write((4, 4), GOSPER_GLIDER_GUN);
+        for _ in 0..4 {
+            engines.step(60);
+            engines.compare();
+        }
+        // 每 30 代发射一个 5 个细胞的滑翔机，240 代时枪回到初始的相位，还没有滑翔机撞到边界
+        assert_eq!(population(&engines.compare()), 36 + 8 * 5);
+    }
+}
+
+#[test]
+fn other_rules() {
+    let rules = ["B36/S23", "B3678/S34678", "B2/S", "B3/S12345", "B0/S8"];
+    for rule in rules {
+        let rule: Rule = rule.parse().unwrap();
+        for storage in storages((64, 48)) {
+            let mut engines = Engines::new((64, 48), rule, Topology::Torus, storage);
+            engines.write((20, 12), &soup(24, 24).to_rle());
+            for _ in 0..10 {
+                engines.step(7);
+                engines.compare();
+            }
+        }
+    }
+}
+
+#[test]
+fn soup_across_words() {
+    // 铺满整张地图，按位储存时每行有三个字，字与字之间和地图边缘的细胞都会被检查到
+    for topology in Topology::ALL {
+        for storage in storages((96, 40)) {
+            let mut engines = Engines::new((96, 40), Rule::CONWAY, topology, storage);
+            engines.write((0, 0), &soup(96, 40).to_rle());
+            for _ in 0..10 {
+                engines.step(5);
+                engines.compare();
+            }
+        }
+    }
+}
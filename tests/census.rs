@@ -0,0 +1,98 @@
+//! 汤搜索：物体的标准形式要和 Catagolue 上的一样，同一个种子的结果不随储存方式和地图的大小变化
+
+mod common;
+
+use life_game_wgpu::{
+    census::{classify, soup, Kind, SoupSearch},
+    pattern::Pattern,
+    rule::Rule,
+    topology::Topology,
+    Storage,
+};
+
+#[test]
+fn known_objects() {
+    for (rle, apgcode, kind) in [
+        ("2o$2o!", "xs4_33", Kind::StillLife),
+        ("b2o$o2bo$b2o!", "xs6_696", Kind::StillLife),
+        ("b2o$o2bo$bobo$2bo!", "xs7_2596", Kind::StillLife),
+        ("2o$obo$bo!", "xs5_253", Kind::StillLife),
+        ("bo$obo$bo!", "xs4_252", Kind::StillLife),
+        ("b2o$o2bo$o2bo$b2o!", "xs8_6996", Kind::StillLife),
+        ("3o!", "xp2_7", Kind::Oscillator),
+        ("b3o$3o!", "xp2_7e", Kind::Oscillator),
+        ("2o$2o$2b2o$2b2o!", "xp2_318c", Kind::Oscillator),
+        ("bo$2bo$3o!", "xq4_153", Kind::Spaceship),
+        ("bo2bo$o$o3bo$4o!", "xq4_6frc", Kind::Spaceship),
+    ] {
+        let pattern = Pattern::from_rle(&format!("x = 5, y = 5\n{rle}"), (5, 5)).unwrap();
+        let object = classify(&pattern, Rule::CONWAY);
+        assert_eq!(object.apgcode, apgcode, "{rle}");
+        assert_eq!(object.kind, kind, "{rle}");
+    }
+}
+
+#[test]
+fn soups_are_reproducible() {
+    assert_eq!(soup(7, (16, 16), 0.5), soup(7, (16, 16), 0.5));
+    assert_ne!(soup(7, (16, 16), 0.5), soup(8, (16, 16), 0.5));
+}
+
+#[test]
+fn search_agrees_across_storages() {
+    let mut results = vec![];
+    for storage in [Storage::Texture, Storage::Packed] {
+        let Some(sim) = common::simulator((128, 128), Rule::CONWAY, Topology::Torus, storage)
+        else {
+            return;
+        };
+        let mut search = SoupSearch::new(sim, (16, 16), 0.5).unwrap();
+        let mut objects = vec![];
+        for seed in 0..4 {
+            let mut found = search.run(seed).unwrap().expect("小的汤很快就会稳定");
+            found.sort_by(|a, b| a.apgcode.cmp(&b.apgcode));
+            objects.push(found);
+        }
+        assert_eq!(search.census.soups, 4);
+        assert!(search
+            .census
+            .entries()
+            .iter()
+            .all(|(_, entry)| entry.kind != Kind::Unknown));
+        results.push(objects);
+    }
+    if let [texture, packed] = &results[..] {
+        assert_eq!(texture, packed);
+    }
+}
+
+#[test]
+fn escaping_gliders_do_not_depend_on_map_size() {
+    // 这两个汤都会放出滑翔机，飞出去的滑翔机被记下来删掉，不会绕过环面撞到剩下的部分
+    let mut results = vec![];
+    for map_size in [(96, 96), (160, 160)] {
+        let Some(sim) =
+            common::simulator(map_size, Rule::CONWAY, Topology::Torus, Storage::Texture)
+        else {
+            return;
+        };
+        let mut search = SoupSearch::new(sim, (16, 16), 0.5).unwrap();
+        for seed in [7, 9] {
+            search
+                .run(seed)
+                .unwrap()
+                .expect("放出滑翔机之后很快就会稳定");
+        }
+        let entries: Vec<(String, u64)> = search
+            .census
+            .entries()
+            .iter()
+            .map(|&(apgcode, entry)| (apgcode.to_string(), entry.count))
+            .collect();
+        assert_eq!(search.census.get("xq4_153").unwrap().count, 3);
+        results.push(entries);
+    }
+    if let [small, large] = &results[..] {
+        assert_eq!(small, large);
+    }
+}
@@ -0,0 +1,25 @@
+//! 各个测试共用的辅助函数
+
+use life_game_wgpu::{rule::Rule, topology::Topology, Simulator, Storage};
+
+/// 创建模拟器，只有没有可用的适配器时才返回 `None` 跳过测试，其他错误让测试失败
+pub fn simulator(
+    map_size: (u32, u32),
+    rule: Rule,
+    topology: Topology,
+    storage: Storage,
+) -> Option<Simulator> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        ..Default::default()
+    });
+    if let Err(error) = pollster::block_on(life_game_wgpu::request_adapter(&instance, None)) {
+        eprintln!("没有可用的GPU，跳过：{error}");
+        return None;
+    }
+    drop(instance);
+    match pollster::block_on(Simulator::new(map_size, rule, topology, storage)) {
+        Ok(sim) => Some(sim),
+        Err(error) => panic!("无法创建模拟器：{error}"),
+    }
+}
@@ -0,0 +1,135 @@
+//! 图案格式：按内容识别，同一个图案在各种格式中读出来一样，出错时报告行号
+
+use life_game_wgpu::{
+    census::soup,
+    pattern::{Error, Format, Pattern},
+    rule::Rule,
+};
+
+const MAP: (u32, u32) = (64, 64);
+
+const FILES: [(&str, Format); 5] = [
+    (
+        "#N 滑翔机\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!",
+        Format::Rle,
+    ),
+    (
+        "!Name: 滑翔机\n!Rule: B3/S23\n.O\n..O\nOOO\n",
+        Format::Cells,
+    ),
+    (
+        "#Life 1.05\n#D 滑翔机\n#N\n#P -1 -1\n.*\n..*\n***\n",
+        Format::Life105,
+    ),
+    (
+        "#Life 1.06\n#D 滑翔机\n0 -1\n1 0\n-1 1\n0 1\n1 1\n",
+        Format::Life106,
+    ),
+    (
+        "[M2] (golly 2.0)\n#R B3/S23\n.*$..*$***$\n4 1 0 0 0\n",
+        Format::Macrocell,
+    ),
+];
+
+#[test]
+fn detects_formats() {
+    let glider = Pattern::from_rle("x = 3, y = 3\nbo$2bo$3o!", MAP).unwrap();
+    for (text, format) in FILES {
+        assert_eq!(Format::detect(text), format, "{text}");
+        let pattern = Pattern::parse(text, MAP).unwrap();
+        assert_eq!(pattern.cells, glider.cells, "{text}");
+        // Life 1.06 中没有规则
+        let rule = (format != Format::Life106).then_some(Rule::CONWAY);
+        assert_eq!(pattern.rule, rule, "{format:?}");
+        assert_eq!(pattern.centered_offset(MAP), (30, 30));
+    }
+
+    // 两个块，第二个块在第一个的左上方
+    let blocks = "#Life 1.05\n#R 23/36\n#P 2 2\n**\n**\n#P -3 -1\n*\n";
+    let pattern = Pattern::parse(blocks, MAP).unwrap();
+    assert_eq!((pattern.width, pattern.height), (7, 5));
+    assert_eq!(pattern.cells.iter().filter(|&&alive| alive).count(), 5);
+    assert!(pattern.get(0, 0) && pattern.get(5, 3) && pattern.get(6, 4));
+    assert_eq!(pattern.rule, Some("B36/S23".parse().unwrap()));
+
+    // `.cells` 可以原样读回来
+    let mut soup = soup(9, (23, 17), 0.5).cropped();
+    soup.name = Some("汤".to_string());
+    soup.comments = vec!["generation 0".to_string()];
+    assert_eq!(Pattern::parse(&soup.to_cells(), MAP).unwrap(), soup);
+}
+
+#[test]
+fn reports_lines() {
+    for (text, line) in [
+        ("#Life 1.06\n0 0\n1\n", 3),
+        ("#Life 1.06\n\n0 0\n1 x\n", 4),
+        ("#Life 1.05\n#P 0 0\n.*.\n.o.\n", 4),
+        ("#Life 1.05\n#P 0\n", 2),
+        ("#Life 1.05\n#R 2x/3\n", 2),
+        ("#Life 1.05\n#P 9223372036854775807 0\n**\n", 3),
+        ("#Life 1.05\n#P 0 9223372036854775807\n*\n*\n", 4),
+        ("!Name: 滑翔机\n.O\n..X\n", 3),
+        ("!Rule: B9\n", 1),
+        ("x = 3, y = 3\nbo$\n2bo$3p!", 3),
+    ] {
+        match Pattern::parse(text, MAP) {
+            Err(Error::Parse { line: actual, .. }) => assert_eq!(actual, line, "{text}"),
+            other => panic!("{text}: {other:?}"),
+        }
+    }
+
+    // 坐标相距很远时不分配内存
+    match Pattern::parse("#Life 1.06\n0 0\n-2000000000 2000000000\n", MAP) {
+        Err(Error::TooLarge { size, .. }) => assert_eq!(size, (2_000_000_001, 2_000_000_001)),
+        other => panic!("{other:?}"),
+    }
+    match Pattern::parse(
+        "#Life 1.06\n-9223372036854775808 0\n9223372036854775807 0\n",
+        MAP,
+    ) {
+        Err(Error::TooLarge { size, .. }) => assert_eq!(size, (u32::MAX, 1)),
+        other => panic!("{other:?}"),
+    }
+    assert!(Pattern::parse("", MAP).is_err());
+}
+
+#[test]
+fn rle_overflow() {
+    // 声明的大小、重复次数和偏移太大时报错，不会溢出
+    for (text, line) in [
+        ("x = 70000, y = 70000\no!", 1),
+        ("x = 3, y = 3\n2o4294967295o!", 2),
+        ("x = 3, y = 3\n2b4294967295b!", 2),
+        ("x = 3, y = 3\n$\n4294967295$o!", 3),
+    ] {
+        match Pattern::from_rle(text, (u32::MAX, u32::MAX)) {
+            Err(Error::Parse { line: actual, .. }) => assert_eq!(actual, line, "{text}"),
+            other => panic!("{text}: {other:?}"),
+        }
+    }
+
+    // 头部声明的大小放不进地图时，在分配细胞之前就报错
+    for text in [
+        "x = 65535, y = 65535\no!",
+        "x = 65, y = 1\no!",
+        "x = 1, y = 65\no!",
+    ] {
+        match Pattern::parse(text, MAP) {
+            Err(Error::TooLarge { size, map_size, .. }) => {
+                assert!(size.0 > MAP.0 || size.1 > MAP.1, "{text}");
+                assert_eq!(map_size, MAP);
+            }
+            other => panic!("{text}: {other:?}"),
+        }
+    }
+
+    let glider = Pattern::from_rle("x = 3, y = 3\nbo$2bo$3o!", MAP).unwrap();
+    for offset in [(u32::MAX, 0), (0, u32::MAX - 1)] {
+        let mut map = vec![[0; 4]; (MAP.0 * MAP.1) as usize];
+        match glider.place(&mut map, MAP, offset) {
+            Err(Error::TooLarge { .. }) => {}
+            other => panic!("{offset:?}: {other:?}"),
+        }
+    }
+}
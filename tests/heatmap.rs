@@ -0,0 +1,54 @@
+//! 热度图：每个细胞在窗口内变化的次数
+
+mod common;
+
+use life_game_wgpu::{
+    pattern::Pattern, resources::Gradient, rule::Rule, topology::Topology, Engine, Storage,
+};
+
+#[test]
+fn blinker_changes() {
+    for storage in [Storage::Texture, Storage::Packed] {
+        let Some(mut sim) = common::simulator((64, 16), Rule::CONWAY, Topology::Dead, storage)
+        else {
+            return;
+        };
+        if sim.enable_heatmap(4).is_err() {
+            assert!(sim.is_cpu_fallback());
+            return;
+        }
+        // 跨过第一个字的边界
+        let blinker = Pattern::from_rle("x = 3, y = 1\n3o!", sim.map_size()).unwrap();
+        sim.write_cells((31, 7), &blinker).unwrap();
+
+        sim.step(3);
+        let heat = sim.read_heatmap().unwrap().unwrap();
+        let at = |x: u32, y: u32| heat[(y * 64 + x) as usize];
+        // 中间的细胞一直活着，四个角上的细胞每代都变
+        assert_eq!(at(32, 7), 0, "{storage:?}");
+        assert_eq!(at(31, 7), 3, "{storage:?}");
+        assert_eq!(at(32, 6), 3, "{storage:?}");
+        assert_eq!(at(33, 8), 0, "{storage:?}");
+        assert_eq!(at(0, 0), 0, "{storage:?}");
+
+        // 只数最近 4 代
+        sim.step(10);
+        let heat = sim.read_heatmap().unwrap().unwrap();
+        assert_eq!(heat[7 * 64 + 33], 4, "{storage:?}");
+        assert_eq!(heat.iter().filter(|&&changes| changes > 0).count(), 4);
+    }
+}
+
+#[test]
+fn parse_gradient() {
+    let gradient: Gradient = "#000000, #ff000080".parse().unwrap();
+    assert_eq!(
+        gradient.stops(),
+        [[0.0, 0.0, 0.0, 1.0], [1.0, 0.0, 0.0, 128.0 / 255.0]]
+    );
+    assert!("#fff".parse::<Gradient>().is_err());
+    assert!("#ffffff".parse::<Gradient>().is_err());
+    assert!("#ffffff,#ffffff,#ffffff,#ffffff,#ffffff"
+        .parse::<Gradient>()
+        .is_err());
+}
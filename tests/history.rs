@@ -0,0 +1,123 @@
+//! 倒退和撤销：倒退之后的地图必须和当时演化出来的一样
+
+mod common;
+
+use life_game_wgpu::{
+    cpu::CpuEngine, pattern::Pattern, rule::Rule, topology::Topology, Engine, Simulator, Storage,
+};
+
+/// 没有可用的适配器时返回 `None`，跳过测试
+fn simulator(storage: Storage) -> Option<Simulator> {
+    common::simulator((64, 48), Rule::CONWAY, Topology::Torus, storage)
+}
+
+/// 固定种子的伪随机汤
+fn soup() -> Pattern {
+    let mut seed = 0x9e37_79b9_u32;
+    let mut soup = Pattern::new(32, 32);
+    for cell in &mut soup.cells {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        *cell = seed & 1 == 0;
+    }
+    soup
+}
+
+fn cells(sim: &Simulator) -> Vec<bool> {
+    sim.read_cells().unwrap().cells
+}
+
+#[test]
+fn step_back_to_the_first_keyframe() {
+    for storage in [Storage::Texture, Storage::Packed] {
+        let Some(mut sim) = simulator(storage) else {
+            return;
+        };
+        sim.write_cells((16, 8), &soup()).unwrap();
+        sim.enable_history(10, 4);
+
+        let mut generations = vec![cells(&sim)];
+        for _ in 0..35 {
+            sim.update();
+            generations.push(cells(&sim));
+        }
+
+        for generation in (0..35).rev() {
+            assert!(sim.step_back());
+            assert_eq!(sim.generation(), generation);
+            assert!(cells(&sim) == generations[generation as usize]);
+        }
+        assert!(!sim.step_back());
+        assert_eq!(sim.history_range(), Some((0, 0)));
+    }
+}
+
+#[test]
+fn seek_after_a_long_jump() {
+    for storage in [Storage::Texture, Storage::Packed] {
+        // 用CPU上的引擎准备每一代的结果，同时存在两个GPU实例时有的驱动会出错
+        let mut cpu = CpuEngine::new((64, 48), Rule::CONWAY, Topology::Torus);
+        cpu.write_cells((16, 8), &soup()).unwrap();
+        let mut generations = vec![cpu.read_cells().unwrap().cells];
+        for _ in 0..50 {
+            cpu.update();
+            generations.push(cpu.read_cells().unwrap().cells);
+        }
+
+        let Some(mut sim) = simulator(storage) else {
+            return;
+        };
+        sim.write_cells((16, 8), &soup()).unwrap();
+        // 只能保存 3 个关键帧，最早能退到第 30 代
+        sim.enable_history(10, 3);
+        sim.step(50);
+        assert_eq!(sim.history_range(), Some((30, 50)));
+        assert!(!sim.seek(29));
+
+        for generation in [47, 33, 41, 30] {
+            assert!(sim.seek(generation));
+            assert!(cells(&sim) == generations[generation as usize]);
+        }
+        // 倒退之后，之后的关键帧不再是历史，但可以重新演化过去
+        assert!(sim.seek(50));
+        assert!(cells(&sim) == generations[50]);
+    }
+}
+
+#[test]
+fn undo_edits() {
+    for storage in [Storage::Texture, Storage::Packed] {
+        let Some(mut sim) = simulator(storage) else {
+            return;
+        };
+        sim.write_cells((16, 8), &soup()).unwrap();
+        sim.enable_history(10, 8);
+        sim.step(5);
+        let before = cells(&sim);
+        let glider = Pattern::from_rle("x = 3, y = 3\nbo$2bo$3o!", sim.map_size()).unwrap();
+        sim.write_cells((2, 2), &glider).unwrap();
+        sim.set_cell((60, 40), true).unwrap();
+        let after = cells(&sim);
+        assert!(before != after);
+
+        // 没有演化时直接撤销编辑
+        assert!(sim.step_back());
+        assert!(cells(&sim) == before);
+
+        // 演化之后，先退回编辑后的那一代，再撤销编辑
+        sim.write_cells((2, 2), &glider).unwrap();
+        sim.set_cell((60, 40), true).unwrap();
+        sim.step(3);
+        for _ in 0..3 {
+            assert!(sim.step_back());
+        }
+        assert_eq!(sim.generation(), 5);
+        assert!(cells(&sim) == after);
+        assert!(sim.step_back());
+        assert_eq!(sim.generation(), 5);
+        assert!(cells(&sim) == before);
+        assert!(sim.step_back());
+        assert_eq!(sim.generation(), 4);
+    }
+}
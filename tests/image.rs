@@ -0,0 +1,82 @@
+//! PNG 图片：导出的黑白图片可以原样读回来，亮度阈值和透明度，带通道的导出和纹理一致
+
+mod common;
+
+use life_game_wgpu::{
+    census::soup,
+    pattern::{Error, Pattern, DEFAULT_THRESHOLD},
+    rule::Rule,
+    topology::Topology,
+    Engine, Storage,
+};
+
+fn encode(size: (u32, u32), color_type: png::ColorType, data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, size.0, size.1);
+    encoder.set_color(color_type);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(data).unwrap();
+    writer.finish().unwrap();
+    bytes
+}
+
+#[test]
+fn threshold_and_alpha() {
+    let soup = soup(5, (37, 21), 0.4);
+    let png = soup.to_png().unwrap();
+    assert_eq!(
+        Pattern::from_png(&png[..], DEFAULT_THRESHOLD).unwrap(),
+        soup
+    );
+
+    // 灰度：比阈值暗的是活细胞
+    let gray = encode((4, 1), png::ColorType::Grayscale, &[0, 99, 100, 255]);
+    let cells = |threshold| Pattern::from_png(&gray[..], threshold).unwrap().cells;
+    assert_eq!(cells(100), [true, true, false, false]);
+    assert_eq!(cells(DEFAULT_THRESHOLD), [true, true, true, false]);
+
+    // 彩色和透明：红色很暗，黄色很亮，透明的黑色当作白色，半透明的黑色是灰色
+    let rgba = encode(
+        (4, 1),
+        png::ColorType::Rgba,
+        &[255, 0, 0, 255, 255, 255, 0, 255, 0, 0, 0, 0, 0, 0, 0, 192],
+    );
+    let pattern = Pattern::from_png(&rgba[..], DEFAULT_THRESHOLD).unwrap();
+    assert_eq!((pattern.width, pattern.height), (4, 1));
+    assert_eq!(pattern.cells, [true, false, false, true]);
+
+    assert!(matches!(
+        Pattern::from_png(&b"not a png"[..], DEFAULT_THRESHOLD),
+        Err(Error::Image(_))
+    ));
+}
+
+#[test]
+fn saves_channels() {
+    let size = (64, 40);
+    for storage in [Storage::Texture, Storage::Packed] {
+        let Some(mut sim) = common::simulator(size, Rule::CONWAY, Topology::Torus, storage) else {
+            return;
+        };
+        sim.write_cells((8, 4), &soup(11, (40, 30), 0.4)).unwrap();
+        sim.step(7);
+
+        let path = std::env::temp_dir().join(format!("life_game_wgpu_{storage:?}.png"));
+        sim.save_image(&path, false).unwrap();
+        let image = Pattern::from_png(std::fs::File::open(&path).unwrap(), DEFAULT_THRESHOLD);
+        let mut expected = sim.read_cells().unwrap();
+        expected.rule = None;
+        assert_eq!(image.unwrap(), expected, "{storage:?}");
+
+        sim.save_image(&path, true).unwrap();
+        let decoder = png::Decoder::new(std::fs::File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((info.width, info.height), size);
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        let texels = sim.read_texels().unwrap();
+        assert_eq!(data, texels.concat(), "{storage:?}");
+    }
+}
@@ -0,0 +1,107 @@
+//! macrocell：读 Golly 写的文件，写出去再读回来不变，重复的子树只写一次
+
+mod common;
+
+use life_game_wgpu::{
+    census::soup,
+    pattern::{Error, Pattern},
+    rule::Rule,
+    topology::Topology,
+    Engine, Storage,
+};
+
+const GLIDER: &str = "\
+[M2] (golly 2.0)
+#R B36/S23
+#C 滑翔机
+.*$..*$***$
+4 1 0 0 0
+5 0 0 0 2
+";
+
+#[test]
+fn reads_golly_files() {
+    let pattern = Pattern::from_macrocell(GLIDER, (64, 64)).unwrap();
+    let glider = Pattern::from_rle("x = 3, y = 3\nbo$2bo$3o!", (64, 64)).unwrap();
+    assert_eq!(pattern.cells, glider.cells);
+    assert_eq!((pattern.width, pattern.height), (3, 3));
+    assert_eq!(pattern.rule, Some("B36/S23".parse().unwrap()));
+    assert_eq!(pattern.comments, ["滑翔机"]);
+
+    // 同一个节点用了两次，两个滑翔机的包围盒是 19x19
+    let pattern = Pattern::from_macrocell(&GLIDER.replace("5 0 0 0 2", "5 2 0 0 2"), (64, 64));
+    assert_eq!(pattern.unwrap().width, 19);
+
+    // 包围盒放不进地图时不展开
+    let huge = format!("{GLIDER}6 3 0 0 0\n7 0 0 0 4\n8 5 0 0 5\n");
+    match Pattern::from_macrocell(&huge, (100, 100)) {
+        Err(Error::TooLarge { size, .. }) => assert_eq!(size, (131, 131)),
+        other => panic!("{other:?}"),
+    }
+
+    for (text, line) in [
+        ("4 0 0 0 1", 1),
+        ("[M2]\n.*$\n5 1 0 0 0", 3),
+        ("[M2]\n.*$\n4 1 0 0 2", 3),
+        ("[M2]\n.*$\n4 1 0 0", 3),
+        ("[M2]\n.........$", 2),
+        ("[M2]\n.*$\n1 0 1 1 0", 3),
+    ] {
+        match Pattern::from_macrocell(text, (64, 64)) {
+            Err(Error::Parse { line: actual, .. }) => assert_eq!(actual, line, "{text}"),
+            other => panic!("{text}: {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn round_trip() {
+    for (width, height) in [(1, 1), (3, 5), (8, 8), (37, 100), (200, 64)] {
+        let mut pattern = soup((width * 31 + height) as u64, (width, height), 0.4);
+        pattern.rule = Some(Rule::CONWAY);
+        pattern.name = Some("汤".to_string());
+        pattern.comments = vec!["generation 0".to_string()];
+        let text = pattern.to_macrocell();
+        let read = Pattern::from_macrocell(&text, (width, height)).unwrap();
+        assert_eq!(read, pattern.cropped(), "{width}x{height}\n{text}");
+    }
+
+    // 没有活细胞
+    let empty = Pattern::new(20, 20).to_macrocell();
+    assert_eq!(Pattern::from_macrocell(&empty, (1, 1)).unwrap().cells, []);
+
+    // 同一个方块铺满 256x256，每层只有一个节点
+    let mut tiles = Pattern::new(256, 256);
+    for y in 0..256 {
+        for x in 0..256 {
+            tiles.set(x, y, x % 4 < 2 && y % 4 < 2);
+        }
+    }
+    let text = tiles.to_macrocell();
+    let nodes = text.lines().filter(|line| !line.starts_with(['[', '#']));
+    assert_eq!(nodes.count(), 6, "{text}");
+    let read = Pattern::from_macrocell(&text, (256, 256)).unwrap();
+    assert_eq!(read.cells, tiles.cropped().cells);
+}
+
+#[test]
+fn saves_readback() {
+    let size = (96, 64);
+    for storage in [Storage::Texture, Storage::Packed] {
+        let Some(mut sim) = common::simulator(size, Rule::CONWAY, Topology::Torus, storage) else {
+            return;
+        };
+        sim.write_cells((10, 7), &soup(3, (70, 50), 0.35)).unwrap();
+        sim.step(10);
+
+        let path = std::env::temp_dir().join(format!("life_game_wgpu_{storage:?}.mc"));
+        sim.save(&path).unwrap();
+        let saved = Pattern::load(&path, size).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let expected = sim.read_cells().unwrap().cropped();
+        assert_eq!(saved.cells, expected.cells, "{storage:?}");
+        assert_eq!(saved.comments, ["generation 10"]);
+        assert!(Pattern::from_macrocell(&expected.to_macrocell(), (8, 8)).is_err());
+    }
+}
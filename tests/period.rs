@@ -0,0 +1,105 @@
+//! 周期检测：静物、振荡器，以及在环面上绕回原处的滑翔机
+
+mod common;
+
+use life_game_wgpu::{
+    pattern::Pattern, period::Period, rule::Rule, topology::Topology, Engine, Simulator, Storage,
+};
+
+const BLOCK: &str = "x = 2, y = 2\n2o$2o!";
+const BLINKER: &str = "x = 3, y = 1\n3o!";
+const GLIDER: &str = "x = 3, y = 3\nbo$2bo$3o!";
+
+/// 64x32 的环面，没有可用的适配器时返回 `None`，跳过测试
+fn simulator(storage: Storage, rle: &str) -> Option<Simulator> {
+    let mut sim = common::simulator((64, 32), Rule::CONWAY, Topology::Torus, storage)?;
+    sim.write_cells((30, 14), &Pattern::from_rle(rle, sim.map_size()).unwrap())
+        .unwrap();
+    sim.enable_period_detection(1024);
+    Some(sim)
+}
+
+#[test]
+fn still_life_and_oscillator() {
+    for storage in [Storage::Texture, Storage::Packed] {
+        for (rle, period) in [(BLOCK, 1), (BLINKER, 2)] {
+            let Some(mut sim) = simulator(storage, rle) else {
+                return;
+            };
+            // 第 0 代也算，所以第 `period` 代时发现重复
+            sim.step(period - 1);
+            sim.wait_stats();
+            assert_eq!(sim.period(), None, "{storage:?}");
+            sim.update();
+            sim.wait_stats();
+            assert_eq!(
+                sim.period(),
+                Some(Period {
+                    generation: period,
+                    period
+                }),
+                "{storage:?}"
+            );
+            // 继续演化时保留第一次发现的代数
+            sim.step(3);
+            sim.wait_stats();
+            assert_eq!(sim.period().unwrap().generation, period);
+        }
+    }
+}
+
+#[test]
+fn glider_returns_around_the_torus() {
+    for storage in [Storage::Texture, Storage::Packed] {
+        let Some(mut sim) = simulator(storage, GLIDER) else {
+            return;
+        };
+        // 每 4 代沿对角线移动一格，绕环面一圈要 64 格
+        sim.run_until(255);
+        sim.wait_stats();
+        assert_eq!(sim.period(), None, "{storage:?}");
+        sim.update();
+        sim.wait_stats();
+        assert_eq!(sim.period().unwrap().period, 256, "{storage:?}");
+    }
+}
+
+#[test]
+fn edits_reset_the_table() {
+    let Some(mut sim) = simulator(Storage::Texture, BLOCK) else {
+        return;
+    };
+    sim.step(2);
+    sim.wait_stats();
+    assert!(sim.period().is_some());
+    sim.set_cell((0, 0), true).unwrap();
+    sim.wait_stats();
+    assert_eq!(sim.period(), None);
+    sim.step(1);
+    sim.wait_stats();
+    assert_eq!(sim.period(), None);
+}
+
+#[test]
+fn records_every_generation_in_one_submit() {
+    // 一次提交中演化的代数比环形缓冲区长，要分几次读回，结果仍然一代不少
+    for storage in [Storage::Texture, Storage::Packed] {
+        let Some(mut sim) = simulator(storage, GLIDER) else {
+            return;
+        };
+        sim.step(600);
+        sim.wait_stats();
+        let generations: Vec<u64> = sim
+            .stats()
+            .samples()
+            .iter()
+            .map(|sample| sample.generation)
+            .collect();
+        assert_eq!(generations, (0..=600).collect::<Vec<_>>(), "{storage:?}");
+        assert!(sim
+            .stats()
+            .samples()
+            .iter()
+            .all(|sample| sample.population == 5));
+    }
+}
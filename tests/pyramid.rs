@@ -0,0 +1,107 @@
+//! 缩小显示用的金字塔：每一层都和CPU上的归约一致，奇数边上的细胞不会丢
+
+mod common;
+
+use life_game_wgpu::{
+    census::soup, pattern::Pattern, rule::Rule, topology::Topology, Engine, Simulator, Storage,
+};
+
+/// 一层：大小和每个纹素的最大值、平均值
+type Level = ((u32, u32), Vec<(f32, f32)>);
+
+/// 把 `size` 大小的一层按 2x2 归约，最后一行和最后一列多管一个
+fn reduce(((width, height), texels): &Level) -> Level {
+    let size = ((width / 2).max(1), (height / 2).max(1));
+    let range =
+        |i: u32, count: u32, source: u32| 2 * i..if i == count - 1 { source } else { 2 * i + 2 };
+    let mut result = Vec::new();
+    for y in 0..size.1 {
+        for x in 0..size.0 {
+            let (mut maximum, mut sum, mut count) = (0.0f32, 0.0, 0.0);
+            for sy in range(y, size.1, *height) {
+                for sx in range(x, size.0, *width) {
+                    let (max, average) = texels[(sy * width + sx) as usize];
+                    maximum = maximum.max(max);
+                    sum += average;
+                    count += 1.0;
+                }
+            }
+            result.push((maximum, sum / count));
+        }
+    }
+    (size, result)
+}
+
+fn check(sim: &Simulator, pattern: &Pattern) {
+    let mut level: Level = (
+        (pattern.width, pattern.height),
+        pattern
+            .cells
+            .iter()
+            .map(|&alive| if alive { (1.0, 1.0) } else { (0.0, 0.0) })
+            .collect(),
+    );
+    let count = sim.pyramid().unwrap().level_count();
+    for index in 0..count {
+        level = reduce(&level);
+        assert_eq!(sim.pyramid().unwrap().level_size(index), level.0);
+        let texels = sim.read_pyramid(index).unwrap().unwrap();
+        for (i, (gpu, cpu)) in texels.iter().zip(&level.1).enumerate() {
+            assert_eq!(gpu.0, cpu.0, "第 {index} 层第 {i} 个纹素的最大值");
+            // 平均值是半精度浮点数
+            assert!(
+                (gpu.1 - cpu.1).abs() <= cpu.1 * 2e-3 + 1e-7,
+                "第 {index} 层第 {i} 个纹素的平均值"
+            );
+        }
+    }
+    assert_eq!(level.0, (1, 1));
+}
+
+#[test]
+fn levels_match_cpu() {
+    // 宽和高都不是 2 的幂，高是奇数
+    let size = (96, 37);
+    for storage in [Storage::Texture, Storage::Packed] {
+        let Some(mut sim) = common::simulator(size, Rule::CONWAY, Topology::Torus, storage) else {
+            return;
+        };
+        if sim.enable_pyramid().is_err() {
+            assert!(sim.is_cpu_fallback());
+            return;
+        }
+        assert!(sim
+            .read_pyramid(sim.pyramid().unwrap().level_count())
+            .is_err());
+
+        // 只有右下角一个细胞，每一层都要看到它
+        sim.set_cell((95, 36), true).unwrap();
+        check(&sim, &sim.read_cells().unwrap());
+        let top = sim.read_pyramid(sim.pyramid().unwrap().level_count() - 1);
+        assert_eq!(top.unwrap().unwrap()[0].0, 1.0, "{storage:?}");
+
+        // 演化之后重建
+        sim.write_cells((0, 0), &soup(7, size, 0.4)).unwrap();
+        sim.step(20);
+        check(&sim, &sim.read_cells().unwrap());
+    }
+}
+
+#[test]
+fn rejects_oversized() {
+    // 第 0 层是地图的一半，超出设备支持的纹理尺寸时报错，而不是崩溃
+    let Some(sim) = common::simulator((32, 32), Rule::CONWAY, Topology::Dead, Storage::Packed)
+    else {
+        return;
+    };
+    let max = sim.device.limits().max_texture_dimension_2d;
+    drop(sim);
+
+    let size = ((max / 32 + 1) * 64, 32);
+    let mut sim = common::simulator(size, Rule::CONWAY, Topology::Dead, Storage::Packed).unwrap();
+    if sim.is_cpu_fallback() {
+        return;
+    }
+    assert!(sim.enable_pyramid().is_err());
+    assert!(sim.pyramid().is_none());
+}
@@ -0,0 +1,163 @@
+//! 录制：地图区域一个像素一个细胞，GIF、APNG 和图片序列解码之后和录制的帧一样
+
+mod common;
+
+use std::time::Duration;
+
+use life_game_wgpu::{
+    census::soup,
+    recording::{board_frame, Format, Recorder},
+    rule::Rule,
+    topology::Topology,
+    Engine, Storage,
+};
+
+const DELAY: Duration = Duration::from_millis(50);
+
+/// 演化 12 代，每 4 代读出一块区域，共 4 帧；没有可用的适配器时返回 `None`
+fn frames(size: (u32, u32)) -> Option<Vec<Vec<u8>>> {
+    let mut sim = common::simulator((64, 48), Rule::CONWAY, Topology::Torus, Storage::Texture)?;
+    sim.write_cells((10, 8), &soup(3, (30, 20), 0.4)).unwrap();
+    let mut frames = vec![board_frame(&sim, (8, 6), size).unwrap()];
+    for _ in 0..3 {
+        sim.step(4);
+        frames.push(board_frame(&sim, (8, 6), size).unwrap());
+    }
+
+    // 区域之外的细胞不在图片中，活细胞是黑色
+    let cells = sim.read_cells().unwrap();
+    let last = frames.last().unwrap();
+    for y in 0..size.1 {
+        for x in 0..size.0 {
+            let value = if cells.get(x + 8, y + 6) { 0 } else { 255 };
+            let index = ((y * size.0 + x) * 4) as usize;
+            assert_eq!(last[index..index + 4], [value, value, value, 255]);
+        }
+    }
+    assert!(board_frame(&sim, (40, 0), (30, 10)).is_err());
+    Some(frames)
+}
+
+#[test]
+fn reads_only_the_region() {
+    // 区域的左右两边不在按位储存的字边界上，结果和读回整张地图再裁剪一样
+    for storage in [Storage::Texture, Storage::Packed] {
+        let Some(mut sim) = common::simulator((96, 40), Rule::CONWAY, Topology::Torus, storage)
+        else {
+            return;
+        };
+        sim.write_cells((0, 0), &soup(7, (96, 40), 0.5)).unwrap();
+        sim.step(3);
+        let cells = sim.read_cells().unwrap();
+        for (origin, size) in [((0, 0), (96, 40)), ((5, 3), (61, 17)), ((33, 39), (1, 1))] {
+            let region = sim.read_region(origin, size).unwrap();
+            assert_eq!((region.width, region.height), size);
+            for y in 0..size.1 {
+                for x in 0..size.0 {
+                    assert_eq!(
+                        region.get(x, y),
+                        cells.get(x + origin.0, y + origin.1),
+                        "{storage:?} {origin:?} {size:?}"
+                    );
+                }
+            }
+        }
+        assert!(sim.read_region((90, 0), (7, 1)).is_err());
+        assert!(sim.read_region((0, 0), (0, 1)).is_err());
+    }
+}
+
+#[test]
+fn encodes_formats() {
+    let size = (34, 26);
+    let Some(frames) = frames(size) else {
+        return;
+    };
+    let directory = std::env::temp_dir().join("life_game_wgpu_recording");
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+
+    for name in ["clip.gif", "clip.png", "clip"] {
+        let path = directory.join(name);
+        let format = Format::from_path(&path);
+        let mut recorder = Recorder::new(&path, format, DELAY).unwrap();
+        for frame in &frames {
+            recorder.push(size, frame).unwrap();
+        }
+        assert!(recorder
+            .push((size.0, size.1 + 1), &[0; 34 * 27 * 4])
+            .is_err());
+        assert_eq!(recorder.finish().unwrap(), 4);
+
+        let decoded: Vec<Vec<u8>> = match format {
+            Format::Gif => {
+                let mut options = gif::DecodeOptions::new();
+                options.set_color_output(gif::ColorOutput::RGBA);
+                let mut decoder = options
+                    .read_info(std::fs::File::open(&path).unwrap())
+                    .unwrap();
+                let mut decoded = vec![];
+                while let Some(frame) = decoder.read_next_frame().unwrap() {
+                    assert_eq!((frame.width as u32, frame.height as u32), size);
+                    assert_eq!(frame.delay, 5);
+                    decoded.push(frame.buffer.to_vec());
+                }
+                decoded
+            }
+            Format::Apng => {
+                let decoder = png::Decoder::new(std::fs::File::open(&path).unwrap());
+                let mut reader = decoder.read_info().unwrap();
+                let control = reader.info().animation_control.unwrap();
+                assert_eq!(control.num_frames, 4);
+                let mut decoded = vec![];
+                for _ in 0..control.num_frames {
+                    let mut data = vec![0; reader.output_buffer_size()];
+                    reader.next_frame(&mut data).unwrap();
+                    decoded.push(data);
+                }
+                decoded
+            }
+            Format::Sequence => (0..)
+                .map(|index| path.join(format!("{index:05}.png")))
+                .take_while(|path| path.exists())
+                .map(|path| {
+                    let decoder = png::Decoder::new(std::fs::File::open(path).unwrap());
+                    let mut reader = decoder.read_info().unwrap();
+                    let mut data = vec![0; reader.output_buffer_size()];
+                    reader.next_frame(&mut data).unwrap();
+                    data
+                })
+                .collect(),
+        };
+        assert_eq!(decoded, frames, "{format:?}");
+    }
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn quantizes_many_colors() {
+    // 超过 256 种颜色时量化，大小和帧数不变
+    let size = (32, 32);
+    let rgba: Vec<u8> = (0..size.0 * size.1)
+        .flat_map(|index| [(index % 32 * 8) as u8, (index / 32 * 8) as u8, 128, 255])
+        .collect();
+    let path = std::env::temp_dir().join("life_game_wgpu_colors.gif");
+    let mut recorder = Recorder::new(&path, Format::Gif, DELAY).unwrap();
+    recorder.push(size, &rgba).unwrap();
+    recorder.push(size, &rgba).unwrap();
+    assert!(recorder.push(size, &rgba[4..]).is_err());
+    assert_eq!(recorder.finish().unwrap(), 2);
+
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options
+        .read_info(std::fs::File::open(&path).unwrap())
+        .unwrap();
+    let mut count = 0;
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        assert_eq!(frame.buffer.len(), rgba.len());
+        count += 1;
+    }
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(count, 2);
+}
@@ -0,0 +1,105 @@
+//! 快照：恢复之后的状态和保存时一样，接着演化的结果也一样
+
+mod common;
+
+use life_game_wgpu::{
+    census::soup,
+    rule::Rule,
+    snapshot::{Snapshot, View},
+    topology::Topology,
+    Engine, Simulator, Storage,
+};
+
+const SIZE: (u32, u32) = (64, 48);
+
+/// 没有可用的适配器时返回 `None`，跳过测试
+fn simulator(storage: Storage) -> Option<Simulator> {
+    common::simulator(
+        SIZE,
+        "B36/S23".parse().unwrap(),
+        Topology::KleinBottle,
+        storage,
+    )
+}
+
+#[test]
+fn resumes_deterministically() {
+    let view = View {
+        orthographic: true,
+        center: [12.5, 30.0],
+        cells_per_pixel: 0.25,
+        position: [0.5, -0.25, 2.0],
+        scale: 3.0,
+    };
+    for storage in [Storage::Texture, Storage::Packed] {
+        // 奇数代之后，当前这一代在第二份地图中
+        let (bytes, saved, expected) = {
+            let Some(mut sim) = simulator(storage) else {
+                return;
+            };
+            sim.write_cells((4, 4), &soup(17, (48, 36), 0.4)).unwrap();
+            sim.step(37);
+            let snapshot = sim.snapshot(view).unwrap();
+            let mut bytes = vec![];
+            snapshot.write(&mut bytes).unwrap();
+            let saved = sim.read_texels().unwrap();
+            sim.step(50);
+            (bytes, saved, sim.read_texels().unwrap())
+        };
+
+        let snapshot = Snapshot::read(&bytes[..]).unwrap();
+        assert_eq!(snapshot.generation, 37);
+        assert_eq!(snapshot.view, view);
+        assert_eq!(snapshot.storage, storage);
+        assert!(
+            bytes.len() < (SIZE.0 * SIZE.1) as usize,
+            "{storage:?} 没有压缩"
+        );
+
+        // 新的模拟器停在第一份地图上
+        let Some(mut sim) = simulator(storage) else {
+            return;
+        };
+        sim.enable_history(10, 16);
+        sim.resume(&snapshot).unwrap();
+        assert_eq!(sim.generation(), 37);
+        assert_eq!(sim.read_texels().unwrap(), saved, "{storage:?}");
+        assert_eq!(sim.snapshot(view).unwrap(), snapshot, "{storage:?}");
+        sim.step(50);
+        assert_eq!(sim.read_texels().unwrap(), expected, "{storage:?}");
+
+        // 历史从恢复的那一代开始
+        assert_eq!(sim.history_range(), Some((37, 87)));
+        assert!(sim.seek(37));
+        assert_eq!(sim.read_texels().unwrap(), saved, "{storage:?}");
+    }
+}
+
+#[test]
+fn rejects_bad_files() {
+    let Some(mut sim) = simulator(Storage::Texture) else {
+        return;
+    };
+    let snapshot = sim.snapshot(View::default()).unwrap();
+    let mut bytes = vec![];
+    snapshot.write(&mut bytes).unwrap();
+
+    assert!(Snapshot::read(&b"LIFESNAQ"[..]).is_err());
+    assert!(Snapshot::read(&bytes[..bytes.len() - 1]).is_err());
+    let mut version = bytes.clone();
+    version[8] = 2;
+    assert!(Snapshot::read(&version[..]).is_err());
+    // 头部的地图尺寸大到字节数溢出时报错而不是崩溃
+    let mut huge = bytes.clone();
+    huge[12..20].fill(0xff);
+    let error = Snapshot::read(&huge[..]).unwrap_err();
+    assert!(error.to_string().contains("太大"), "{error}");
+
+    // 规则不一致时不恢复
+    let mut conway = bytes.clone();
+    conway[20..24].copy_from_slice(&[8, 0, 12, 0]);
+    let conway = Snapshot::read(&conway[..]).unwrap();
+    assert_eq!(conway.rule, Rule::CONWAY);
+    assert!(sim.resume(&conway).is_err());
+    assert!(sim.resume(&snapshot).is_ok());
+}